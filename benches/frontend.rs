@@ -0,0 +1,70 @@
+//! Lexer and parser throughput on large generated programs
+//!
+//! Run with `cargo bench`, criterion compares against the previous run.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ezc::{lexer::tokenize, parser::parse};
+
+/// Number of generated functions, each over a dozen statements long
+const FUNCTION_COUNT: usize = 500;
+
+/// Number of distinct identifiers in `unique_identifiers`
+const IDENTIFIER_COUNT: usize = 50_000;
+
+/// A program exercising declarations, arithmetic, arrays, loops, branches and
+/// calls, about `FUNCTION_COUNT * 13` statements long
+fn generate_source() -> String {
+	let mut source = String::from("int f0(int n) { return n; }\n");
+	for i in 1..FUNCTION_COUNT {
+		source += &format!(
+			r"
+int f{i}(int n) {{
+	int a, b, i, c[8];
+	a = n * {i};
+	b = 0x{i:x};
+	while (a > 0) {{
+		i = a % 8;
+		c[i] = a + b;
+		b += c[i];
+		if (b > 1000) {{
+			b = b / 2;
+		}}
+		a = a - 1;
+	}}
+	a = f{}(b);
+	return a ^ b;
+}}
+",
+			i - 1
+		);
+	}
+	source
+}
+
+/// A single function declaring and assigning `IDENTIFIER_COUNT` distinct
+/// variables, stressing the interning of the symbol table
+fn unique_identifiers() -> String {
+	let mut source = String::from("int main() {\n");
+	for i in 0..IDENTIFIER_COUNT {
+		source += &format!("\tint v{i} = {i};\n");
+	}
+	source + "\treturn 0;\n}\n"
+}
+
+fn frontend(c: &mut Criterion) {
+	let source = generate_source();
+	let mut group = c.benchmark_group("frontend");
+	group.throughput(Throughput::Bytes(source.len() as u64));
+	group.bench_function("tokenize", |b| b.iter(|| tokenize(&source).unwrap()));
+	let lexer_output = tokenize(&source).unwrap();
+	group.bench_function("parse", |b| b.iter(|| parse(lexer_output.clone()).unwrap()));
+	group.finish();
+
+	let source = unique_identifiers();
+	let mut group = c.benchmark_group("symbol_table");
+	group.throughput(Throughput::Elements(IDENTIFIER_COUNT as u64));
+	group.bench_function("tokenize", |b| b.iter(|| tokenize(&source).unwrap()));
+	group.finish();
+}
+
+criterion_group!(benches, frontend);
+criterion_main!(benches);
@@ -1,51 +1,487 @@
 //! Semantic Analyzer
 //!
-//! Takes a reference to `parser::Program` and returns any errors if present
+//! Takes a reference to `parser::Program` and returns any errors if present,
+//! otherwise the warnings for suspicious but valid code.
 //! Should be ran before going for code gen, since the later stages expect the
 //! program to be semantically sound.
-use std::collections::HashMap;
+use std::{
+	cell::RefCell,
+	collections::{HashMap, HashSet},
+};
 
-use crate::parser::{Decl, DirectValue, Expression, FuncSignature, Ident, Program, Scope, Stmts};
+use crate::parser::{
+	BinaryOperation, Decl, DirectValue, Expression, Func, FuncSignature, Ident, IdentNameTable,
+	Inlining, Program, ReturnType, Scope, Stmts,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SemanticError {
 	UndefinedFunction(FuncSignature),
-	FunctionRedeclaration(FuncSignature),
+	/// Tuple struct of the redeclaration and the function declared first
+	FunctionRedeclaration(FuncSignature, FuncSignature),
 	UseBeforeDeclaration(Ident),
 	MultipleDeclaration(Ident),
-	ContinueOutsideLoop,
-	BreakOutsideLoop,
-	InvalidArguments(FuncSignature),
+	/// `continue` at the line number, outside of any loop
+	ContinueOutsideLoop(usize),
+	/// `break` at the line number, outside of any loop
+	BreakOutsideLoop(usize),
+	InvalidArguments(FuncSignature, ArgumentMismatch),
 	ExpectedPrimitiveFoundArray(Ident),
 	ExpectedArrayFoundPrimitive(Ident),
+	/// `return a;` where `a` is an array, functions only return an `int`
+	CannotReturnArray(Ident),
+	/// Call to a `void` function used as a value
+	VoidValueUsed(FuncSignature),
+	/// `return;` inside the `int` function
+	MissingReturnValue(FuncSignature),
+	/// `return <Expression>;` inside the `void` function
+	UnexpectedReturnValue(FuncSignature),
+	/// `/` or `%` by a divisor known to be zero, at the line number
+	DivisionByZero(usize),
+	/// Function able to call itself, directly or through others, while
+	/// `AnalyzerOptions::no_recursion` is set
+	RecursionNotAllowed(FuncSignature),
+	/// `static` variable initialized with a value unknown at compile time
+	NonConstantStaticInitializer(Ident),
+	/// `inline` or `noinline` on a function without a body
+	InliningWithoutBody(FuncSignature),
+	/// `inline` on a function `tac_gen::inline_leaf_functions` can't inline
+	CannotInline(FuncSignature, InlineObstacle),
+}
+impl SemanticError {
+	/// Human readable message, naming the identifiers from `ident_table`.
+	/// The line is left to `line_number`
+	pub fn describe(&self, ident_table: &IdentNameTable) -> String {
+		let name = |index: usize| ident_table.0[index].as_str();
+		let count = |n: usize, noun: &str| match n {
+			1 => format!("1 {noun}"),
+			n => format!("{n} {noun}s"),
+		};
+		match self {
+			Self::UndefinedFunction(sig) => {
+				format!("call to undefined function '{}'", name(sig.table_index))
+			}
+			Self::FunctionRedeclaration(sig, previous) => format!(
+				"function '{}' with {} redeclares the one with {} at line {}, overloading is not supported",
+				name(sig.table_index),
+				count(sig.parameter_count, "parameter"),
+				count(previous.parameter_count, "parameter"),
+				previous.line_number()
+			),
+			Self::UseBeforeDeclaration(ident) => {
+				format!("'{}' used before its declaration", name(ident.table_index))
+			}
+			Self::MultipleDeclaration(ident) => format!(
+				"'{}' declared again in the same scope",
+				name(ident.table_index)
+			),
+			Self::ContinueOutsideLoop(_) => "'continue' outside of a loop".to_string(),
+			Self::BreakOutsideLoop(_) => "'break' outside of a loop".to_string(),
+			Self::InvalidArguments(sig, ArgumentMismatch::Count(arguments)) => format!(
+				"'{}' called with {}, not matching its parameters",
+				name(sig.table_index),
+				count(*arguments, "argument")
+			),
+			Self::InvalidArguments(sig, ArgumentMismatch::Type { index, expected }) => format!(
+				"argument {} of the call to '{}' should be {}",
+				index + 1,
+				name(sig.table_index),
+				match expected {
+					IdentType::Primitive => "a scalar",
+					IdentType::Array => "an array",
+				}
+			),
+			Self::InvalidArguments(sig, ArgumentMismatch::Literal { index }) => format!(
+				"argument {} of the call to '{}' is a string literal, only external functions take them",
+				index + 1,
+				name(sig.table_index)
+			),
+			Self::ExpectedPrimitiveFoundArray(ident) => {
+				format!("array '{}' used as a scalar", name(ident.table_index))
+			}
+			Self::ExpectedArrayFoundPrimitive(ident) => {
+				format!("scalar '{}' indexed as an array", name(ident.table_index))
+			}
+			Self::CannotReturnArray(ident) => format!(
+				"array '{}' returned, only an 'int' can be",
+				name(ident.table_index)
+			),
+			Self::VoidValueUsed(sig) => format!(
+				"value of the void function '{}' used",
+				name(sig.table_index)
+			),
+			Self::MissingReturnValue(sig) => format!(
+				"'return' without a value in the int function '{}'",
+				name(sig.table_index)
+			),
+			Self::UnexpectedReturnValue(sig) => format!(
+				"'return' with a value in the void function '{}'",
+				name(sig.table_index)
+			),
+			Self::DivisionByZero(_) => "division by zero".to_string(),
+			Self::NonConstantStaticInitializer(ident) => format!(
+				"static '{}' must be initialized with a constant",
+				name(ident.table_index)
+			),
+			Self::InliningWithoutBody(sig) => format!(
+				"inlining attribute on '{}', which has no body",
+				name(sig.table_index)
+			),
+			Self::CannotInline(sig, obstacle) => format!(
+				"'{}' is marked inline but {}",
+				name(sig.table_index),
+				match obstacle {
+					InlineObstacle::Calls => "calls other functions",
+					InlineObstacle::ArrayParameter => "takes an array",
+					InlineObstacle::Static => "declares a static",
+				}
+			),
+			Self::RecursionNotAllowed(sig) => format!(
+				"function '{}' is recursive, which '--no-recursion' forbids",
+				name(sig.table_index)
+			),
+		}
+	}
+	/// Line the error is reported at, that of the function for the errors
+	/// about its `return` statements
+	pub fn line_number(&self) -> usize {
+		match self {
+			Self::UndefinedFunction(sig)
+			| Self::FunctionRedeclaration(sig, _)
+			| Self::InvalidArguments(sig, _)
+			| Self::VoidValueUsed(sig)
+			| Self::MissingReturnValue(sig)
+			| Self::UnexpectedReturnValue(sig)
+			| Self::RecursionNotAllowed(sig)
+			| Self::InliningWithoutBody(sig)
+			| Self::CannotInline(sig, _) => sig.line_number(),
+			Self::UseBeforeDeclaration(ident)
+			| Self::MultipleDeclaration(ident)
+			| Self::ExpectedPrimitiveFoundArray(ident)
+			| Self::ExpectedArrayFoundPrimitive(ident)
+			| Self::CannotReturnArray(ident)
+			| Self::NonConstantStaticInitializer(ident) => ident.line_number(),
+			Self::ContinueOutsideLoop(line)
+			| Self::BreakOutsideLoop(line)
+			| Self::DivisionByZero(line) => *line,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SemanticWarning {
+	/// Condition of an `if` or loop known at compile time, at the line
+	/// number, and whether it always holds. `while (1)` is exempt as the
+	/// idiomatic infinite loop, as is `do ... while (0)`
+	ConstantCondition(usize, bool),
+	/// End of the `int` function is reachable without returning a value
+	MissingReturn(FuncSignature),
+	/// Read of a variable not assigned on every path leading to it, only the
+	/// first such read of each variable is reported
+	UsedUninitialized(Ident),
+}
+impl SemanticWarning {
+	/// Human readable message, naming the identifiers from `ident_table`.
+	/// The line is left to `line_number`
+	pub fn describe(&self, ident_table: &IdentNameTable) -> String {
+		let name = |index: usize| ident_table.0[index].as_str();
+		match self {
+			Self::ConstantCondition(_, holds) => format!("condition is always {holds}"),
+			Self::MissingReturn(sig) => format!(
+				"end of the int function '{}' reachable without returning a value",
+				name(sig.table_index)
+			),
+			Self::UsedUninitialized(ident) => {
+				format!("'{}' may be used uninitialized", name(ident.table_index))
+			}
+		}
+	}
+	/// Line the warning is reported at, that of the function for
+	/// `MissingReturn`
+	pub fn line_number(&self) -> usize {
+		match self {
+			Self::ConstantCondition(line, _) => *line,
+			Self::MissingReturn(sig) => sig.line_number(),
+			Self::UsedUninitialized(ident) => ident.line_number(),
+		}
+	}
+}
+
+/// Why a function marked `inline` can't be inlined, only leaf functions are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineObstacle {
+	Calls,
+	/// Parameters are inlined as temporaries, which can't be indexed
+	ArrayParameter,
+	/// Statics belong to the callee, not to every caller
+	Static,
+}
+
+/// Why the arguments of a call don't fit the callee's parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentMismatch {
+	/// Number of arguments passed
+	Count(usize),
+	/// Position of the offending argument and the type of the parameter
+	Type { index: usize, expected: IdentType },
+	/// Position of a string literal passed to a function with a body, which
+	/// would index its bytes as 4 byte elements
+	Literal { index: usize },
 }
 
-pub fn analyze(program: &Program) -> Result<(), SemanticError> {
-	let Program(functions) = program;
+/// Toggles for the restrictions on otherwise valid programs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnalyzerOptions {
+	/// Reject recursive functions, mutually recursive ones included, for
+	/// targets where unbounded stack growth is unacceptable
+	pub no_recursion: bool,
+}
+
+pub fn analyze(program: &Program) -> Result<Vec<SemanticWarning>, Vec<SemanticError>> {
+	analyze_with_options(program, AnalyzerOptions::default())
+}
+
+/// Same as `analyze`, with the restrictions toggled by `options`
+pub fn analyze_with_options(
+	program: &Program,
+	options: AnalyzerOptions,
+) -> Result<Vec<SemanticWarning>, Vec<SemanticError>> {
+	analyze_resolving(program, options, &mut HashMap::new())
+}
+
+/// Declaration every use of a variable or array refers to, shadowing
+/// included, keyed by the use. Declarations aren't keys themselves.
+///
+/// Meant for programs passing `analyze`, otherwise the uses following an
+/// error within its statement are left unresolved.
+pub fn resolve(program: &Program) -> HashMap<Ident, Ident> {
+	let mut resolutions = HashMap::new();
+	let _ = analyze_resolving(program, AnalyzerOptions::default(), &mut resolutions);
+	resolutions
+}
+
+/// `analyze_with_options`, collecting the resolutions of `resolve` on the way
+fn analyze_resolving(
+	program: &Program,
+	options: AnalyzerOptions,
+	resolutions: &mut HashMap<Ident, Ident>,
+) -> Result<Vec<SemanticWarning>, Vec<SemanticError>> {
+	let Program(functions, _) = program;
+	let mut warnings = Vec::new();
+	let mut errors = Vec::new();
 	let mut defined_functions = HashMap::new();
 	for func in functions {
-		if let Some(_prev_decl) =
-			defined_functions.insert(func.name().table_index, func.parameter().len())
+		let function_type = FunctionType {
+			parameters: func
+				.parameter()
+				.iter()
+				.map(|parameter| match parameter.is_array {
+					true => IdentType::Array,
+					false => IdentType::Primitive,
+				})
+				.collect(),
+			return_type: func.return_type(),
+			is_external: func.is_external(),
+			is_variadic: func.is_variadic(),
+		};
+		// External functions are callable, but their body lives elsewhere so
+		// they can't be defined here as well
+		if defined_functions
+			.insert(func.name().table_index, function_type)
+			.is_some()
 		{
-			return Err(SemanticError::FunctionRedeclaration(func.name()));
+			let previous = functions
+				.iter()
+				.map(Func::name)
+				.find(|sig| sig.table_index == func.name().table_index)
+				.unwrap();
+			errors.push(SemanticError::FunctionRedeclaration(func.name(), previous));
 		}
-		let mut stack = ScopeStack::new(func.parameter_table_idx(), &defined_functions);
-		stack.scope_analyze(func.scope(), ScopeKind::Function, false)?;
+		if func.is_external() {
+			if func.inlining() != Inlining::Auto {
+				errors.push(SemanticError::InliningWithoutBody(func.name()));
+			}
+			continue;
+		}
+		if func.inlining() == Inlining::Always
+			&& let Some(obstacle) = inline_obstacle(func)
+		{
+			errors.push(SemanticError::CannotInline(func.name(), obstacle));
+		}
+		let mut stack = ScopeStack::new(func, &defined_functions);
+		stack.scope_analyze(func.scope(), ScopeKind::Function);
+		resolutions.extend(stack.resolutions.take());
+		errors.append(&mut stack.errors);
+		warnings.append(&mut stack.warnings);
+		warnings.extend(
+			uninitialized_reads(func.scope(), &mut HashSet::new())
+				.into_iter()
+				.map(SemanticWarning::UsedUninitialized),
+		);
+		if func.return_type() == ReturnType::Int && !terminates(func.scope()) {
+			warnings.push(SemanticWarning::MissingReturn(func.name()));
+		}
+	}
+	if options.no_recursion
+		&& let Some(sig) = recursive_function(program)
+	{
+		errors.push(SemanticError::RecursionNotAllowed(sig));
+	}
+	match errors.is_empty() {
+		true => Ok(warnings),
+		false => Err(errors),
 	}
-	Ok(())
+}
+
+/// First function of `program` able to call itself, directly or through
+/// others. External functions are assumed to never call back into it
+fn recursive_function(program: &Program) -> Option<FuncSignature> {
+	let graph: HashMap<usize, Vec<usize>> = program
+		.0
+		.iter()
+		.map(|func| (func.name().table_index, calls(func.scope())))
+		.collect();
+	program.0.iter().map(Func::name).find(|sig| {
+		let mut visited = HashSet::new();
+		let mut pending = graph[&sig.table_index].clone();
+		while let Some(callee) = pending.pop() {
+			if callee == sig.table_index {
+				return true;
+			}
+			if visited.insert(callee)
+				&& let Some(next) = graph.get(&callee)
+			{
+				pending.extend(next);
+			}
+		}
+		false
+	})
+}
+
+fn inline_obstacle(func: &Func) -> Option<InlineObstacle> {
+	fn declares_static(scope: &Scope) -> bool {
+		scope.0.iter().any(|stmt| match stmt {
+			Stmts::Decl(decls) => decls.iter().any(|decl| {
+				matches!(
+					decl,
+					Decl::Variable {
+						is_static: true,
+						..
+					}
+				)
+			}),
+			Stmts::If(_, body, otherwise) => {
+				declares_static(body) || otherwise.as_ref().is_some_and(declares_static)
+			}
+			Stmts::While(_, body) | Stmts::DoWhile(body, _) | Stmts::For { body, .. } => {
+				declares_static(body)
+			}
+			_ => false,
+		})
+	}
+	if !calls(func.scope()).is_empty() {
+		Some(InlineObstacle::Calls)
+	} else if func.parameter().iter().any(|parameter| parameter.is_array) {
+		Some(InlineObstacle::ArrayParameter)
+	} else if declares_static(func.scope()) {
+		Some(InlineObstacle::Static)
+	} else {
+		None
+	}
+}
+
+/// Scope of just the statement, for walking the init or step of a `for`
+/// like any other scope
+fn single(stmt: &Stmts) -> Scope {
+	Scope(vec![stmt.clone()], vec![0])
+}
+
+/// Table indices of the functions called within `scope`, nested scopes
+/// included
+fn calls(scope: &Scope) -> Vec<usize> {
+	let call = |expr: &Expression| match expr {
+		Expression::FuncCall(sig, _) => Some(sig.table_index),
+		_ => None,
+	};
+	let mut res = Vec::new();
+	for stmt in &scope.0 {
+		match stmt {
+			Stmts::If(expr, body, _) | Stmts::While(expr, body) | Stmts::DoWhile(body, expr) => {
+				res.extend(call(expr));
+				res.append(&mut calls(body));
+				if let Stmts::If(_, _, Some(otherwise)) = stmt {
+					res.append(&mut calls(otherwise));
+				}
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				for clause in init.iter().chain(step) {
+					res.append(&mut calls(&single(clause)));
+				}
+				res.extend(call(cond));
+				res.append(&mut calls(body));
+			}
+			Stmts::Decl(decls) => res.extend(decls.iter().filter_map(|decl| match decl {
+				Decl::Variable {
+					init_val: Some(expr),
+					..
+				} => call(expr),
+				_ => None,
+			})),
+			Stmts::Assignment(_, expr) | Stmts::Return(Some(expr)) => res.extend(call(expr)),
+			Stmts::ArrayAssignment(_, index, value) => {
+				res.extend(call(index));
+				res.extend(call(value));
+			}
+			Stmts::FuncCall(sig, _) => res.push(sig.table_index),
+			Stmts::Break | Stmts::Continue | Stmts::Return(None) => (),
+		}
+	}
+	res
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum IdentType {
+pub enum IdentType {
 	Primitive,
 	Array,
 }
 
-type ScopeTable = Vec<(usize, IdentType)>;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FunctionType {
+	parameters: Vec<IdentType>,
+	return_type: ReturnType,
+	is_external: bool,
+	/// Takes any arguments past `parameters`
+	is_variadic: bool,
+}
+
+/// Declaration of every identifier in the scope along with its type
+type ScopeTable = Vec<(Ident, IdentType)>;
+/// Every function defined so far, keyed by the name's `table_index`
+type DefinedFunctions = HashMap<usize, FunctionType>;
+
 #[derive(Debug)]
 struct ScopeStack<'a> {
 	scope_table: Vec<ScopeTable>,
-	defined_functions: &'a HashMap<usize, usize>,
+	defined_functions: &'a DefinedFunctions,
+	/// The function being analyzed
+	function: FuncSignature,
+	return_type: ReturnType,
+	warnings: Vec<SemanticWarning>,
+	/// Analysis goes on past a failing statement, collecting the errors of
+	/// all of them
+	errors: Vec<SemanticError>,
+	/// Only tracked through straight-line code, entering or leaving a nested
+	/// scope forgets all of them
+	constants: ConstEnv,
+	/// Number of loops enclosing the statement being analyzed
+	loop_depth: usize,
+	/// Declaration found by every lookup, see `resolve`
+	resolutions: RefCell<HashMap<Ident, Ident>>,
 }
 
 enum ScopeKind {
@@ -54,23 +490,34 @@ enum ScopeKind {
 }
 
 impl<'a> ScopeStack<'a> {
-	fn new(parameters: Vec<usize>, defined_functions: &'a HashMap<usize, usize>) -> Self {
+	fn new(func: &Func, defined_functions: &'a DefinedFunctions) -> Self {
+		let parameter_types = &defined_functions[&func.name().table_index].parameters;
 		Self {
-			scope_table: vec![parameters
+			scope_table: vec![func
+				.parameter()
 				.iter()
-				.copied()
-				.map(|id| (id, IdentType::Primitive))
+				.map(|parameter| parameter.name)
+				.zip(parameter_types.iter().copied())
 				.collect()],
 			defined_functions,
+			function: func.name(),
+			return_type: func.return_type(),
+			warnings: Vec::new(),
+			errors: Vec::new(),
+			constants: HashMap::new(),
+			loop_depth: 0,
+			resolutions: RefCell::new(HashMap::new()),
 		}
 	}
 	fn get_ident_type(&self, ident: &Ident) -> Option<IdentType> {
-		self.scope_table
+		let &(declaration, ident_type) = self
+			.scope_table
 			.iter()
 			.flatten()
 			.rev()
-			.find(|(i, _)| *i == ident.table_index)
-			.map(|i| i.1)
+			.find(|(i, _)| i.table_index == ident.table_index)?;
+		self.resolutions.borrow_mut().insert(*ident, declaration);
+		Some(ident_type)
 	}
 	fn find_ident(&self, ident: &Ident) -> Result<(), SemanticError> {
 		match self.get_ident_type(ident) {
@@ -86,117 +533,1266 @@ impl<'a> ScopeStack<'a> {
 			None => Err(SemanticError::UseBeforeDeclaration(*ident)),
 		}
 	}
-	fn expression_valid(&mut self, expr: &Expression) -> Result<(), SemanticError> {
-		let find_direct_value = |direct_value: &DirectValue| -> Result<(), SemanticError> {
-			if let DirectValue::Ident(i) = direct_value {
-				self.find_ident(i)?
+	fn direct_value_valid(
+		&mut self,
+		direct_value: &DirectValue,
+		line_number: usize,
+	) -> Result<(), SemanticError> {
+		match direct_value {
+			DirectValue::Ident(i) => self.find_ident(i),
+			DirectValue::ArrayElement(ident, index) => {
+				self.expression_valid(index, line_number)?;
+				self.find_array(ident)
 			}
-			Ok(())
+			DirectValue::Const(_) | DirectValue::Literal(_) => Ok(()),
+		}
+	}
+	/// Records the value assigned to `ident`, forgetting it when not constant
+	fn assign(&mut self, ident: &Ident, expr: Option<&Expression>) {
+		match expr.and_then(|expr| const_eval(expr, &self.constants)) {
+			Some(value) => self.constants.insert(ident.table_index, value),
+			None => self.constants.remove(&ident.table_index),
 		};
+	}
+	/// Validates the call and returns the callee's return type
+	fn call_valid(
+		&mut self,
+		sig: &FuncSignature,
+		arguments: &[Expression],
+		line_number: usize,
+	) -> Result<ReturnType, SemanticError> {
+		let Some(function_type) = self.defined_functions.get(&sig.table_index) else {
+			return Err(SemanticError::UndefinedFunction(*sig));
+		};
+		let fixed = function_type.parameters.len();
+		if arguments.len() < fixed || (arguments.len() > fixed && !function_type.is_variadic) {
+			return Err(SemanticError::InvalidArguments(
+				*sig,
+				ArgumentMismatch::Count(arguments.len()),
+			));
+		}
+		// Any value goes past the parameters, arrays passing their address
+		for argument in &arguments[fixed..] {
+			match argument {
+				Expression::DirectValue(DirectValue::Ident(ident)) => {
+					self.get_ident_type(ident)
+						.ok_or(SemanticError::UseBeforeDeclaration(*ident))?;
+				}
+				Expression::DirectValue(DirectValue::Literal(_)) => (),
+				_ => self.expression_valid(argument, line_number)?,
+			}
+		}
+		for (index, (argument, &expected)) in arguments
+			.iter()
+			.zip(function_type.parameters.iter())
+			.enumerate()
+		{
+			let found = match argument {
+				Expression::DirectValue(DirectValue::Ident(ident)) => self
+					.get_ident_type(ident)
+					.ok_or(SemanticError::UseBeforeDeclaration(*ident))?,
+				Expression::DirectValue(DirectValue::Literal(_)) if !function_type.is_external => {
+					return Err(SemanticError::InvalidArguments(
+						*sig,
+						ArgumentMismatch::Literal { index },
+					));
+				}
+				Expression::DirectValue(DirectValue::Literal(_)) => IdentType::Array,
+				_ => {
+					self.expression_valid(argument, line_number)?;
+					IdentType::Primitive
+				}
+			};
+			if found != expected {
+				return Err(SemanticError::InvalidArguments(
+					*sig,
+					ArgumentMismatch::Type { index, expected },
+				));
+			}
+		}
+		Ok(function_type.return_type)
+	}
+	fn expression_valid(
+		&mut self,
+		expr: &Expression,
+		line_number: usize,
+	) -> Result<(), SemanticError> {
 		match expr {
 			Expression::ArrayAccess(ident, index) => {
-				find_direct_value(index).and_then(|_| self.find_array(ident))
+				self.expression_valid(index, line_number)?;
+				self.find_array(ident)
 			}
 			Expression::FuncCall(sig, arguments) => {
-				let arg_count = self.defined_functions.get(&sig.table_index).copied();
-				if arg_count.is_none() {
-					return Err(SemanticError::UndefinedFunction(*sig));
+				match self.call_valid(sig, arguments, line_number)? {
+					ReturnType::Int => Ok(()),
+					ReturnType::Void => Err(SemanticError::VoidValueUsed(*sig)),
 				}
-				if arg_count != Some(arguments.len()) {
-					return Err(SemanticError::InvalidArguments(*sig));
+			}
+			Expression::DirectValue(d_value) => self.direct_value_valid(d_value, line_number),
+			Expression::Binary(l_value, operation, r_value) => {
+				self.expression_valid(l_value, line_number)?;
+				let skipped = match (operation, const_eval(l_value, &self.constants)) {
+					(BinaryOperation::LogicalAnd, Some(lhs)) => lhs == 0,
+					(BinaryOperation::LogicalOr, Some(lhs)) => lhs != 0,
+					_ => false,
+				};
+				if skipped {
+					// The right operand is never evaluated, dividing by zero there is harmless
+					let constants = std::mem::take(&mut self.constants);
+					let valid = self.expression_valid(r_value, line_number);
+					self.constants = constants;
+					return valid;
 				}
-				for direct_value in arguments {
-					find_direct_value(direct_value)?;
+				self.expression_valid(r_value, line_number)?;
+				if matches!(operation, BinaryOperation::Div | BinaryOperation::Mod)
+					&& const_eval(r_value, &self.constants) == Some(0)
+				{
+					return Err(SemanticError::DivisionByZero(line_number));
 				}
 				Ok(())
 			}
-			Expression::DirectValue(d_value) => find_direct_value(d_value),
-			Expression::Binary(l_value, _, r_value) => {
-				find_direct_value(l_value).and_then(|_| find_direct_value(r_value))
+			Expression::Unary(_, operand) => self.expression_valid(operand, line_number),
+			Expression::Ternary(condition, then, otherwise) => {
+				self.expression_valid(condition, line_number)?;
+				self.expression_valid(then, line_number)?;
+				self.expression_valid(otherwise, line_number)
 			}
 		}
 	}
-	fn scope_analyze(
-		&mut self,
-		scope: &Scope,
-		scope_kind: ScopeKind,
-		in_loop: bool,
-	) -> Result<(), SemanticError> {
+	/// Whether a `break` or `continue` can leave `count` enclosing loops
+	fn loop_exit_valid(&self, count: usize) -> bool {
+		(1..=self.loop_depth).contains(&count)
+	}
+	fn scope_analyze(&mut self, scope: &Scope, scope_kind: ScopeKind) {
 		if let ScopeKind::Nested = scope_kind {
 			self.scope_table.push(ScopeTable::new());
 		}
-		for stmt in scope.0.iter() {
-			match stmt {
-				Stmts::Decl(decls) => {
-					for decl in decls {
-						match decl {
-							Decl::Variable { name, init_val } => {
-								if self
-									.scope_table
-									.last()
-									.unwrap()
-									.iter()
-									.any(|i| i.0 == name.table_index)
-								{
-									return Err(SemanticError::MultipleDeclaration(*name));
-								}
-								if let Some(expr) = init_val {
-									self.expression_valid(expr)?;
-								}
-								self.scope_table
-									.last_mut()
-									.unwrap()
-									.push((name.table_index, IdentType::Primitive))
+		for (stmt, &line_number) in scope.0.iter().zip(scope.1.iter()) {
+			let analyzed = self.stmt_analyze(stmt, line_number);
+			self.record(analyzed);
+		}
+		self.scope_table.pop();
+	}
+	/// Keeps the error of `result` for later, letting the caller go on
+	fn record(&mut self, result: Result<(), SemanticError>) {
+		if let Err(error) = result {
+			self.errors.push(error);
+		}
+	}
+	fn stmt_analyze(&mut self, stmt: &Stmts, line_number: usize) -> Result<(), SemanticError> {
+		match stmt {
+			Stmts::Decl(decls) => {
+				for decl in decls {
+					match decl {
+						Decl::Variable {
+							name,
+							init_val,
+							is_static,
+						} => {
+							if self
+								.scope_table
+								.last()
+								.unwrap()
+								.iter()
+								.any(|i| i.0.table_index == name.table_index)
+							{
+								self.errors.push(SemanticError::MultipleDeclaration(*name));
+								continue;
+							}
+							if let Some(expr) = init_val {
+								let analyzed = self.expression_valid(expr, line_number);
+								self.record(analyzed);
 							}
-							Decl::Array { name, size: _ } => {
-								if self
-									.scope_table
-									.last()
-									.unwrap()
-									.iter()
-									.any(|i| i.0 == name.table_index)
+							if *is_static {
+								// Initialized once, so only known till the first call
+								if let Some(expr) = init_val
+									&& const_eval(expr, &ConstEnv::new()).is_none()
 								{
-									return Err(SemanticError::MultipleDeclaration(*name));
+									self.errors
+										.push(SemanticError::NonConstantStaticInitializer(*name));
 								}
-								self.scope_table
-									.last_mut()
-									.unwrap()
-									.push((name.table_index, IdentType::Array))
+								self.assign(name, None);
+							} else {
+								self.assign(name, init_val.as_ref());
+							}
+							self.scope_table
+								.last_mut()
+								.unwrap()
+								.push((*name, IdentType::Primitive))
+						}
+						Decl::Array { name, size: _ } => {
+							if self
+								.scope_table
+								.last()
+								.unwrap()
+								.iter()
+								.any(|i| i.0.table_index == name.table_index)
+							{
+								self.errors.push(SemanticError::MultipleDeclaration(*name));
+								continue;
 							}
+							self.scope_table
+								.last_mut()
+								.unwrap()
+								.push((*name, IdentType::Array))
 						}
 					}
 				}
-				Stmts::Assignment(ident, expr) => {
-					self.find_ident(ident)?;
-					self.expression_valid(expr)?;
-				}
-				Stmts::ArrayAssignment(ident, index, r_value) => {
-					self.find_array(ident)?;
-					self.expression_valid(index)?;
-					self.expression_valid(r_value)?;
-				}
-				Stmts::If(expr, scope) | Stmts::While(expr, scope) => {
-					self.expression_valid(expr)?;
-					self.scope_analyze(
-						scope,
-						ScopeKind::Nested,
-						matches!(stmt, Stmts::While(_, _)) | in_loop,
-					)?
-				}
-				Stmts::Return(expr) => self.expression_valid(expr)?,
-				Stmts::Break => {
-					if !in_loop {
-						return Err(SemanticError::BreakOutsideLoop);
+			}
+			Stmts::Assignment(ident, expr) => {
+				self.find_ident(ident)?;
+				self.expression_valid(expr, line_number)?;
+				self.assign(ident, Some(expr));
+			}
+			Stmts::ArrayAssignment(ident, index, r_value) => {
+				self.find_array(ident)?;
+				self.expression_valid(index, line_number)?;
+				self.expression_valid(r_value, line_number)?;
+			}
+			Stmts::If(expr, scope, _) | Stmts::While(expr, scope) => {
+				let analyzed = self.expression_valid(expr, line_number);
+				self.record(analyzed);
+				let infinite_loop = matches!(
+					(stmt, expr),
+					(
+						Stmts::While(..),
+						Expression::DirectValue(DirectValue::Const(1))
+					)
+				);
+				if !infinite_loop && let Some(holds) = constant_condition(expr) {
+					self.warnings
+						.push(SemanticWarning::ConstantCondition(line_number, holds));
+				}
+				let is_loop = matches!(stmt, Stmts::While(_, _));
+				self.loop_depth += is_loop as usize;
+				self.constants.clear();
+				self.scope_analyze(scope, ScopeKind::Nested);
+				self.constants.clear();
+				if let Stmts::If(_, _, Some(otherwise)) = stmt {
+					self.scope_analyze(otherwise, ScopeKind::Nested);
+					self.constants.clear();
+				}
+				self.loop_depth -= is_loop as usize;
+			}
+			Stmts::DoWhile(scope, expr) => {
+				self.loop_depth += 1;
+				self.constants.clear();
+				self.scope_analyze(scope, ScopeKind::Nested);
+				self.constants.clear();
+				self.loop_depth -= 1;
+				// Declarations of the body are out of scope in the condition
+				self.expression_valid(expr, line_number)?;
+				let idiomatic = matches!(expr, Expression::DirectValue(DirectValue::Const(0 | 1)));
+				if !idiomatic && let Some(holds) = constant_condition(expr) {
+					self.warnings
+						.push(SemanticWarning::ConstantCondition(line_number, holds));
+				}
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				// Declared by `init`, only visible within the loop
+				self.scope_table.push(ScopeTable::new());
+				if let Some(init) = init {
+					let analyzed = self.stmt_analyze(init, line_number);
+					self.record(analyzed);
+				}
+				self.constants.clear();
+				let analyzed = self.expression_valid(cond, line_number);
+				self.record(analyzed);
+				let infinite_loop = matches!(cond, Expression::DirectValue(DirectValue::Const(1)));
+				if !infinite_loop && let Some(holds) = constant_condition(cond) {
+					self.warnings
+						.push(SemanticWarning::ConstantCondition(line_number, holds));
+				}
+				self.loop_depth += 1;
+				self.scope_analyze(body, ScopeKind::Nested);
+				self.constants.clear();
+				if let Some(step) = step {
+					let analyzed = self.stmt_analyze(step, line_number);
+					self.record(analyzed);
+					self.constants.clear();
+				}
+				self.loop_depth -= 1;
+				self.scope_table.pop();
+			}
+			Stmts::FuncCall(sig, arguments) => {
+				self.call_valid(sig, arguments, line_number)?;
+			}
+			Stmts::Return(expr) => match (self.return_type, expr) {
+				(ReturnType::Int, Some(Expression::DirectValue(DirectValue::Ident(ident))))
+					if self.get_ident_type(ident) == Some(IdentType::Array) =>
+				{
+					return Err(SemanticError::CannotReturnArray(*ident));
+				}
+				(ReturnType::Int, Some(expr)) => self.expression_valid(expr, line_number)?,
+				(ReturnType::Void, None) => (),
+				(ReturnType::Int, None) => {
+					return Err(SemanticError::MissingReturnValue(self.function));
+				}
+				(ReturnType::Void, Some(_)) => {
+					return Err(SemanticError::UnexpectedReturnValue(self.function));
+				}
+			},
+			Stmts::Break => {
+				if !self.loop_exit_valid(1) {
+					return Err(SemanticError::BreakOutsideLoop(line_number));
+				}
+			}
+			Stmts::Continue => {
+				if !self.loop_exit_valid(1) {
+					return Err(SemanticError::ContinueOutsideLoop(line_number));
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Variables known to hold a constant, keyed by `table_index`
+pub type ConstEnv = HashMap<usize, i32>;
+
+/// Value of `expr` if known at compile time, variables being looked up in
+/// `env`. Calls and array accesses are never constant, nor are divisions by
+/// zero.
+pub fn const_eval(expr: &Expression, env: &ConstEnv) -> Option<i32> {
+	match expr {
+		Expression::DirectValue(direct_value) => const_value(direct_value, env),
+		Expression::Binary(lhs, operation, rhs) => {
+			let lhs = const_eval(lhs, env)?;
+			match (operation, lhs) {
+				(BinaryOperation::LogicalAnd, 0) => Some(0),
+				(BinaryOperation::LogicalOr, lhs) if lhs != 0 => Some(1),
+				_ => operation.evaluate(lhs, const_eval(rhs, env)?),
+			}
+		}
+		Expression::Unary(operation, operand) => {
+			Some(operation.evaluate(const_eval(operand, env)?))
+		}
+		Expression::Ternary(condition, then, otherwise) => match const_eval(condition, env)? {
+			0 => const_eval(otherwise, env),
+			_ => const_eval(then, env),
+		},
+		Expression::FuncCall(..) | Expression::ArrayAccess(..) => None,
+	}
+}
+
+/// Value of the `DirectValue` if known at compile time, see `const_eval`
+pub fn const_value(direct_value: &DirectValue, env: &ConstEnv) -> Option<i32> {
+	let value = |ident: &Ident| env.get(&ident.table_index).copied();
+	match direct_value {
+		DirectValue::Ident(ident) => value(ident),
+		DirectValue::ArrayElement(..) | DirectValue::Literal(_) => None,
+		DirectValue::Const(value) => Some(*value),
+	}
+}
+
+/// Whether control never reaches past the end of the scope, by returning or
+/// looping forever in a `while`, `do` or `for` with a constant non zero
+/// condition and no `break` out of it, or an `if` whose branches both
+/// terminate. The body of a `do` always runs, so it terminating is enough
+/// when it can't `break`
+fn terminates(scope: &Scope) -> bool {
+	scope.0.iter().any(|stmt| match stmt {
+		Stmts::Return(_) => true,
+		Stmts::If(_, body, Some(otherwise)) => terminates(body) && terminates(otherwise),
+		Stmts::While(Expression::DirectValue(DirectValue::Const(value)), body)
+		| Stmts::For {
+			cond: Expression::DirectValue(DirectValue::Const(value)),
+			body,
+			..
+		} => *value != 0 && !breaks(body),
+		Stmts::DoWhile(body, cond) => {
+			!breaks(body)
+				&& (terminates(body)
+					|| matches!(cond, Expression::DirectValue(DirectValue::Const(value)) if *value != 0))
+		}
+		_ => false,
+	})
+}
+
+/// Reads of the variables in `unassigned` or declared uninitialized within
+/// `scope` before they are definitely assigned. Parameters and statics start
+/// out assigned.
+///
+/// The body of an `if`, `while` or `for` may not run, so its assignments are
+/// forgotten once it ends, unless made in both branches of an `if` with an
+/// `else`. Those of a `do` are kept when it has no `break` skipping them. The
+/// statements after a jump are unreachable.
+fn uninitialized_reads(scope: &Scope, unassigned: &mut HashSet<usize>) -> Vec<Ident> {
+	fn direct_value_reads(value: &DirectValue, res: &mut Vec<Ident>) {
+		match value {
+			DirectValue::Ident(ident) => res.push(*ident),
+			DirectValue::ArrayElement(_, index) => expression_reads(index, res),
+			DirectValue::Const(_) | DirectValue::Literal(_) => (),
+		}
+	}
+	fn expression_reads(expr: &Expression, res: &mut Vec<Ident>) {
+		match expr {
+			Expression::FuncCall(_, arguments) => arguments
+				.iter()
+				.for_each(|argument| expression_reads(argument, res)),
+			Expression::ArrayAccess(_, index) => expression_reads(index, res),
+			Expression::DirectValue(value) => direct_value_reads(value, res),
+			Expression::Binary(lhs, _, rhs) => {
+				expression_reads(lhs, res);
+				expression_reads(rhs, res);
+			}
+			Expression::Unary(_, operand) => expression_reads(operand, res),
+			// Either value may be selected
+			Expression::Ternary(condition, then, otherwise) => {
+				expression_reads(condition, res);
+				expression_reads(then, res);
+				expression_reads(otherwise, res);
+			}
+		}
+	}
+	/// Reported once, further reads would only repeat the warning
+	fn read(expr: &Expression, unassigned: &mut HashSet<usize>, res: &mut Vec<Ident>) {
+		let mut reads = Vec::new();
+		expression_reads(expr, &mut reads);
+		res.extend(
+			reads
+				.into_iter()
+				.filter(|ident| unassigned.remove(&ident.table_index)),
+		);
+	}
+	let mut res = Vec::new();
+	for stmt in &scope.0 {
+		match stmt {
+			Stmts::If(expr, body, None) | Stmts::While(expr, body) => {
+				read(expr, unassigned, &mut res);
+				let reported = uninitialized_reads(body, &mut unassigned.clone());
+				for ident in &reported {
+					unassigned.remove(&ident.table_index);
+				}
+				res.extend(reported);
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				if let Some(init) = init {
+					res.append(&mut uninitialized_reads(&single(init), unassigned));
+				}
+				read(cond, unassigned, &mut res);
+				// The step only runs after the body
+				let mut looped = unassigned.clone();
+				let mut reported = uninitialized_reads(body, &mut looped);
+				if let Some(step) = step {
+					reported.append(&mut uninitialized_reads(&single(step), &mut looped));
+				}
+				for ident in &reported {
+					unassigned.remove(&ident.table_index);
+				}
+				res.extend(reported);
+			}
+			Stmts::DoWhile(body, expr) => {
+				let mut looped = unassigned.clone();
+				let mut reported = uninitialized_reads(body, &mut looped);
+				read(expr, &mut looped, &mut reported);
+				match breaks(body) {
+					true => {
+						for ident in &reported {
+							unassigned.remove(&ident.table_index);
+						}
 					}
+					false => *unassigned = looped,
+				}
+				res.extend(reported);
+			}
+			Stmts::If(expr, body, Some(otherwise)) => {
+				read(expr, unassigned, &mut res);
+				let mut body_unassigned = unassigned.clone();
+				let mut reported = uninitialized_reads(body, &mut body_unassigned);
+				let mut otherwise_unassigned = unassigned.clone();
+				for ident in &reported {
+					otherwise_unassigned.remove(&ident.table_index);
 				}
-				Stmts::Continue => {
-					if !in_loop {
-						return Err(SemanticError::ContinueOutsideLoop);
+				reported.append(&mut uninitialized_reads(
+					otherwise,
+					&mut otherwise_unassigned,
+				));
+				// Only the branches reaching past the `if` matter
+				unassigned.retain(|index| {
+					(!terminates(body) && body_unassigned.contains(index))
+						|| (!terminates(otherwise) && otherwise_unassigned.contains(index))
+				});
+				for ident in &reported {
+					unassigned.remove(&ident.table_index);
+				}
+				res.extend(reported);
+			}
+			Stmts::Decl(decls) => {
+				for decl in decls {
+					match decl {
+						Decl::Array { name, .. } => {
+							unassigned.remove(&name.table_index);
+						}
+						Decl::Variable {
+							name,
+							init_val: Some(expr),
+							..
+						} => {
+							read(expr, unassigned, &mut res);
+							unassigned.remove(&name.table_index);
+						}
+						Decl::Variable {
+							name, is_static, ..
+						} => {
+							match is_static {
+								true => unassigned.remove(&name.table_index),
+								false => unassigned.insert(name.table_index),
+							};
+						}
 					}
 				}
 			}
+			Stmts::Assignment(name, expr) => {
+				read(expr, unassigned, &mut res);
+				unassigned.remove(&name.table_index);
+			}
+			Stmts::ArrayAssignment(_, index, value) => {
+				read(index, unassigned, &mut res);
+				read(value, unassigned, &mut res);
+			}
+			Stmts::FuncCall(sig, arguments) => {
+				let call = Expression::FuncCall(*sig, arguments.clone());
+				read(&call, unassigned, &mut res);
+			}
+			Stmts::Return(Some(expr)) => {
+				read(expr, unassigned, &mut res);
+				break;
+			}
+			Stmts::Break | Stmts::Continue | Stmts::Return(None) => break,
 		}
-		self.scope_table.pop();
-		Ok(())
+	}
+	res
+}
+
+/// Whether a `break` in the scope leaves the loop enclosing it, those within
+/// nested loops only leave the nested loop
+fn breaks(scope: &Scope) -> bool {
+	scope.0.iter().any(|stmt| match stmt {
+		Stmts::Break => true,
+		Stmts::If(_, body, otherwise) => breaks(body) || otherwise.as_ref().is_some_and(breaks),
+		_ => false,
+	})
+}
+
+/// Whether the condition always holds or never does, if known at compile
+/// time
+fn constant_condition(expr: &Expression) -> Option<bool> {
+	match is_constant(expr) {
+		true => const_eval(expr, &ConstEnv::new()).map(|value| value != 0),
+		false => None,
+	}
+}
+
+/// Whether the value of the expression is known at compile time
+fn is_constant(expr: &Expression) -> bool {
+	match expr {
+		Expression::DirectValue(DirectValue::Const(_)) => true,
+		Expression::Binary(lhs, _, rhs) => is_constant(lhs) && is_constant(rhs),
+		Expression::Unary(_, operand) => is_constant(operand),
+		_ => false,
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse};
+
+	#[test]
+	fn argument_count() {
+		let test_program = r"
+			int f(int a, int b) { return a; }
+			int main(int n) { return f(n); }
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::InvalidArguments(
+				_,
+				ArgumentMismatch::Count(1)
+			)]
+		));
+	}
+
+	#[test]
+	fn variadic() {
+		let analyzed =
+			|test_program: &str| analyze(&parse(tokenize(test_program).unwrap()).unwrap().0);
+		let test_program = r#"
+			int printf(int format[], ...);
+			int main(int n) {
+				int a[2];
+				printf("%d", n);
+				printf("%d %d %p", n, -n, a);
+				return printf("");
+			}
+		"#;
+		assert!(analyzed(test_program).is_ok());
+		let test_program = r"
+			int printf(int format[], ...);
+			int main(int n) { return printf(); }
+		";
+		assert!(matches!(
+			analyzed(test_program).unwrap_err().as_slice(),
+			[SemanticError::InvalidArguments(
+				_,
+				ArgumentMismatch::Count(0)
+			)]
+		));
+		let test_program = r#"
+			int printf(int format[], ...);
+			int main(int n) { return printf("%d", m); }
+		"#;
+		assert!(matches!(
+			analyzed(test_program).unwrap_err().as_slice(),
+			[SemanticError::UseBeforeDeclaration(_)]
+		));
+		// Only the declaration with `...` takes extra arguments
+		let test_program = r#"
+			int printf(int format[], ...);
+			int puts(int s[]);
+			int main(int n) { return puts("%d", n); }
+		"#;
+		assert!(matches!(
+			analyzed(test_program).unwrap_err().as_slice(),
+			[SemanticError::InvalidArguments(
+				_,
+				ArgumentMismatch::Count(2)
+			)]
+		));
+	}
+
+	#[test]
+	fn array_to_scalar_parameter() {
+		let test_program = r"
+			int f(int a, int b) { return a; }
+			int main(int n) {
+				int a[2];
+				return f(n, a);
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::InvalidArguments(
+				_,
+				ArgumentMismatch::Type {
+					index: 1,
+					expected: IdentType::Primitive
+				}
+			)]
+		));
+	}
+
+	#[test]
+	fn scalar_to_array_parameter() {
+		let test_program = r"
+			int f(int a[]) { return 0; }
+			int main(int n) {
+				int a[2];
+				f(a);
+				f(n);
+				return 0;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::InvalidArguments(
+				_,
+				ArgumentMismatch::Type {
+					index: 0,
+					expected: IdentType::Array
+				}
+			)]
+		));
+	}
+
+	#[test]
+	fn array_parameter() {
+		let test_program = r"
+			int sum(int a[], int n) {
+				int res, i;
+				res = 0;
+				i = 0;
+				while (i < n) {
+					res += a[i];
+					i += 1;
+				}
+				return res;
+			}
+			int main() {
+				int arr[3];
+				return sum(arr, 3);
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		let test_program = "int sum(int a[], int n) { return a + n; }";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::ExpectedPrimitiveFoundArray(_)]
+		));
+	}
+
+	#[test]
+	fn constant_condition() {
+		let test_program = r"
+			int main(int n) {
+				if (0) {
+					n = 1;
+				}
+				while (1) {
+					if (2 < 3) {
+						break;
+					}
+				}
+				while (n) {
+					n = n - 1;
+				}
+				return n;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			vec![
+				SemanticWarning::ConstantCondition(3, false),
+				SemanticWarning::ConstantCondition(7, true)
+			],
+			analyze(&parsed).unwrap()
+		);
+	}
+
+	#[test]
+	fn external_redefinition() {
+		let test_program = r"
+			int ext(int n);
+			int ext(int n) { return n; }
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::FunctionRedeclaration(..)]
+		));
+	}
+
+	#[test]
+	fn division_by_zero() {
+		let test_program = r"
+			int main(int x) {
+				int y = 0, z;
+				z = x / y;
+				return z;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::DivisionByZero(4)]
+		));
+		// `y` may have been reassigned inside the `if`
+		let test_program = r"
+			int main(int x) {
+				int y = 0, z;
+				if (x) {
+					y = x;
+				}
+				z = x % y;
+				return z;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		// A static keeps the value of the previous call
+		let test_program = r"
+			int main(int x) {
+				static int y = 0;
+				int z;
+				z = x / y;
+				y = x;
+				return z;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		// The right operand of `&&` and `||` isn't evaluated when the left one
+		// decides the result
+		let test_program = r"
+			int main(int x) {
+				int y = 0, z;
+				z = y != 0 && x / y;
+				z = y == 0 || x % y;
+				z = y || x / y;
+				return z;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::DivisionByZero(6)]
+		));
+	}
+
+	#[test]
+	fn static_initializer() {
+		let test_program = r"
+			int main(int x) {
+				static int a = -2, b = 1 + 2;
+				static int c = x;
+				return a;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let [error] = &analyze(&parsed).unwrap_err()[..] else {
+			unreachable!()
+		};
+		assert_eq!(
+			"static 'c' must be initialized with a constant",
+			error.describe(&table)
+		);
+	}
+
+	#[test]
+	fn collects_errors() {
+		let test_program = r"
+			int main(int n) {
+				int a = f(n);
+				int a;
+				if (a[0]) {
+					break;
+				}
+				return a + b;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		// `a` is declared despite its initializer, so isn't reported again
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[
+				SemanticError::UndefinedFunction(_),
+				SemanticError::MultipleDeclaration(_),
+				SemanticError::ExpectedArrayFoundPrimitive(_),
+				SemanticError::BreakOutsideLoop(6),
+				SemanticError::UseBeforeDeclaration(b),
+			] if b.line_number() == 8
+		));
+	}
+
+	#[test]
+	fn loop_depth() {
+		let test_program = r"
+			int main(int n) {
+				while (n) {
+					while (n) {
+						break;
+					}
+					continue;
+				}
+				break;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::BreakOutsideLoop(9)]
+		));
+		let Program(functions, _) = &parsed;
+		let defined_functions = DefinedFunctions::from([(
+			functions[0].name().table_index,
+			FunctionType {
+				parameters: vec![IdentType::Primitive],
+				return_type: ReturnType::Int,
+				is_external: false,
+				is_variadic: false,
+			},
+		)]);
+		let mut stack = ScopeStack::new(&functions[0], &defined_functions);
+		let Stmts::While(_, outer) = &functions[0].scope().0[0] else {
+			unreachable!()
+		};
+		stack.loop_depth = 1;
+		stack.scope_analyze(outer, ScopeKind::Nested);
+		assert!(stack.errors.is_empty());
+		assert_eq!(1, stack.loop_depth);
+		stack.loop_depth = 2;
+		assert!(!stack.loop_exit_valid(0));
+		assert!(stack.loop_exit_valid(1));
+		assert!(stack.loop_exit_valid(2));
+		assert!(!stack.loop_exit_valid(3));
+	}
+
+	#[test]
+	fn for_loop() {
+		let test_program = r"
+			int main(int n) {
+				for (int i = 0; i < n; i += 1) {
+					if (i == 2)
+						continue;
+					if (i == n)
+						break;
+				}
+				return n;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		// The variable declared by the init clause is scoped to the loop
+		let test_program = test_program.replace("return n;", "return i;");
+		let (parsed, _) = parse(tokenize(&test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::UseBeforeDeclaration(_)]
+		));
+	}
+
+	#[test]
+	fn missing_return() {
+		let test_program = r"
+			int forever(int n) {
+				while (1) {
+					while (n) {
+						break;
+					}
+					return n;
+				}
+			}
+			int escapes(int n) {
+				while (1) {
+					if (n) {
+						break;
+					}
+					return n;
+				}
+			}
+			void nothing() {}
+			int branches(int n) {
+				if (n) {
+					return 1;
+				} else
+					return 2;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		let warnings = analyze(&parsed).unwrap();
+		assert_eq!(1, warnings.len());
+		assert!(matches!(
+			warnings[0],
+			SemanticWarning::MissingReturn(sig) if sig.line_number() == 10
+		));
+	}
+
+	#[test]
+	fn overloading() {
+		let test_program = r"
+			int foo(int a) {}
+			int foo(int a, int b) {}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let [error] = &analyze(&parsed).unwrap_err()[..] else {
+			unreachable!()
+		};
+		assert!(matches!(
+			error,
+			SemanticError::FunctionRedeclaration(sig, previous)
+				if sig.parameter_count == 2 && previous.parameter_count == 1
+		));
+		assert_eq!(
+			"function 'foo' with 2 parameters redeclares the one with 1 parameter at line 2, overloading is not supported",
+			error.describe(&table)
+		);
+	}
+
+	#[test]
+	fn recursion() {
+		let options = AnalyzerOptions { no_recursion: true };
+		let test_program = r"
+			int leaf(int n) { return n; }
+			int fact(int n) {
+				if (n) {
+					int m;
+					m = n - 1;
+					m = fact(m);
+					return n * m;
+				}
+				return 1;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		let [error] = &analyze_with_options(&parsed, options).unwrap_err()[..] else {
+			unreachable!()
+		};
+		assert!(matches!(
+			error,
+			SemanticError::RecursionNotAllowed(sig) if sig.line_number() == 3
+		));
+		assert_eq!(
+			"function 'fact' is recursive, which '--no-recursion' forbids",
+			error.describe(&table)
+		);
+		// Calls must follow the callee's definition, so the cycle is checked
+		// on its own
+		let test_program = r"
+			int leaf(int n) { return n; }
+			int even(int n) { if (n) { n = n - 1; return odd(n); } return 1; }
+			int odd(int n) { if (n) { n = n - 1; return even(n); } return 0; }
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			recursive_function(&parsed),
+			Some(sig) if sig.line_number() == 3
+		));
+		let test_program = r"
+			int leaf(int n) { return n; }
+			int main(int n) { n = leaf(n); return leaf(n); }
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze_with_options(&parsed, options).is_ok());
+	}
+
+	#[test]
+	fn const_evaluation() {
+		let test_program = r"
+			int main(int n) {
+				int a, b;
+				a = 2 + 3;
+				b = n * -4;
+				b = a * 4;
+				a = a / 0;
+				return a;
+			}
+		";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		let expression = |i: usize| match &parsed.0[0].scope().0[i] {
+			Stmts::Assignment(_, expr) => expr,
+			_ => unreachable!(),
+		};
+		let n = parsed.0[0].parameter()[0].name.table_index;
+		let a = match &parsed.0[0].scope().0[0] {
+			Stmts::Decl(decls) => match decls[0] {
+				Decl::Variable { name, .. } => name.table_index,
+				_ => unreachable!(),
+			},
+			_ => unreachable!(),
+		};
+		let env = ConstEnv::new();
+		assert_eq!(Some(5), const_eval(expression(1), &env));
+		assert_eq!(None, const_eval(expression(2), &env));
+		assert_eq!(
+			Some(-12),
+			const_eval(expression(2), &HashMap::from([(n, 3)]))
+		);
+		assert_eq!(
+			Some(20),
+			const_eval(expression(3), &HashMap::from([(a, 5)]))
+		);
+		assert_eq!(None, const_eval(expression(4), &HashMap::from([(a, 5)])));
+	}
+
+	#[test]
+	fn inlining_attributes() {
+		let test_program = r"
+			noinline int ext(int n);
+			inline int add(int a, int b) { return a + b; }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"inlining attribute on 'ext', which has no body",
+			analyze(&parsed).unwrap_err()[0].describe(&table)
+		);
+		let test_program = r"
+			int leaf(int n) { return n; }
+			noinline int twice(int n) { n = leaf(n); return leaf(n); }
+			inline int add(int a, int b) { a = leaf(a); return a + b; }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"'add' is marked inline but calls other functions",
+			analyze(&parsed).unwrap_err()[0].describe(&table)
+		);
+		let test_program = "inline int first(int a[]) { return a[0]; }";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(matches!(
+			analyze(&parsed).unwrap_err().as_slice(),
+			[SemanticError::CannotInline(
+				_,
+				InlineObstacle::ArrayParameter
+			)]
+		));
+	}
+
+	#[test]
+	fn uninitialized() {
+		let uninitialized = |test_program: &str| {
+			let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+			analyze(&parsed)
+				.unwrap()
+				.into_iter()
+				.filter_map(|warning| match warning {
+					SemanticWarning::UsedUninitialized(ident) => {
+						Some((table.0[ident.table_index].clone(), ident.line_number()))
+					}
+					_ => None,
+				})
+				.collect::<Vec<_>>()
+		};
+		let test_program = r"
+			int main() {
+				int x, y;
+				y = x;
+				y = x + 1;
+				return y;
+			}
+		";
+		assert_eq!(vec![("x".to_string(), 4)], uninitialized(test_program));
+		let test_program = r"
+			int main(int n) {
+				int x;
+				if (n) {
+					x = 1;
+				}
+				return x;
+			}
+		";
+		assert_eq!(vec![("x".to_string(), 7)], uninitialized(test_program));
+		let test_program = r"
+			int main(int n) {
+				int x, z = n;
+				static int s;
+				x = n;
+				if (n) {
+					int x;
+					x = 2;
+				}
+				while (x < 3) {
+					x = x + s;
+				}
+				return x - z;
+			}
+		";
+		assert!(uninitialized(test_program).is_empty());
+		let test_program = r"
+			int main(int n) {
+				int x, y;
+				if (n) {
+					x = 1;
+					y = 1;
+				} else if (n < 0) {
+					return 0;
+				} else {
+					x = 2;
+				}
+				return x + y;
+			}
+		";
+		assert_eq!(vec![("y".to_string(), 12)], uninitialized(test_program));
+		// The body of a `do` runs at least once, unless it breaks out early
+		let test_program = r"
+			int main(int n) {
+				int x, y;
+				do {
+					x = n;
+				} while (x > 5);
+				do {
+					if (n)
+						break;
+					y = 1;
+				} while (0);
+				return x + y;
+			}
+		";
+		assert_eq!(vec![("y".to_string(), 12)], uninitialized(test_program));
+	}
+
+	#[test]
+	fn resolution() {
+		let test_program = r"
+			int main(int x) {
+				int y;
+				y = x;
+				if (y) {
+					int x;
+					x = y;
+					y = x;
+				}
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut resolved: Vec<_> = resolve(&parsed)
+			.into_iter()
+			.map(|(usage, declaration)| {
+				(
+					table.0[usage.table_index].as_str(),
+					usage.line_number(),
+					declaration.line_number(),
+				)
+			})
+			.collect();
+		resolved.sort_unstable();
+		assert_eq!(
+			vec![
+				("x", 4, 2),
+				("x", 7, 6),
+				("x", 8, 6),
+				("x", 10, 2),
+				("y", 4, 3),
+				("y", 5, 3),
+				("y", 7, 3),
+				("y", 8, 3),
+			],
+			resolved
+		);
+	}
+
+	#[test]
+	fn return_array() {
+		let test_program = r"
+			int main() {
+				int a[10];
+				a[0] = 1;
+				return a;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"array 'a' returned, only an 'int' can be",
+			analyze(&parsed).unwrap_err()[0].describe(&table)
+		);
+		let test_program = "int first(int a[]) { return a[0]; }";
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+	}
+
+	#[test]
+	fn string_literals() {
+		let test_program = r#"
+			int puts(int s[]);
+			int main() { return puts("hi"); }
+		"#;
+		let (parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		let test_program = r#"
+			int first(int s[]) { return s[0]; }
+			int main() { return first("hi"); }
+		"#;
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"argument 1 of the call to 'first' is a string literal, only external functions take them",
+			analyze(&parsed).unwrap_err()[0].describe(&table)
+		);
 	}
 }
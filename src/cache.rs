@@ -0,0 +1,349 @@
+//! Incremental compilation cache
+//!
+//! The assembly of every function is stored in a directory, named after a
+//! `structural_hash` of its AST combined with the options shaping its code.
+//! Recompiling a program reuses the assembly of every function whose hash is
+//! unchanged, only the edited ones go through `x86_gen::function_gen` again.
+//!
+//! Line numbers are left out of the hash, so adding a line above a function
+//! doesn't invalidate it. Identifiers are hashed by both their index in the
+//! `parser::IdentNameTable` and their name, as labels are derived from both.
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use crate::parser::{
+	Decl, DirectValue, Expression, Func, FuncSignature, Ident, IdentNameTable, Scope, Stmts,
+};
+use crate::x86_gen::Config;
+
+/// Directory of previously generated function assembly
+#[derive(Debug)]
+pub struct Cache {
+	dir: PathBuf,
+	/// Ids of the functions whose assembly wasn't found and got generated, in
+	/// the order they were looked up
+	pub regenerated: Vec<usize>,
+}
+impl Cache {
+	/// Opens the cache in `dir`, creating it when missing
+	pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+		fs::create_dir_all(&dir)?;
+		Ok(Self {
+			dir: dir.as_ref().to_path_buf(),
+			regenerated: Vec::new(),
+		})
+	}
+
+	/// Assembly stored under `key`, calling `generate` and storing its result
+	/// when absent. Failing to write the cache only loses the reuse
+	pub fn get_or_generate(
+		&mut self,
+		id: usize,
+		key: u64,
+		generate: impl FnOnce() -> String,
+	) -> String {
+		let path = self.dir.join(format!("{key:016x}.s"));
+		if let Ok(asm) = fs::read_to_string(&path) {
+			return asm;
+		}
+		self.regenerated.push(id);
+		let asm = generate();
+		if let Err(err) = fs::write(&path, &asm) {
+			log::warn!("Failed to cache {}: {err}", path.display());
+		}
+		asm
+	}
+}
+
+/// Key of a function's assembly, its `structural_hash` along with every
+/// option of `config` changing the code generated for it. The external
+/// functions it calls are part of it too, as calls to them follow another
+/// convention
+pub fn key(
+	structural_hash: u64,
+	externals: &[(usize, bool)],
+	config: &Config,
+	static_locals: bool,
+) -> u64 {
+	let mut hasher = Fnv::default();
+	structural_hash.hash(&mut hasher);
+	externals.hash(&mut hasher);
+	let annotate = config.annotate || log::log_enabled!(log::Level::Debug);
+	let options = (annotate, config.trap, config.div_guard, config.profile);
+	format!("{options:?}").hash(&mut hasher);
+	static_locals.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Hash of the function's AST, stable across runs and independent of the
+/// line numbers
+pub fn structural_hash(func: &Func, ident_table: &IdentNameTable) -> u64 {
+	let mut hasher = Fnv::default();
+	let mut structure = Structure {
+		hasher: &mut hasher,
+		ident_table,
+	};
+	structure.signature(func.name());
+	for parameter in func.parameter() {
+		structure.ident(parameter.name);
+		parameter.is_array.hash(structure.hasher);
+	}
+	structure.scope(func.scope());
+	format!("{:?}", func.return_type()).hash(structure.hasher);
+	func.is_external().hash(structure.hasher);
+	func.is_variadic().hash(structure.hasher);
+	format!("{:?}", func.inlining()).hash(structure.hasher);
+	hasher.finish()
+}
+
+/// 64 bit FNV-1a, unlike `std`'s default hasher its output is fixed across
+/// runs and toolchains
+struct Fnv(u64);
+impl Default for Fnv {
+	fn default() -> Self {
+		Self(0xcbf29ce484222325)
+	}
+}
+impl Hasher for Fnv {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+	fn write(&mut self, bytes: &[u8]) {
+		for byte in bytes {
+			self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+		}
+	}
+}
+
+/// Feeds the AST to the hasher, every node prefixed by a tag of its variant
+struct Structure<'a> {
+	hasher: &'a mut Fnv,
+	ident_table: &'a IdentNameTable,
+}
+impl Structure<'_> {
+	fn tag(&mut self, tag: u8) {
+		tag.hash(self.hasher);
+	}
+	fn ident(&mut self, ident: Ident) {
+		ident.table_index.hash(self.hasher);
+		self.ident_table.0[ident.table_index].hash(self.hasher);
+	}
+	fn signature(&mut self, signature: FuncSignature) {
+		signature.table_index.hash(self.hasher);
+		self.ident_table.0[signature.table_index].hash(self.hasher);
+		signature.parameter_count.hash(self.hasher);
+	}
+	fn scope(&mut self, scope: &Scope) {
+		scope.0.len().hash(self.hasher);
+		for stmt in &scope.0 {
+			self.stmt(stmt);
+		}
+	}
+	fn stmt(&mut self, stmt: &Stmts) {
+		match stmt {
+			Stmts::If(condition, scope, otherwise) => {
+				self.tag(0);
+				self.expression(condition);
+				self.scope(scope);
+				otherwise.is_some().hash(self.hasher);
+				if let Some(otherwise) = otherwise {
+					self.scope(otherwise);
+				}
+			}
+			Stmts::While(condition, scope) => {
+				self.tag(1);
+				self.expression(condition);
+				self.scope(scope);
+			}
+			Stmts::DoWhile(scope, condition) => {
+				self.tag(10);
+				self.scope(scope);
+				self.expression(condition);
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				self.tag(9);
+				for clause in [init, step] {
+					clause.is_some().hash(self.hasher);
+					if let Some(clause) = clause {
+						self.stmt(clause);
+					}
+				}
+				self.expression(cond);
+				self.scope(body);
+			}
+			Stmts::Decl(decls) => {
+				self.tag(2);
+				decls.len().hash(self.hasher);
+				for decl in decls {
+					match decl {
+						Decl::Array { name, size } => {
+							self.tag(0);
+							self.ident(*name);
+							size.hash(self.hasher);
+						}
+						Decl::Variable {
+							name,
+							init_val,
+							is_static,
+						} => {
+							self.tag(1);
+							self.ident(*name);
+							is_static.hash(self.hasher);
+							init_val.is_some().hash(self.hasher);
+							if let Some(init_val) = init_val {
+								self.expression(init_val);
+							}
+						}
+					}
+				}
+			}
+			Stmts::Assignment(ident, expression) => {
+				self.tag(3);
+				self.ident(*ident);
+				self.expression(expression);
+			}
+			Stmts::ArrayAssignment(ident, index, value) => {
+				self.tag(4);
+				self.ident(*ident);
+				self.expression(index);
+				self.expression(value);
+			}
+			Stmts::FuncCall(signature, arguments) => {
+				self.tag(5);
+				self.call(*signature, arguments);
+			}
+			Stmts::Break => self.tag(6),
+			Stmts::Continue => self.tag(7),
+			Stmts::Return(value) => {
+				self.tag(8);
+				value.is_some().hash(self.hasher);
+				if let Some(value) = value {
+					self.expression(value);
+				}
+			}
+		}
+	}
+	fn call(&mut self, signature: FuncSignature, arguments: &[Expression]) {
+		self.signature(signature);
+		arguments.len().hash(self.hasher);
+		for argument in arguments {
+			self.expression(argument);
+		}
+	}
+	fn expression(&mut self, expression: &Expression) {
+		match expression {
+			Expression::FuncCall(signature, arguments) => {
+				self.tag(0);
+				self.call(*signature, arguments);
+			}
+			Expression::ArrayAccess(ident, index) => {
+				self.tag(1);
+				self.ident(*ident);
+				self.expression(index);
+			}
+			Expression::DirectValue(value) => {
+				self.tag(2);
+				self.direct_value(value);
+			}
+			Expression::Binary(lhs, operation, rhs) => {
+				self.tag(3);
+				self.expression(lhs);
+				format!("{operation:?}").hash(self.hasher);
+				self.expression(rhs);
+			}
+			Expression::Ternary(condition, then, otherwise) => {
+				self.tag(4);
+				self.expression(condition);
+				self.expression(then);
+				self.expression(otherwise);
+			}
+			Expression::Unary(operation, operand) => {
+				self.tag(5);
+				format!("{operation:?}").hash(self.hasher);
+				self.expression(operand);
+			}
+		}
+	}
+	fn direct_value(&mut self, value: &DirectValue) {
+		match value {
+			DirectValue::Ident(ident) => {
+				self.tag(0);
+				self.ident(*ident);
+			}
+			DirectValue::ArrayElement(ident, index) => {
+				self.tag(1);
+				self.ident(*ident);
+				self.expression(index);
+			}
+			DirectValue::Const(value) => {
+				self.tag(2);
+				value.hash(self.hasher);
+			}
+			DirectValue::Literal(index) => {
+				self.tag(3);
+				index.hash(self.hasher);
+			}
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{compile_cached, lexer::tokenize, parser::parse};
+
+	#[test]
+	fn line_independent() {
+		let hashes = |source: &str| {
+			let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+			parsed
+				.0
+				.iter()
+				.map(|func| structural_hash(func, &table))
+				.collect::<Vec<_>>()
+		};
+		let program = "int f(int a) {\n\treturn a + 1;\n}\n";
+		assert_eq!(hashes(program), hashes(&format!("\n\n{program}")));
+		assert_ne!(hashes(program), hashes(&program.replace('+', "-")));
+		assert_ne!(hashes(program), hashes(&program.replace("f(", "g(")));
+	}
+
+	#[test]
+	fn recompile_changed() {
+		let dir = std::env::temp_dir().join(format!("ezc-cache-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		let program = r"
+			int twice(int n) {
+				return n * 2;
+			}
+			int main(int n) {
+				return twice(n);
+			}
+		";
+		let config = Config::default();
+		let mut cache = Cache::open(&dir).unwrap();
+		let asm = compile_cached(program, &config, &mut cache).unwrap();
+		assert_eq!(2, cache.regenerated.len());
+		assert_eq!(crate::compile(program, &config).unwrap(), asm);
+
+		let changed = program.replace("n * 2", "n * 3");
+		let mut cache = Cache::open(&dir).unwrap();
+		let asm = compile_cached(&changed, &config, &mut cache).unwrap();
+		let (_, table) = parse(tokenize(&changed).unwrap()).unwrap();
+		let twice = table.0.iter().position(|name| name == "twice").unwrap();
+		assert_eq!(vec![twice], cache.regenerated);
+		assert_eq!(crate::compile(&changed, &config).unwrap(), asm);
+
+		let mut cache = Cache::open(&dir).unwrap();
+		compile_cached(&changed, &config, &mut cache).unwrap();
+		assert!(cache.regenerated.is_empty());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
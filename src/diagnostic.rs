@@ -0,0 +1,276 @@
+//! Diagnostics
+//!
+//! Errors of every stage up to the `analyzer` converted into a `Diagnostic`,
+//! rendered either for humans or as a line of JSON for editors:
+//! ```json
+//! {"line":7,"col":3,"message":"malformed number '0xZ'"}
+//! ```
+//! `CompileError` pairs the error of the stage that failed with its
+//! `Diagnostic`, displayed as `line:col: error: message`. The `analyzer`
+//! reports all of its errors at once, one `Diagnostic` each, and its warnings
+//! the same way labeled `warning` instead.
+use std::fmt;
+
+use crate::{
+	analyzer::{SemanticError, SemanticWarning},
+	lexer::{LexError, LexerOutput, Symbol, Token},
+	parser::{IdentNameTable, ParseError, Program},
+	preprocessor::PreprocessError,
+};
+
+/// Failure of `compile`, tuple structs of the error of the stage that failed
+/// and the `Diagnostic` locating it, or those of every `SemanticError`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+	Preprocess(PreprocessError, Diagnostic),
+	Lex(LexError, Diagnostic),
+	Parse(ParseError, Diagnostic),
+	Semantic(Vec<SemanticError>, Vec<Diagnostic>),
+	/// Ids and frame sizes of the functions over the stack budget, see
+	/// `x86_gen::oversized_frames`
+	Stack(Vec<(usize, usize)>, Vec<Diagnostic>),
+}
+impl CompileError {
+	pub fn preprocess(error: PreprocessError) -> Self {
+		let diagnostic = Diagnostic::preprocess_error(&error);
+		Self::Preprocess(error, diagnostic)
+	}
+	pub fn lex(source: &str, error: LexError) -> Self {
+		let diagnostic = Diagnostic::lex_error(source, &error);
+		Self::Lex(error, diagnostic)
+	}
+	pub fn parse(lexer_output: &LexerOutput, error: ParseError) -> Self {
+		Self::Parse(error, Diagnostic::parse_error(lexer_output, error))
+	}
+	pub fn semantic(errors: Vec<SemanticError>, ident_table: &IdentNameTable) -> Self {
+		let diagnostics = errors
+			.iter()
+			.map(|error| Diagnostic::semantic_error(error, ident_table))
+			.collect();
+		Self::Semantic(errors, diagnostics)
+	}
+	/// Each of the `oversized` frames reported at its function
+	pub fn stack(
+		oversized: Vec<(usize, usize)>,
+		max_stack: usize,
+		program: &Program,
+		ident_table: &IdentNameTable,
+	) -> Self {
+		let diagnostics = oversized
+			.iter()
+			.map(|&(id, stack_usage)| Diagnostic {
+				line: program
+					.0
+					.iter()
+					.find(|func| func.name().table_index == id)
+					.map_or(0, |func| func.name().line_number()),
+				col: None,
+				message: format!(
+					"frame of '{}' uses {stack_usage} bytes, over the budget of {max_stack}",
+					ident_table.0[id]
+				),
+			})
+			.collect();
+		Self::Stack(oversized, diagnostics)
+	}
+	/// In the order they were found, only `Semantic` and `Stack` have more
+	/// than one
+	pub fn diagnostics(&self) -> &[Diagnostic] {
+		match self {
+			Self::Preprocess(_, diagnostic)
+			| Self::Lex(_, diagnostic)
+			| Self::Parse(_, diagnostic) => std::slice::from_ref(diagnostic),
+			Self::Semantic(_, diagnostics) | Self::Stack(_, diagnostics) => diagnostics,
+		}
+	}
+}
+/// One line per `Diagnostic`
+impl fmt::Display for CompileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let lines: Vec<_> = self
+			.diagnostics()
+			.iter()
+			.map(|diagnostic| diagnostic.labeled("error"))
+			.collect();
+		write!(f, "{}", lines.join("\n"))
+	}
+}
+
+/// How diagnostics are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticFormat {
+	#[default]
+	Human,
+	Json,
+}
+impl std::str::FromStr for DiagnosticFormat {
+	type Err = String;
+	/// Parses one of `human` or `json`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"human" => Ok(Self::Human),
+			"json" => Ok(Self::Json),
+			_ => Err(format!("Unknown error format: '{s}'")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	pub line: usize,
+	/// Column starting at 1, `None` when only the line is known
+	pub col: Option<usize>,
+	pub message: String,
+}
+impl Diagnostic {
+	pub fn preprocess_error(error: &PreprocessError) -> Self {
+		let (line, message) = match error {
+			PreprocessError::UnknownDirective(line, directive) => {
+				(*line, format!("unknown directive '#{directive}'"))
+			}
+			PreprocessError::MalformedDefine(line) => (
+				*line,
+				"malformed '#define', expected '#define NAME value'".to_string(),
+			),
+			PreprocessError::Redefinition(line, name) => (
+				*line,
+				format!("'{name}' defined again with a different value"),
+			),
+		};
+		Self {
+			line,
+			col: None,
+			message,
+		}
+	}
+	pub fn lex_error(source: &str, error: &LexError) -> Self {
+		match error {
+			LexError::MalformedNumber(line, literal) => Self {
+				line: *line,
+				col: source
+					.lines()
+					.nth(line - 1)
+					.and_then(|text| text.find(literal.as_str()))
+					.map(|col| col + 1),
+				message: format!("malformed number '{literal}'"),
+			},
+		}
+	}
+	pub fn parse_error(lexer_output: &LexerOutput, error: ParseError) -> Self {
+		// The end of the input is the span of the trailing `Token::Eof`
+		let index = match error {
+			ParseError::UnexpectedToken(_, index) => index,
+			ParseError::UnexpectedEof { .. } => lexer_output.symbol.len() - 1,
+		};
+		let offset = match lexer_output.spans.get(index) {
+			Some(span) => span.start,
+			None => lexer_output.spans.last().map_or(0, |span| span.end),
+		};
+		let (line, col) = lexer_output.position_of(offset);
+		let message = match error {
+			ParseError::UnexpectedToken(Some(Symbol(token, _)), _) if token != Token::Eof => {
+				format!("unexpected {token:?}")
+			}
+			ParseError::UnexpectedEof { expected } => {
+				format!("unexpected end of input, expected {expected}")
+			}
+			ParseError::UnexpectedToken(..) => "unexpected end of input".to_string(),
+		};
+		Self {
+			line,
+			col: Some(col),
+			message,
+		}
+	}
+	pub fn semantic_error(error: &SemanticError, ident_table: &IdentNameTable) -> Self {
+		Self {
+			line: error.line_number(),
+			col: None,
+			message: error.describe(ident_table),
+		}
+	}
+	pub fn semantic_warning(warning: &SemanticWarning, ident_table: &IdentNameTable) -> Self {
+		Self {
+			line: warning.line_number(),
+			col: None,
+			message: warning.describe(ident_table),
+		}
+	}
+	/// `line:col: severity: message`, the column left out when unknown
+	pub fn labeled(&self, severity: &str) -> String {
+		match self.col {
+			Some(col) => format!("{}:{col}: {severity}: {}", self.line, self.message),
+			None => format!("{}: {severity}: {}", self.line, self.message),
+		}
+	}
+	pub fn render(&self, format: DiagnosticFormat) -> String {
+		match format {
+			DiagnosticFormat::Human => match self.col {
+				Some(col) => format!("{}:{col}: {}", self.line, self.message),
+				None => format!("{}: {}", self.line, self.message),
+			},
+			DiagnosticFormat::Json => format!(
+				r#"{{"line":{},"col":{},"message":"{}"}}"#,
+				self.line,
+				self.col.map_or("null".to_string(), |col| col.to_string()),
+				self.message.replace('\\', "\\\\").replace('"', "\\\"")
+			),
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{analyzer::analyze, lexer::tokenize, parser::parse};
+
+	#[test]
+	fn json() {
+		let source = "int main() {\n\tint 5;\n}";
+		let lexer_output = tokenize(source).unwrap();
+		let Err(error) = parse(lexer_output.clone()) else {
+			unreachable!()
+		};
+		assert_eq!(
+			r#"{"line":2,"col":6,"message":"unexpected Const(0)"}"#,
+			Diagnostic::parse_error(&lexer_output, error).render(DiagnosticFormat::Json)
+		);
+		let source = "int main() {\n\treturn 0;";
+		let lexer_output = tokenize(source).unwrap();
+		let error = parse(lexer_output.clone()).unwrap_err();
+		assert_eq!(
+			"2:11: unexpected end of input, expected '}'",
+			Diagnostic::parse_error(&lexer_output, error).render(DiagnosticFormat::Human)
+		);
+		let source = "int a;\na = 0xZ;";
+		let error = tokenize(source).unwrap_err();
+		let diagnostic = Diagnostic::lex_error(source, &error);
+		assert_eq!(
+			r#"{"line":2,"col":5,"message":"malformed number '0xZ'"}"#,
+			diagnostic.render(DiagnosticFormat::Json)
+		);
+		assert_eq!(
+			"2:5: malformed number '0xZ'",
+			diagnostic.render(DiagnosticFormat::Human)
+		);
+	}
+
+	#[test]
+	fn warning() {
+		let source = "int main(int n) {\n\tint x;\n\tif (1 < 0)\n\t\tn = x;\n\treturn n;\n}";
+		let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+		let warnings: Vec<_> = analyze(&parsed)
+			.unwrap()
+			.iter()
+			.map(|warning| Diagnostic::semantic_warning(warning, &table).labeled("warning"))
+			.collect();
+		assert_eq!(
+			vec![
+				"3: warning: condition is always false",
+				"4: warning: 'x' may be used uninitialized"
+			],
+			warnings
+		);
+	}
+}
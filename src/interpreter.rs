@@ -0,0 +1,567 @@
+//! TAC Interpreter
+//!
+//! Call `interpreter::run` with the output of `tac_gen::generate` to execute a
+//! function without assembling the program. Arrays live in a single flat
+//! memory and are passed around by their base address, same as the x86 backend.
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::tac_gen::{Function, Ident, Instruction, Operand, RValue};
+
+/// Calls nested any deeper are reported as `RuntimeError::StackOverflow`
+pub const MAX_CALL_DEPTH: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+	/// Call to a function without a body, such as an external one
+	UndefinedFunction(usize),
+	/// Read of a parameter not passed by the caller
+	MissingArgument(usize),
+	DivisionByZero,
+	/// Tuple struct of the array's base address and the index accessed
+	OutOfBounds(usize, i32),
+	StackOverflow,
+}
+
+/// Calls the function with `id` and returns its result
+pub fn run(functions: &[Function], id: usize, arguments: &[i32]) -> Result<i32, RuntimeError> {
+	run_profiled(functions, id, arguments).map(|(value, _)| value)
+}
+
+/// Same as `run`, along with the number of times every function was called,
+/// keyed by id. Functions never called are absent
+pub fn run_profiled(
+	functions: &[Function],
+	id: usize,
+	arguments: &[i32],
+) -> Result<(i32, HashMap<usize, usize>), RuntimeError> {
+	let mut interpreter = Interpreter {
+		functions: functions
+			.iter()
+			.map(|function| (function.id, function))
+			.collect(),
+		memory: Vec::new(),
+		statics: functions
+			.iter()
+			.map(|function| {
+				let statics = function.statics.iter().copied().collect();
+				(function.id, Rc::new(RefCell::new(statics)))
+			})
+			.collect(),
+		calls: HashMap::new(),
+	};
+	let value = interpreter.call(id, arguments.to_vec())?;
+	Ok((value, interpreter.calls))
+}
+
+struct Interpreter<'a> {
+	functions: HashMap<usize, &'a Function>,
+	/// Elements of every live array
+	memory: Vec<i32>,
+	/// Values of the `Ident::Static`s of every function, shared by its frames
+	statics: HashMap<usize, Statics>,
+	/// Number of frames created for every function
+	calls: HashMap<usize, usize>,
+}
+
+type Statics = Rc<RefCell<HashMap<Ident, i32>>>;
+
+/// State of a function call in progress
+#[derive(Debug)]
+struct Frame<'a> {
+	function: &'a Function,
+	/// Index of the instruction being executed
+	pc: usize,
+	/// Length of `memory` on entry, arrays past it are freed on return
+	memory_start: usize,
+	arguments: Vec<i32>,
+	idents: HashMap<Ident, i32>,
+	statics: Statics,
+	temporaries: HashMap<usize, i32>,
+	/// Base address of every array allocated by the function
+	arrays: HashMap<Ident, usize>,
+	/// Arguments pushed for the upcoming call
+	pushed: Vec<i32>,
+}
+impl Frame<'_> {
+	/// Arrays evaluate to their base address
+	fn read(&self, operand: Operand) -> Result<i32, RuntimeError> {
+		Ok(match operand {
+			Operand::Ident(Ident::Parameter(index)) => *self
+				.arguments
+				.get(index)
+				.ok_or(RuntimeError::MissingArgument(index))?,
+			Operand::Ident(ident @ Ident::Static(..)) => self.statics.borrow()[&ident],
+			Operand::Ident(ident) => match self.arrays.get(&ident) {
+				Some(&base) => base as i32,
+				None => self.idents.get(&ident).copied().unwrap_or_default(),
+			},
+			Operand::Temporary(id) => self.temporaries.get(&id).copied().unwrap_or_default(),
+			Operand::Immediate(value) => value,
+		})
+	}
+	fn write(&mut self, operand: Operand, value: i32) -> Result<(), RuntimeError> {
+		match operand {
+			Operand::Ident(Ident::Parameter(index)) => {
+				*self
+					.arguments
+					.get_mut(index)
+					.ok_or(RuntimeError::MissingArgument(index))? = value
+			}
+			Operand::Ident(ident @ Ident::Static(..)) => {
+				self.statics.borrow_mut().insert(ident, value);
+			}
+			Operand::Ident(ident) => {
+				self.idents.insert(ident, value);
+			}
+			Operand::Temporary(id) => {
+				self.temporaries.insert(id, value);
+			}
+			Operand::Immediate(_) => unreachable!("Immediates are never assigned to"),
+		}
+		Ok(())
+	}
+}
+
+impl<'a> Interpreter<'a> {
+	fn frame(&mut self, id: usize, arguments: Vec<i32>) -> Result<Frame<'a>, RuntimeError> {
+		let function = self
+			.functions
+			.get(&id)
+			.ok_or(RuntimeError::UndefinedFunction(id))?;
+		*self.calls.entry(id).or_default() += 1;
+		Ok(Frame {
+			function,
+			pc: 0,
+			memory_start: self.memory.len(),
+			arguments,
+			idents: HashMap::new(),
+			statics: self.statics[&id].clone(),
+			temporaries: HashMap::new(),
+			arrays: HashMap::new(),
+			pushed: Vec::new(),
+		})
+	}
+	/// Calls are tracked on an explicit stack of `Frame`s, so deep recursion
+	/// in the program doesn't overflow the native stack
+	fn call(&mut self, id: usize, arguments: Vec<i32>) -> Result<i32, RuntimeError> {
+		let mut stack = vec![self.frame(id, arguments)?];
+		loop {
+			let frame = stack.last_mut().unwrap();
+			let instruction = frame.function.instructions.get(frame.pc).copied();
+			let returned = match instruction {
+				None => Some(0),
+				Some(Instruction::Return(op)) => Some(frame.read(op)?),
+				Some(Instruction::Expression(_, RValue::FuncCall(id, arg_count))) => {
+					// Pushed in reverse, so the last push is the first argument
+					let first = frame.pushed.len() - arg_count;
+					let arguments = frame.pushed.split_off(first).into_iter().rev();
+					if stack.len() == MAX_CALL_DEPTH {
+						return Err(RuntimeError::StackOverflow);
+					}
+					stack.push(self.frame(id, arguments.collect())?);
+					continue;
+				}
+				Some(instruction) => {
+					self.execute(frame, instruction)?;
+					None
+				}
+			};
+			if let Some(value) = returned {
+				// Arrays only outlive their function through the callees
+				let callee = stack.pop().unwrap();
+				self.memory.truncate(callee.memory_start);
+				let Some(caller) = stack.last_mut() else {
+					return Ok(value);
+				};
+				let Instruction::Expression(lhs, _) = caller.function.instructions[caller.pc]
+				else {
+					unreachable!("Only `FuncCall` pushes a frame")
+				};
+				caller.write(lhs, value)?;
+				caller.pc += 1;
+			}
+		}
+	}
+	/// Executes an instruction other than `Return` and `FuncCall`, advancing
+	/// the `pc`
+	fn execute(&mut self, frame: &mut Frame, instruction: Instruction) -> Result<(), RuntimeError> {
+		match instruction {
+			Instruction::ArrayAlloc(ident, size) => {
+				frame.arrays.insert(ident, self.memory.len());
+				self.memory.resize(self.memory.len() + size as usize, 0);
+			}
+			Instruction::ArrayWrite(ident, index, value) => {
+				let address = self.address(frame, ident, index)?;
+				self.memory[address] = frame.read(value)?;
+			}
+			Instruction::Ifz(op, offset) => {
+				if frame.read(op)? == 0 {
+					frame.pc += offset;
+					return Ok(());
+				}
+			}
+			Instruction::Goto(offset) => {
+				frame.pc = (frame.pc as isize + offset) as usize;
+				return Ok(());
+			}
+			Instruction::Push(op) => {
+				let value = frame.read(op)?;
+				frame.pushed.push(value);
+			}
+			Instruction::Expression(lhs, r_value) => {
+				let value = match r_value {
+					RValue::Assignment(op) => frame.read(op)?,
+					RValue::Operation(l_op, operation, r_op) => {
+						let (lhs, rhs) = (frame.read(l_op)?, frame.read(r_op)?);
+						operation
+							.evaluate(lhs, rhs)
+							.ok_or(RuntimeError::DivisionByZero)?
+					}
+					RValue::ArrayAccess(ident, index) => {
+						self.memory[self.address(frame, ident, index)?]
+					}
+					RValue::BitwiseNot(op) => !frame.read(op)?,
+					RValue::Negate(op) => frame.read(op)?.wrapping_neg(),
+					RValue::FuncCall(..) => unreachable!("Calls are handled by `call`"),
+				};
+				frame.write(lhs, value)?;
+			}
+			Instruction::Return(_) => unreachable!("Returns are handled by `call`"),
+		}
+		frame.pc += 1;
+		Ok(())
+	}
+	/// Index into `memory` of the array element
+	fn address(&self, frame: &Frame, array: Ident, index: Operand) -> Result<usize, RuntimeError> {
+		let base = frame.read(Operand::Ident(array))? as usize;
+		let index = frame.read(index)?;
+		let address = base as isize + index as isize;
+		if index < 0 || address as usize >= self.memory.len() {
+			return Err(RuntimeError::OutOfBounds(base, index));
+		}
+		Ok(address as usize)
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate};
+
+	#[allow(dead_code)]
+	fn run_source(source: &str, arguments: &[i32]) -> Result<i32, RuntimeError> {
+		let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let main = table.0.iter().position(|name| name == "main").unwrap();
+		run(&functions, main, arguments)
+	}
+
+	#[test]
+	fn argument() {
+		assert_eq!(Ok(5), run_source("int main(int n) { return n; }", &[5]));
+		assert_eq!(
+			Err(RuntimeError::MissingArgument(0)),
+			run_source("int main(int n) { return n; }", &[])
+		);
+	}
+
+	#[test]
+	fn recursion_and_arrays() {
+		let test_program = r"
+			int fibb(int n) {
+				if (n < 2) {
+					return n;
+				}
+				int a, b;
+				a = n - 1;
+				a = fibb(a);
+				b = n - 2;
+				b = fibb(b);
+				return a + b;
+			}
+			int main(int n) {
+				int a[3];
+				a[2] = fibb(n);
+				return a[2];
+			}
+		";
+		assert_eq!(Ok(55), run_source(test_program, &[10]));
+	}
+
+	#[test]
+	fn array_operands() {
+		let test_program = r"
+			int main(int i, int j) {
+				int a[4], b[4];
+				b[j] = 7;
+				a[i + 1] = b[j] * 2;
+				i += 1;
+				return a[i] + b[j];
+			}
+		";
+		assert_eq!(Ok(21), run_source(test_program, &[1, 3]));
+	}
+
+	#[test]
+	fn out_parameter() {
+		let test_program = r"
+			int divmod(int a, int b, int out[]) {
+				out[0] = a / b;
+				out[1] = a % b;
+				return 0;
+			}
+			int main(int a, int b) {
+				int res[2], q;
+				divmod(a, b, res);
+				q = res[0] * 10;
+				return q + res[1];
+			}
+		";
+		assert_eq!(Ok(32), run_source(test_program, &[17, 5]));
+	}
+
+	#[test]
+	fn static_counter() {
+		let test_program = r"
+			int counter(int step) {
+				static int count = 10;
+				count = count + step;
+				return count;
+			}
+			int main() {
+				int a;
+				a = counter(1);
+				a = counter(2);
+				return counter(3);
+			}
+		";
+		assert_eq!(Ok(16), run_source(test_program, &[]));
+	}
+
+	#[test]
+	fn argument_order() {
+		let test_program = r"
+			int sub(int a, int b, int c) {
+				a = a - b;
+				return a - c;
+			}
+			int main(int i, int j) {
+				int a[4];
+				a[0] = 10;
+				a[1] = 3;
+				a[2] = 1;
+				return sub(a[i], a[j], -i);
+			}
+		";
+		assert_eq!(Ok(7), run_source(test_program, &[0, 1]));
+		assert_eq!(Ok(3), run_source(test_program, &[1, 2]));
+		// The leftmost argument is evaluated first and faults first
+		assert!(matches!(
+			run_source(test_program, &[5, 7]),
+			Err(RuntimeError::OutOfBounds(_, 5))
+		));
+	}
+
+	#[test]
+	fn parallel_arrays() {
+		let test_program = r"
+			void update(int xs[], int ys[], int zs[], int n) {
+				int i = 0;
+				while (i < n) {
+					xs[i] = ys[i] + zs[i];
+					ys[i] = xs[i] * zs[i];
+					i = i + 1;
+				}
+			}
+			int main(int n) {
+				int xs[4], ys[4], zs[4], i, s;
+				i = 0;
+				while (i < n) {
+					ys[i] = i;
+					zs[i] = i + 2;
+					i = i + 1;
+				}
+				update(xs, ys, zs, n);
+				update(zs, xs, ys, n);
+				s = 0;
+				i = 0;
+				while (i < n) {
+					s = s + xs[i] + ys[i] - zs[i] / 2;
+					i = i + 1;
+				}
+				return s;
+			}
+		";
+		assert_eq!(Ok(2886), run_source(test_program, &[4]));
+		assert_eq!(Ok(25), run_source(test_program, &[1]));
+	}
+
+	#[test]
+	fn unary() {
+		let test_program = r"
+			int main(int a, int b) {
+				int r;
+				r = -(a + b) * 2;
+				r = r + !(a < b) + ~a * -b;
+				return r - -a + !!b - ~(a - b);
+			}
+		";
+		assert_eq!(Ok(12), run_source(test_program, &[5, 3]));
+		assert_eq!(Ok(7), run_source(test_program, &[3, 5]));
+	}
+
+	#[test]
+	fn ternary() {
+		let test_program = r"
+			int main(int i) {
+				int a[2], n;
+				a[0] = 4;
+				a[1] = 5;
+				n = i < 2 ? a[i] : -i;
+				while (n < 9 ? 1 : 0) {
+					n = n + 1;
+					continue;
+				}
+				return n + i;
+			}
+		";
+		assert_eq!(Ok(10), run_source(test_program, &[1]));
+		// The element past the end is never read
+		assert_eq!(Ok(16), run_source(test_program, &[7]));
+	}
+
+	#[test]
+	fn call_counts() {
+		let test_program = r"
+			int twice(int n) { return n * 2; }
+			int unused() { return 0; }
+			int main(int n) {
+				int i;
+				i = 0;
+				while (i < n) {
+					i = twice(i);
+					i = i + 1;
+				}
+				return i;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let id = |name: &str| table.0.iter().position(|n| n == name).unwrap();
+		let (value, calls) = run_profiled(&functions, id("main"), &[10]).unwrap();
+		assert_eq!(15, value);
+		assert_eq!(HashMap::from([(id("main"), 1), (id("twice"), 4)]), calls);
+	}
+
+	#[test]
+	fn many_parameters() {
+		let test_program = r"
+			int weigh(int a, int b, int c, int d, int e, int f, int g, int h) {
+				a = a * 10;
+				a = a + b;
+				a = a * 10;
+				a = a + c;
+				a = a * 10;
+				a = a + d;
+				a = a * 10;
+				a = a + e;
+				a = a * 10;
+				a = a + f;
+				a = a * 10;
+				a = a + g;
+				a = a * 10;
+				return a + h;
+			}
+			int main(int n) {
+				return weigh(1, 2, 3, 4, 5, 6, 7, n);
+			}
+		";
+		assert_eq!(Ok(12345678), run_source(test_program, &[8]));
+	}
+
+	#[test]
+	fn truthiness() {
+		assert_eq!(Ok(1), run_source("int main() { return !0; }", &[]));
+		assert_eq!(Ok(0), run_source("int main() { return !5; }", &[]));
+		let test_program = "int main(int n) { return !n; }";
+		assert_eq!(Ok(1), run_source(test_program, &[0]));
+		assert_eq!(Ok(0), run_source(test_program, &[5]));
+		assert_eq!(Ok(0), run_source(test_program, &[-1]));
+		let test_program = "int main() { if (-1) { return 1; } return 0; }";
+		assert_eq!(Ok(1), run_source(test_program, &[]));
+		let test_program = "int main(int n) { if (n) { return 1; } return 0; }";
+		assert_eq!(Ok(1), run_source(test_program, &[-1]));
+		assert_eq!(Ok(0), run_source(test_program, &[0]));
+		let test_program = "int main(int a, int b) { return a < b; }";
+		assert_eq!(Ok(1), run_source(test_program, &[-3, 7]));
+		assert_eq!(Ok(0), run_source(test_program, &[7, -3]));
+	}
+
+	#[test]
+	fn bitwise() {
+		let test_program = r"
+			int main(int a, int b) {
+				int r;
+				r = a & b;
+				return r;
+			}
+		";
+		let cases = [
+			("&", 0xF0, 0x3C, 0x30),
+			("&", -16, 0xFF, 0xF0),
+			("&", i32::MIN, -1, i32::MIN),
+			("|", 0xF0, 0x0F, 0xFF),
+			("|", -256, 0xFF, -1),
+			("^", 0xF0, 0x0F, 0xFF),
+			("^", -1, 0x0F, -16),
+			("^", 0x5A, 0x5A, 0),
+		];
+		for (operation, a, b, expected) in cases {
+			let test_program = test_program.replace('&', operation);
+			assert_eq!(
+				Ok(expected),
+				run_source(&test_program, &[a, b]),
+				"{a} {operation} {b}"
+			);
+		}
+		let test_program = "int main(int a) { int r; r = ~a; return r; }";
+		assert_eq!(Ok(-1), run_source(test_program, &[0]));
+		assert_eq!(Ok(0), run_source(test_program, &[-1]));
+		assert_eq!(Ok(i32::MIN), run_source(test_program, &[i32::MAX]));
+		assert_eq!(Ok(-0xF1), run_source(test_program, &[0xF0]));
+	}
+
+	#[test]
+	fn do_while() {
+		let test_program = r"
+			int main(int n) {
+				int runs;
+				runs = 0;
+				do {
+					runs += 1;
+					n -= 1;
+				} while (n > 0);
+				return runs;
+			}
+		";
+		assert_eq!(Ok(1), run_source(test_program, &[0]));
+		assert_eq!(Ok(3), run_source(test_program, &[3]));
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(
+			Err(RuntimeError::DivisionByZero),
+			run_source("int main(int n) { return 1 / n; }", &[0])
+		);
+		assert_eq!(
+			Err(RuntimeError::OutOfBounds(0, 2)),
+			run_source("int main(int n) { int a[2]; a[n] = 1; return 0; }", &[2])
+		);
+		assert_eq!(
+			Err(RuntimeError::StackOverflow),
+			run_source("int main(int n) { return main(n); }", &[0])
+		);
+	}
+}
@@ -1,6 +1,10 @@
 //! Lexical Analyzer
 //!
-//! Call the `lexer::tokenize` function with the input source code as `&str`
+//! Call the `lexer::tokenize` function with the input source code as `&str`,
+//! or `lexer::tokenize_with_options` to toggle the lexer's `LexerOptions`
+use std::{cell::Cell, collections::HashMap, ops::Range};
+
+pub mod diff;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Token {
@@ -63,6 +67,7 @@ pub enum Token {
 	Question,
 	Colon,
 	Comma,
+	Ellipsis,
 
 	Eof,
 }
@@ -76,67 +81,207 @@ impl Symbol {
 	}
 }
 
-#[derive(Clone, Debug, PartialEq, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct SymbolTable {
 	pub identifier: Vec<String>,
 	pub consts: Vec<String>,
 	pub literal: Vec<String>,
+	/// Index of every entry of the tables above, interning in constant time
+	identifier_index: HashMap<String, usize>,
+	consts_index: HashMap<String, usize>,
+	literal_index: HashMap<String, usize>,
 }
 impl SymbolTable {
 	fn add_identifier(&mut self, identifier: String) -> usize {
-		self.identifier
-			.iter()
-			.position(|i| **i == identifier)
-			.unwrap_or_else(|| {
-				self.identifier.push(identifier);
-				self.identifier.len() - 1
-			})
+		intern(&mut self.identifier, &mut self.identifier_index, identifier)
 	}
 	fn add_consts(&mut self, consts: String) -> usize {
-		self.consts
-			.iter()
-			.position(|i| **i == consts)
-			.unwrap_or_else(|| {
-				self.consts.push(consts);
-				self.consts.len() - 1
-			})
+		intern(&mut self.consts, &mut self.consts_index, consts)
 	}
 	fn add_literal(&mut self, literal: String) -> usize {
-		self.literal
-			.iter()
-			.position(|i| **i == literal)
-			.unwrap_or_else(|| {
-				self.literal.push(literal);
-				self.literal.len() - 1
-			})
+		intern(&mut self.literal, &mut self.literal_index, literal)
 	}
 }
+/// The indices only mirror the tables
+impl PartialEq for SymbolTable {
+	fn eq(&self, other: &Self) -> bool {
+		self.identifier == other.identifier
+			&& self.consts == other.consts
+			&& self.literal == other.literal
+	}
+}
+
+/// Index of `entry` in `table`, appended to it when first seen
+fn intern(table: &mut Vec<String>, index: &mut HashMap<String, usize>, entry: String) -> usize {
+	*index.entry(entry).or_insert_with_key(|entry| {
+		table.push(entry.clone());
+		table.len() - 1
+	})
+}
 
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct LexerOutput {
 	pub symbol_table: SymbolTable,
 	pub symbol: Vec<Symbol>,
+	/// Byte offset of the start of every line in the source
+	pub line_starts: Vec<usize>,
+	/// Byte range in the source of every symbol, empty at the end of the
+	/// source for the `Eof`
+	pub spans: Vec<Range<usize>>,
+}
+impl LexerOutput {
+	/// Line and column, both starting at 1, of the byte `offset` into the
+	/// source. Columns are counted in bytes
+	pub fn position_of(&self, offset: usize) -> (usize, usize) {
+		let line = self.line_starts.partition_point(|&start| start <= offset);
+		(line, offset - self.line_starts[line - 1] + 1)
+	}
+	/// Every symbol on its own line along with its span, from the first to
+	/// the last character, and text:
+	/// ```text
+	/// [1:1-1:3] Keyword(Int) "int"
+	/// [1:5-1:8] Identifier(0) "main"
+	/// ```
+	/// Identifiers, constants and literals are resolved through the symbol
+	/// table, literals without their quotes, the rest is cut from `source`
+	pub fn annotated(&self, source: &str) -> String {
+		let mut res = String::new();
+		for (Symbol(token, _), span) in self.symbol.iter().zip(&self.spans) {
+			let text = match *token {
+				Token::Identifier(index) => &self.symbol_table.identifier[index],
+				Token::Const(index) => &self.symbol_table.consts[index],
+				Token::Literal(index) => &self.symbol_table.literal[index],
+				_ => &source[span.clone()],
+			};
+			let (line, col) = self.position_of(span.start);
+			let (end_line, end_col) = self.position_of(span.end.max(span.start + 1) - 1);
+			res += &format!("[{line}:{col}-{end_line}:{end_col}] {token:?} {text:?}\n");
+		}
+		res
+	}
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Reserved {
 	If,
+	Else,
 	Int,
 	Return,
 	While,
+	Do,
+	For,
 	Break,
 	Continue,
+	Void,
+	Static,
+	Inline,
+	NoInline,
 }
 
-pub fn tokenize(input_stream: &str) -> LexerOutput {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LexError {
+	/// Tuple struct of the line number and the offending numeric literal
+	MalformedNumber(usize, String),
+}
+
+/// Suffix of an integer literal, such as the `ul` in `10ul`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegerSuffix {
+	None,
+	Unsigned,
+	Long,
+	UnsignedLong,
+	LongLong,
+	UnsignedLongLong,
+}
+impl IntegerSuffix {
+	/// Splits a numeric literal into its digits and suffix, `None` on an
+	/// unrecognized suffix
+	pub fn split(literal: &str) -> Option<(&str, Self)> {
+		let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+		let suffix = match literal[digits.len()..].to_ascii_lowercase().as_str() {
+			"" => Self::None,
+			"u" => Self::Unsigned,
+			"l" => Self::Long,
+			"ul" | "lu" => Self::UnsignedLong,
+			"ll" => Self::LongLong,
+			"ull" | "llu" => Self::UnsignedLongLong,
+			_ => return None,
+		};
+		Some((digits, suffix))
+	}
+}
+
+/// Whether the literal is a decimal, or a `0b`, `0o` or `0x` prefixed
+/// number with only digits of that radix, followed by an optional suffix
+fn is_valid_number(literal: &str) -> bool {
+	let Some((digits, _)) = IntegerSuffix::split(literal) else {
+		return false;
+	};
+	let (radix, digits) = match digits.trim_start_matches('0').chars().next() {
+		Some('b') => (2, &digits[digits.find('b').unwrap() + 1..]),
+		Some('o') => (8, &digits[digits.find('o').unwrap() + 1..]),
+		Some('x') => (16, &digits[digits.find('x').unwrap() + 1..]),
+		_ => (10, digits),
+	};
+	!digits.is_empty() && digits.chars().all(|char| char.is_digit(radix))
+}
+
+/// Toggles for the lexical extensions to C
+#[derive(Clone, Copy, Debug)]
+pub struct LexerOptions {
+	/// `//` comments, only standard since C99. On by default
+	pub allow_line_comments: bool,
+	/// `/* */` comments nest, so `/* a /* b */ c */` is a single comment. Off
+	/// by default
+	pub nested_comments: bool,
+}
+impl Default for LexerOptions {
+	fn default() -> Self {
+		Self {
+			allow_line_comments: true,
+			nested_comments: false,
+		}
+	}
+}
+impl LexerOptions {
+	/// Only the comments of C89, every extension rejected
+	pub fn pedantic() -> Self {
+		Self {
+			allow_line_comments: false,
+			nested_comments: false,
+		}
+	}
+}
+
+pub fn tokenize(input_stream: &str) -> Result<LexerOutput, LexError> {
+	tokenize_with_options(input_stream, LexerOptions::default())
+}
+
+/// Same as `tokenize`, with the lexical extensions toggled by `options`
+pub fn tokenize_with_options(
+	input_stream: &str,
+	options: LexerOptions,
+) -> Result<LexerOutput, LexError> {
 	let LexerOutput {
 		mut symbol_table,
 		mut symbol,
+		..
 	} = LexerOutput::default();
+	let line_starts = std::iter::once(0)
+		.chain(input_stream.match_indices('\n').map(|(i, _)| i + 1))
+		.collect();
 	let is_identifier_symbol = |char: char| char.is_alphanumeric() || char == '_';
-	let mut stream_iter = input_stream.chars().peekable();
+	// Bytes pulled out of the source, peeked characters included
+	let consumed = Cell::new(0);
+	let mut stream_iter = input_stream
+		.chars()
+		.inspect(|char| consumed.set(consumed.get() + char.len_utf8()))
+		.peekable();
+	let mut spans = Vec::new();
 	let mut line_number = 1;
 	while let Some(current) = stream_iter.next() {
+		let start = consumed.get() - current.len_utf8();
 		if current == '\n' {
 			line_number += 1;
 		}
@@ -144,17 +289,26 @@ pub fn tokenize(input_stream: &str) -> LexerOutput {
 			continue;
 		}
 		// Handle line comments
-		if current == '/' && stream_iter.peek().is_some_and(|x| *x == '/') {
+		if options.allow_line_comments
+			&& current == '/'
+			&& stream_iter.peek().is_some_and(|x| *x == '/')
+		{
 			while stream_iter.next_if(|x| *x != '\n').is_some() {}
 			continue;
 		}
 		if current == '/' && stream_iter.next_if(|x| *x == '*').is_some() {
-			loop {
-				if let Some('*') = stream_iter.next()
-					&& Some(&'/') == stream_iter.peek()
-				{
-					stream_iter.next();
-					break;
+			let mut depth = 1;
+			while depth > 0 {
+				match stream_iter.next() {
+					Some('*') if stream_iter.next_if_eq(&'/').is_some() => depth -= 1,
+					Some('/')
+						if options.nested_comments && stream_iter.next_if_eq(&'*').is_some() =>
+					{
+						depth += 1
+					}
+					// Unterminated, the comment runs till the end of the input
+					None => break,
+					_ => (),
 				}
 			}
 			continue;
@@ -165,6 +319,9 @@ pub fn tokenize(input_stream: &str) -> LexerOutput {
 				while let Some(char) = stream_iter.next_if(|i| i.is_alphanumeric()) {
 					const_buffer.push(char);
 				}
+				if !is_valid_number(&const_buffer) {
+					return Err(LexError::MalformedNumber(line_number, const_buffer));
+				}
 				Token::Const(symbol_table.add_consts(const_buffer))
 			}
 			// TODO: The numeric check here is redundant, check if compiler has optimized it
@@ -180,6 +337,12 @@ pub fn tokenize(input_stream: &str) -> LexerOutput {
 				let mut literal_buffer = String::new();
 				while let Some(char) = stream_iter.next_if(|&i| i != '\"') {
 					literal_buffer.push(char);
+					// An escaped quote doesn't end the literal
+					if char == '\\'
+						&& let Some(escaped) = stream_iter.next()
+					{
+						literal_buffer.push(escaped);
+					}
 				}
 				stream_iter.next();
 				Token::Literal(symbol_table.add_literal(literal_buffer))
@@ -294,6 +457,11 @@ pub fn tokenize(input_stream: &str) -> LexerOutput {
 			':' => Token::Colon,
 			'~' => Token::Tilde,
 			',' => Token::Comma,
+			'.' if stream_iter.next_if_eq(&'.').is_some()
+				&& stream_iter.next_if_eq(&'.').is_some() =>
+			{
+				Token::Ellipsis
+			}
 			';' => Token::Semicolon,
 			'(' => Token::LeftParenthesis,
 			')' => Token::RightParenthesis,
@@ -304,22 +472,62 @@ pub fn tokenize(input_stream: &str) -> LexerOutput {
 			x => panic!("{x} at line#{line_number}"),
 		};
 		symbol.push(Symbol(matched_token, line_number));
+		let peeked = stream_iter.peek().map_or(0, |char| char.len_utf8());
+		let end = consumed.get() - peeked;
+		spans.push(start..end);
 	}
 	symbol.push(Symbol(Token::Eof, line_number));
-	LexerOutput {
+	spans.push(input_stream.len()..input_stream.len());
+	Ok(LexerOutput {
 		symbol_table,
 		symbol,
+		line_starts,
+		spans,
+	})
+}
+
+/// Bytes of a string literal as interned in `SymbolTable::literal`, with C's
+/// simple escape sequences resolved. Any other escaped character stands for
+/// itself, same as GAS does.
+pub fn unescape(literal: &str) -> Vec<u8> {
+	let mut res = Vec::with_capacity(literal.len());
+	let mut bytes = literal.bytes();
+	while let Some(byte) = bytes.next() {
+		if byte != b'\\' {
+			res.push(byte);
+			continue;
+		}
+		res.push(match bytes.next() {
+			Some(b'n') => b'\n',
+			Some(b't') => b'\t',
+			Some(b'r') => b'\r',
+			Some(b'0') => 0,
+			Some(b'a') => 0x07,
+			Some(b'b') => 0x08,
+			Some(b'f') => 0x0c,
+			Some(b'v') => 0x0b,
+			Some(escaped) => escaped,
+			None => b'\\',
+		});
 	}
+	res
 }
 
 fn keywords(id: &str) -> Option<Token> {
 	match id {
 		"if" => Some(Token::Keyword(Reserved::If)),
+		"else" => Some(Token::Keyword(Reserved::Else)),
 		"int" => Some(Token::Keyword(Reserved::Int)),
 		"return" => Some(Token::Keyword(Reserved::Return)),
 		"while" => Some(Token::Keyword(Reserved::While)),
+		"do" => Some(Token::Keyword(Reserved::Do)),
+		"for" => Some(Token::Keyword(Reserved::For)),
 		"break" => Some(Token::Keyword(Reserved::Break)),
 		"continue" => Some(Token::Keyword(Reserved::Continue)),
+		"void" => Some(Token::Keyword(Reserved::Void)),
+		"static" => Some(Token::Keyword(Reserved::Static)),
+		"inline" => Some(Token::Keyword(Reserved::Inline)),
+		"noinline" => Some(Token::Keyword(Reserved::NoInline)),
 		_ => None,
 	}
 }
@@ -328,24 +536,32 @@ mod test {
 	#[allow(unused_imports)]
 	use super::*;
 	#[test]
+	// Every source has a single span of the `Eof`
+	#[allow(clippy::single_range_in_vec_init)]
 	fn comments() {
 		assert_eq!(
 			LexerOutput {
 				symbol: vec![Symbol(Token::Eof, 1)],
+				line_starts: vec![0],
+				spans: vec![0..0],
 				..Default::default()
 			},
-			tokenize("")
+			tokenize("").unwrap()
 		);
 		assert_eq!(
 			LexerOutput {
 				symbol: vec![Symbol(Token::Eof, 1)],
+				line_starts: vec![0],
+				spans: vec![2..2],
 				..Default::default()
 			},
-			tokenize("//")
+			tokenize("//").unwrap()
 		);
 		assert_eq!(
 			LexerOutput {
 				symbol: vec![Symbol(Token::Eof, 3)],
+				line_starts: vec![0, 1, 8, 23, 30],
+				spans: vec![34..34],
 				..Default::default()
 			},
 			tokenize(
@@ -355,8 +571,46 @@ mod test {
 				*/
 				"
 			)
+			.unwrap()
 		);
 	}
+	#[test]
+	fn options() {
+		let tokens = |input, options| {
+			tokenize_with_options(input, options)
+				.unwrap()
+				.symbol
+				.into_iter()
+				.map(|Symbol(token, _)| token)
+				.collect::<Vec<_>>()
+		};
+		let input = "/* a /* b */ c */";
+		assert_eq!(
+			vec![Token::Identifier(0), Token::Star, Token::Slash, Token::Eof],
+			tokens(input, LexerOptions::default())
+		);
+		let options = LexerOptions {
+			nested_comments: true,
+			..Default::default()
+		};
+		assert_eq!(vec![Token::Eof], tokens(input, options));
+		let input = "a // b";
+		assert_eq!(
+			vec![Token::Identifier(0), Token::Eof],
+			tokens(input, LexerOptions::default())
+		);
+		assert_eq!(
+			vec![
+				Token::Identifier(0),
+				Token::Slash,
+				Token::Slash,
+				Token::Identifier(1),
+				Token::Eof
+			],
+			tokens(input, LexerOptions::pedantic())
+		);
+	}
+
 	#[test]
 	fn program() {
 		use Reserved::*;
@@ -425,7 +679,68 @@ mod test {
 					Symbol(Identifier(1), 16),
 					Symbol(Semicolon, 16),
 					Symbol(Eof, 17)
-				]
+				],
+				line_starts: vec![
+					0, 1, 38, 49, 61, 68, 75, 86, 98, 115, 126, 142, 166, 181, 193, 195, 210
+				],
+				spans: vec![
+					38..41,
+					42..47,
+					47..48,
+					49..52,
+					53..59,
+					59..60,
+					61..64,
+					65..66,
+					66..67,
+					68..69,
+					70..71,
+					72..73,
+					73..74,
+					75..80,
+					81..82,
+					83..84,
+					84..85,
+					86..92,
+					93..94,
+					95..96,
+					96..97,
+					98..103,
+					104..105,
+					105..106,
+					107..108,
+					109..111,
+					111..112,
+					113..114,
+					116..119,
+					120..124,
+					124..125,
+					127..131,
+					132..133,
+					134..140,
+					140..141,
+					143..149,
+					150..151,
+					152..157,
+					158..159,
+					160..164,
+					164..165,
+					167..172,
+					173..174,
+					175..179,
+					179..180,
+					182..183,
+					184..185,
+					186..187,
+					188..189,
+					190..191,
+					191..192,
+					193..194,
+					195..201,
+					202..208,
+					208..209,
+					210..210
+				],
 			},
 			tokenize(
 				r"
@@ -446,6 +761,103 @@ while (i < 10) {
 return second;
 ",
 			)
+			.unwrap()
+		);
+	}
+
+	#[test]
+	fn malformed_numbers() {
+		assert_eq!(
+			Err(LexError::MalformedNumber(2, "0xZ".to_string())),
+			tokenize("int a;\na = 0xZ;")
+		);
+		assert_eq!(
+			Err(LexError::MalformedNumber(1, "123abc".to_string())),
+			tokenize("a = 123abc;")
+		);
+		assert_eq!(
+			Err(LexError::MalformedNumber(1, "0b12".to_string())),
+			tokenize("0b12")
+		);
+		assert_eq!(
+			Err(LexError::MalformedNumber(1, "0x".to_string())),
+			tokenize("0x")
+		);
+		let LexerOutput { symbol_table, .. } = tokenize("a = 0x1F + 10ul + 0;").unwrap();
+		assert_eq!(vec!["0x1F", "10ul", "0"], symbol_table.consts);
+	}
+
+	#[test]
+	fn many_symbols() {
+		let count = 50_000;
+		let source: String = (0..count)
+			.map(|i| format!("x{i} = {i};\nf(\"s{i}\");\n"))
+			.chain(["x0 = 0;".to_string()])
+			.collect();
+		let LexerOutput {
+			symbol_table,
+			symbol,
+			..
+		} = tokenize(&source).unwrap();
+		assert_eq!(count + 1, symbol_table.identifier.len());
+		assert_eq!(count, symbol_table.consts.len());
+		assert_eq!(count, symbol_table.literal.len());
+		// `f` is interned right after `x0`
+		assert_eq!("x49999", symbol_table.identifier[count]);
+		// The trailing `x0 = 0;` reuses the first entries
+		assert_eq!(Token::Identifier(0), symbol[symbol.len() - 5].0);
+		assert_eq!(Token::Const(0), symbol[symbol.len() - 3].0);
+	}
+
+	#[test]
+	fn annotated() {
+		let source = "int main() {\n\treturn 0x1F; /* \"no\" */\n}";
+		assert_eq!(
+			concat!(
+				"[1:1-1:3] Keyword(Int) \"int\"\n",
+				"[1:5-1:8] Identifier(0) \"main\"\n",
+				"[1:9-1:9] LeftParenthesis \"(\"\n",
+				"[1:10-1:10] RightParenthesis \")\"\n",
+				"[1:12-1:12] LeftBrace \"{\"\n",
+				"[2:2-2:7] Keyword(Return) \"return\"\n",
+				"[2:9-2:12] Const(0) \"0x1F\"\n",
+				"[2:13-2:13] Semicolon \";\"\n",
+				"[3:1-3:1] RightBrace \"}\"\n",
+				"[3:2-3:2] Eof \"\"\n",
+			),
+			tokenize(source).unwrap().annotated(source)
 		);
+		let source = "a = \"hi\";";
+		let annotated = tokenize(source).unwrap().annotated(source);
+		assert_eq!(
+			Some("[1:5-1:8] Literal(0) \"hi\""),
+			annotated.lines().nth(2)
+		);
+	}
+
+	#[test]
+	fn unescape() {
+		assert_eq!(b"hi".to_vec(), super::unescape("hi"));
+		assert_eq!(b"a\nb\t\\\0".to_vec(), super::unescape(r"a\nb\t\\\0"));
+		assert_eq!(b"?'".to_vec(), super::unescape(r"\?\'"));
+		// Escaped quotes stay within the literal
+		let LexerOutput { symbol_table, .. } = tokenize(r#"s = "a\"b\\";"#).unwrap();
+		assert_eq!(vec![r#"a\"b\\"#.to_string()], symbol_table.literal);
+		assert_eq!(
+			b"a\"b\\".to_vec(),
+			super::unescape(&symbol_table.literal[0])
+		);
+	}
+
+	#[test]
+	fn position_of() {
+		let source = "int a;\nint bc;\n\nbc = a;";
+		let lexer_output = tokenize(source).unwrap();
+		assert_eq!(vec![0, 7, 15, 16], lexer_output.line_starts);
+		assert_eq!((1, 1), lexer_output.position_of(0));
+		let offset = source.find("bc;").unwrap();
+		assert_eq!((2, 5), lexer_output.position_of(offset));
+		assert_eq!((3, 1), lexer_output.position_of(15));
+		assert_eq!((4, 6), lexer_output.position_of(source.rfind('a').unwrap()));
 	}
 }
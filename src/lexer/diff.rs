@@ -0,0 +1,117 @@
+//! Differences between two token streams
+//!
+//! Meant for checking what a change to the lexer does to the tokens of a
+//! corpus, every token only present in one of the streams is reported along
+//! with its line:
+//! ```text
+//! - 3 Plus
+//! + 3 Minus
+//! ```
+use std::fmt;
+
+use super::{LexerOutput, Symbol, Token};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenEdit {
+	/// Token and its line in the new stream
+	Inserted(String, usize),
+	/// Token and its line in the old stream
+	Deleted(String, usize),
+}
+impl fmt::Display for TokenEdit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Inserted(token, line) => write!(f, "+ {line} {token}"),
+			Self::Deleted(token, line) => write!(f, "- {line} {token}"),
+		}
+	}
+}
+
+/// Smallest set of insertions and deletions turning `old` into `new`, in
+/// order of the streams, deletions first where both touch the same spot.
+/// Tokens are compared by their text rather than their symbol table index,
+/// which differ between the streams as soon as an identifier does
+pub fn diff(old: &LexerOutput, new: &LexerOutput) -> Vec<TokenEdit> {
+	let (old_tokens, new_tokens) = (resolved(old), resolved(new));
+	let (n, m) = (old_tokens.len(), new_tokens.len());
+	// Length of the longest common subsequence of the suffixes
+	let mut common = vec![vec![0; m + 1]; n + 1];
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			common[i][j] = match old_tokens[i] == new_tokens[j] {
+				true => common[i + 1][j + 1] + 1,
+				false => common[i + 1][j].max(common[i][j + 1]),
+			};
+		}
+	}
+	let mut res = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < n || j < m {
+		if i < n && j < m && old_tokens[i] == new_tokens[j] {
+			i += 1;
+			j += 1;
+		} else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+			res.push(TokenEdit::Deleted(old_tokens[i].clone(), old.symbol[i].1));
+			i += 1;
+		} else {
+			res.push(TokenEdit::Inserted(new_tokens[j].clone(), new.symbol[j].1));
+			j += 1;
+		}
+	}
+	res
+}
+
+/// Every token with identifiers, constants and literals replaced by their text
+fn resolved(output: &LexerOutput) -> Vec<String> {
+	let table = &output.symbol_table;
+	output
+		.symbol
+		.iter()
+		.map(|Symbol(token, _)| match *token {
+			Token::Identifier(index) => format!("Identifier({:?})", table.identifier[index]),
+			Token::Const(index) => format!("Const({})", table.consts[index]),
+			Token::Literal(index) => format!("Literal({:?})", table.literal[index]),
+			token => format!("{token:?}"),
+		})
+		.collect()
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::lexer::tokenize;
+
+	#[test]
+	fn single_operator() {
+		let old = tokenize("int main() {\n\tint x = 1;\n\treturn x + 2;\n}").unwrap();
+		let new = tokenize("int main() {\n\tint x = 1;\n\treturn x - 2;\n}").unwrap();
+		assert_eq!(
+			vec![
+				TokenEdit::Deleted("Plus".to_string(), 3),
+				TokenEdit::Inserted("Minus".to_string(), 3),
+			],
+			diff(&old, &new)
+		);
+		assert!(diff(&old, &old).is_empty());
+	}
+
+	#[test]
+	fn renamed_identifiers() {
+		// Compared by name, `y` taking the table index `x` had is a change
+		let old = tokenize("int f(int x, int y) { return y; }").unwrap();
+		let new = tokenize("int f(int y, int z) { return z; }").unwrap();
+		let edits: Vec<String> = diff(&old, &new).iter().map(ToString::to_string).collect();
+		assert_eq!(
+			vec![
+				"- 1 Identifier(\"x\")",
+				"+ 1 Identifier(\"y\")",
+				"- 1 Identifier(\"y\")",
+				"+ 1 Identifier(\"z\")",
+				"- 1 Identifier(\"y\")",
+				"+ 1 Identifier(\"z\")",
+			],
+			edits
+		);
+	}
+}
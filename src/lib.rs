@@ -0,0 +1,144 @@
+//! ezc, a compiler for a small subset of C
+//!
+//! The stages are ran in order: `preprocessor`, `lexer`, `parser`, `analyzer`,
+//! `tac_gen` and finally `x86_gen`, or `llvm_gen` for LLVM IR. `compile` runs
+//! them all in memory, see `main.rs` for the driver writing the output.
+//! `compile_cached` does the same reusing the assembly of unchanged functions,
+//! `parse_only` stops at the parse tree for tooling. All three fail with a
+//! `CompileError` locating the problem in the source.
+pub mod analyzer;
+pub mod cache;
+pub mod diagnostic;
+pub mod interpreter;
+pub mod lexer;
+pub mod listing;
+pub mod llvm_gen;
+pub mod parser;
+pub mod preprocessor;
+pub mod tac_gen;
+pub mod x86_gen;
+
+pub use diagnostic::CompileError;
+
+/// Compiles `source` to assembly in memory, running every stage with its
+/// default options and writing nothing to the filesystem or stderr. The error
+/// is the one `main` would abort with, warnings are dropped.
+pub fn compile(source: &str, config: &x86_gen::Config) -> Result<String, CompileError> {
+	let (parsed, ident_table) = front_end(source)?;
+	let functions = tac_gen::generate_named(&parsed, &ident_table);
+	Ok(x86_gen::x86_gen(functions, ident_table, config))
+}
+
+/// Same as `compile`, taking the assembly of every function whose
+/// `cache::structural_hash` is unchanged from `cache` and storing the others
+pub fn compile_cached(
+	source: &str,
+	config: &x86_gen::Config,
+	cache: &mut cache::Cache,
+) -> Result<String, CompileError> {
+	let (parsed, ident_table) = front_end(source)?;
+	let hashes: std::collections::HashMap<usize, u64> = parsed
+		.0
+		.iter()
+		.map(|func| {
+			let hash = cache::structural_hash(func, &ident_table);
+			(func.name().table_index, hash)
+		})
+		.collect();
+	let functions = tac_gen::generate_named(&parsed, &ident_table);
+	Ok(x86_gen::x86_gen_with(
+		functions,
+		ident_table,
+		config,
+		|function, static_locals, generate| {
+			let key = cache::key(
+				hashes[&function.id],
+				&function.externals,
+				config,
+				static_locals,
+			);
+			cache.get_or_generate(function.id, key, generate)
+		},
+	))
+}
+
+/// Every stage up to and including `analyzer`
+fn front_end(source: &str) -> Result<(parser::Program, parser::IdentNameTable), CompileError> {
+	let (parsed, ident_table) = parse_only(source)?;
+	analyzer::analyze(&parsed).map_err(|errors| CompileError::semantic(errors, &ident_table))?;
+	Ok((parsed, ident_table))
+}
+
+/// Parse tree of `source`, running the `preprocessor`, `lexer` and `parser`
+/// but not the `analyzer`. Programs that are syntactically valid but
+/// semantically wrong, calling an undefined function say, still parse.
+pub fn parse_only(source: &str) -> Result<(parser::Program, parser::IdentNameTable), CompileError> {
+	let source = preprocessor::preprocess(source).map_err(CompileError::preprocess)?;
+	let lexer_output =
+		lexer::tokenize(&source).map_err(|error| CompileError::lex(&source, error))?;
+	parser::parse(lexer_output.clone()).map_err(|error| CompileError::parse(&lexer_output, error))
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+
+	#[test]
+	fn parse_without_analysis() {
+		let source = "int main() {\n\tx = f(1);\n\treturn x;\n}";
+		let (parsed, table) = parse_only(source).unwrap();
+		assert_eq!(
+			"(func int main () (assign x (call f 1)) (return x))\n",
+			parser::sexpr::sexpr(&parsed, &table)
+		);
+		assert!(matches!(
+			compile(source, &Default::default()),
+			Err(CompileError::Semantic(..))
+		));
+		assert_eq!(
+			"2:10: error: unexpected end of input, expected ';'",
+			parse_only("int main() {\n\treturn 0")
+				.unwrap_err()
+				.to_string()
+		);
+	}
+
+	#[test]
+	fn error_location() {
+		let error = |source| compile(source, &Default::default()).unwrap_err();
+		let located = |error: &CompileError| {
+			let diagnostic = &error.diagnostics()[0];
+			(diagnostic.line, diagnostic.col)
+		};
+		let preprocess = error("int main() {\n#include <stdio.h>\n\treturn 0;\n}");
+		assert!(matches!(
+			preprocess,
+			CompileError::Preprocess(preprocessor::PreprocessError::UnknownDirective(2, _), _)
+		));
+		assert_eq!((2, None), located(&preprocess));
+		let lex = error("int main() {\n\treturn 0xZ;\n}");
+		assert!(matches!(lex, CompileError::Lex(..)));
+		assert_eq!("2:9: error: malformed number '0xZ'", lex.to_string());
+		let parse = error("int main() {\n\tint 5;\n}");
+		assert!(matches!(
+			parse,
+			CompileError::Parse(parser::ParseError::UnexpectedToken(..), _)
+		));
+		assert_eq!((2, Some(6)), located(&parse));
+		let semantic = error("int main() {\n\treturn 0;\n}\nint f() {\n\tbreak;\n}");
+		assert!(matches!(
+			&semantic,
+			CompileError::Semantic(errors, _)
+				if errors[..] == [analyzer::SemanticError::BreakOutsideLoop(5)]
+		));
+		assert_eq!("5: error: 'break' outside of a loop", semantic.to_string());
+		// Every semantic error is reported, not just the first
+		let semantic = error("int main() {\n\tcontinue;\n\treturn f();\n}\nint g() {\n\tbreak;\n}");
+		assert_eq!(
+			"2: error: 'continue' outside of a loop\n\
+			 3: error: call to undefined function 'f'\n\
+			 6: error: 'break' outside of a loop",
+			semantic.to_string()
+		);
+	}
+}
@@ -0,0 +1,112 @@
+//! Listing
+//!
+//! Call `listing::listing` with the preprocessed source and the final TAC to
+//! get a table correlating every source line with the TAC and x86 instructions
+//! generated for it:
+//! ```text
+//!    3 | x = n + 1;
+//!      | 0: x@0 = arg0 + 1           | mov %eax, DWORD PTR [%rbp + 16]
+//!      |                             | add %eax, 1
+//! ```
+use std::fmt::Write;
+
+use crate::{
+	parser::IdentNameTable,
+	tac_gen::Function,
+	x86_gen::{self, FunctionAsm},
+};
+
+/// Width of the TAC column
+const TAC_WIDTH: usize = 28;
+
+pub fn listing(
+	source: &str,
+	functions: &[Function],
+	ident_table: &IdentNameTable,
+	config: &x86_gen::Config,
+) -> String {
+	let source_lines: Vec<&str> = source.lines().collect();
+	let static_locals = x86_gen::statically_allocated(functions, config);
+	let mut res = String::new();
+	for function in functions {
+		let FunctionAsm {
+			prologue,
+			body,
+			epilogue,
+			..
+		} = x86_gen::function_gen(
+			function,
+			ident_table,
+			config,
+			static_locals.contains(&function.id),
+		);
+		res += prologue.as_str();
+		let mut previous_line = None;
+		for (i, (tac, asm_set)) in function.instructions.iter().zip(body).enumerate() {
+			let line = function.lines.get(i).copied();
+			if line != previous_line
+				&& let Some(line) = line
+			{
+				let text = source_lines.get(line - 1).map_or("", |text| text.trim());
+				let _ = writeln!(res, "{line:>4} | {text}");
+			}
+			previous_line = line;
+			let tac = format!("{i}: {}", tac.named(Some(&ident_table.0)));
+			let asm_set: Vec<String> = match config.syntax {
+				x86_gen::Syntax::Intel => asm_set,
+				x86_gen::Syntax::Att => {
+					asm_set.iter().map(|asm| x86_gen::att_syntax(asm)).collect()
+				}
+				x86_gen::Syntax::Nasm => {
+					asm_set.iter().map(|asm| x86_gen::nasm_lines(asm)).collect()
+				}
+			};
+			let mut asm_set = asm_set.iter();
+			let first = asm_set.next().map_or("", |asm| asm.as_str());
+			let _ = writeln!(res, "     | {tac:<TAC_WIDTH$} | {first}");
+			for asm in asm_set {
+				let _ = writeln!(res, "     | {:<TAC_WIDTH$} | {asm}", "");
+			}
+		}
+		res += epilogue.as_str();
+	}
+	res
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate_named};
+
+	#[test]
+	fn two_line_function() {
+		let source = r"
+int main(int n) {
+	int x;
+	x = n + 1;
+	return x;
+}
+";
+		let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+		let functions = generate_named(&parsed, &table);
+		let listing = listing(source, &functions, &table, &x86_gen::Config::default());
+		let lines: Vec<&str> = listing.lines().collect();
+		let assignment = lines
+			.iter()
+			.position(|&l| l == "   4 | x = n + 1;")
+			.unwrap();
+		let ret = lines.iter().position(|&l| l == "   5 | return x;").unwrap();
+		assert!(assignment < ret);
+		let assignment_rows = &lines[assignment + 1..ret];
+		assert!(assignment_rows[0].starts_with("     | 0: x@0 = arg0 + 1 "));
+		assert!(assignment_rows.iter().any(|l| l.ends_with("| add %eax, 1")));
+		let return_rows = &lines[ret + 1..];
+		assert!(return_rows[0].starts_with("     | 1: t0 = x@0 "));
+		assert!(return_rows
+			.iter()
+			.any(|l| l.starts_with("     | 2: return t0 ")));
+		assert!(return_rows.iter().any(|l| l.ends_with("| jmp END_0")));
+		assert!(!listing.contains("   3 |"));
+	}
+}
@@ -0,0 +1,506 @@
+//! LLVM IR backend
+//!
+//! Call `llvm_gen::llvm_gen` with the `Program` and its TAC to get textual
+//! LLVM IR, an alternative to `x86_gen` leaving optimization and code
+//! generation to the LLVM tools:
+//! ```text
+//! $ ezc --emit=llvm input.c && clang ezc.ll main.c
+//! ```
+//! Every local and temporary lives in an `alloca`, which `mem2reg` promotes to
+//! SSA values. A basic block `L{index}` starts at every TAC instruction jumped
+//! to. Pointers are spelled `i32*`, which LLVM releases before and after
+//! opaque pointers both accept.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::{
+	parser::{BinaryOperation, Func, IdentNameTable, Program, ReturnType},
+	tac_gen::{Function, Ident, Instruction, Operand, RValue},
+};
+
+pub fn llvm_gen(program: &Program, functions: &[Function], ident_table: &IdentNameTable) -> String {
+	let signatures: HashMap<usize, &Func> = program
+		.0
+		.iter()
+		.map(|func| (func.name().table_index, func))
+		.collect();
+	let mut res = String::new();
+	for function in functions {
+		for &(ident, value) in &function.statics {
+			let _ = writeln!(
+				res,
+				"{} = internal global i32 {value}",
+				static_name(function.id, ident, ident_table)
+			);
+		}
+	}
+	let mut literals: Vec<&(usize, Vec<u8>)> = functions
+		.iter()
+		.flat_map(|function| &function.literals)
+		.collect();
+	literals.sort_unstable_by_key(|(index, _)| *index);
+	literals.dedup_by_key(|(index, _)| *index);
+	for (index, bytes) in literals {
+		let string: String = bytes
+			.iter()
+			.map(|&byte| match byte {
+				b' '..=b'~' if byte != b'"' && byte != b'\\' => (byte as char).to_string(),
+				_ => format!("\\{byte:02X}"),
+			})
+			.collect();
+		let _ = writeln!(
+			res,
+			"@str.{index} = private unnamed_addr constant [{} x i8] c\"{string}\\00\"",
+			bytes.len() + 1
+		);
+	}
+	for func in program.0.iter().filter(|func| func.is_external()) {
+		let _ = writeln!(
+			res,
+			"declare {} @{}{}",
+			return_type(func.return_type()),
+			ident_table.0[func.name().table_index],
+			parameter_list(func)
+		);
+	}
+	for function in functions {
+		res.push('\n');
+		res += &FunctionGen {
+			function,
+			func: signatures[&function.id],
+			signatures: &signatures,
+			ident_table,
+			arrays: HashSet::new(),
+			pushed: Vec::new(),
+			values: 0,
+		}
+		.generate();
+	}
+	res
+}
+
+fn return_type(return_type: ReturnType) -> &'static str {
+	match return_type {
+		ReturnType::Int => "i32",
+		ReturnType::Void => "void",
+	}
+}
+
+/// Arrays are passed as the address of their first element
+fn parameter_type(is_array: bool) -> &'static str {
+	match is_array {
+		true => "i32*",
+		false => "i32",
+	}
+}
+
+/// Parenthesized types of an external function's parameters, a variadic
+/// one's ending in `...`
+fn parameter_list(func: &Func) -> String {
+	let mut parameters: Vec<&str> = func
+		.parameter()
+		.iter()
+		.map(|parameter| parameter_type(parameter.is_array))
+		.collect();
+	if func.is_variadic() {
+		parameters.push("...");
+	}
+	format!("({})", parameters.join(", "))
+}
+
+/// Global of a `static` variable, named after its function like in `x86_gen`
+fn static_name(function: usize, ident: Ident, ident_table: &IdentNameTable) -> String {
+	let Ident::Static(name_index, scope_id) = ident else {
+		unreachable!("Only statics are globals")
+	};
+	let name = |index: usize| ident_table.0[index].as_str();
+	format!("@{}.{}.{scope_id}", name(function), name(name_index))
+}
+
+struct FunctionGen<'a> {
+	function: &'a Function,
+	func: &'a Func,
+	signatures: &'a HashMap<usize, &'a Func>,
+	ident_table: &'a IdentNameTable,
+	/// Locals allocated by `ArrayAlloc`
+	arrays: HashSet<Ident>,
+	/// Type and value of the arguments pushed for the upcoming call
+	pushed: Vec<(&'static str, String)>,
+	/// Number of SSA values defined so far, naming the next one
+	values: usize,
+}
+impl FunctionGen<'_> {
+	fn generate(&mut self) -> String {
+		let instructions = &self.function.instructions;
+		let parameters: Vec<String> = self
+			.func
+			.parameter()
+			.iter()
+			.map(|parameter| {
+				format!(
+					"{} %arg.{}",
+					parameter_type(parameter.is_array),
+					self.name(parameter.name.table_index)
+				)
+			})
+			.collect();
+		let mut res = format!(
+			"define {} @{}({}) {{\nentry:\n",
+			return_type(self.func.return_type()),
+			self.name(self.function.id),
+			parameters.join(", ")
+		);
+		// Scalar parameters can be assigned to, so they get a slot as well
+		for parameter in self.func.parameter().iter().filter(|p| !p.is_array) {
+			let name = self.name(parameter.name.table_index);
+			let _ = writeln!(res, "\t%arg.{name}.addr = alloca i32");
+			let _ = writeln!(res, "\tstore i32 %arg.{name}, i32* %arg.{name}.addr");
+		}
+		let mut locals = Vec::new();
+		let mut temporaries = BTreeSet::new();
+		for mut instruction in instructions.iter().copied() {
+			if let Instruction::ArrayAlloc(ident, size) = instruction {
+				let slot = self.slot(ident);
+				let _ = writeln!(res, "\t{slot}.arr = alloca [{size} x i32]");
+				let _ = writeln!(
+					res,
+					"\t{slot} = getelementptr inbounds [{size} x i32], [{size} x i32]* {slot}.arr, i32 0, i32 0"
+				);
+				self.arrays.insert(ident);
+			}
+			for operand in instruction.operands_mut() {
+				match *operand {
+					Operand::Ident(ident @ Ident::Binded(..)) if !locals.contains(&ident) => {
+						locals.push(ident)
+					}
+					Operand::Temporary(id) => {
+						temporaries.insert(id);
+					}
+					_ => (),
+				}
+			}
+		}
+		for ident in locals
+			.into_iter()
+			.filter(|ident| !self.arrays.contains(ident))
+		{
+			let _ = writeln!(res, "\t{} = alloca i32", self.slot(ident));
+		}
+		for id in temporaries {
+			let _ = writeln!(res, "\t%t{id} = alloca i32");
+		}
+		res += "\tbr label %L0\n";
+
+		// Blocks start at the jump targets and after every terminator
+		let mut leaders = BTreeSet::from([0]);
+		for (i, instruction) in instructions.iter().enumerate() {
+			match instruction {
+				Instruction::Ifz(_, offset) => {
+					leaders.insert(i + offset);
+					leaders.insert(i + 1);
+				}
+				Instruction::Goto(offset) => {
+					leaders.insert((i as isize + offset) as usize);
+					leaders.insert(i + 1);
+				}
+				Instruction::Return(_) => {
+					leaders.insert(i + 1);
+				}
+				_ => (),
+			}
+		}
+		let mut terminated = true;
+		for (i, &instruction) in instructions.iter().enumerate() {
+			if leaders.contains(&i) {
+				if !terminated {
+					let _ = writeln!(res, "\tbr label %L{i}");
+				}
+				let _ = writeln!(res, "L{i}:");
+			}
+			terminated = matches!(
+				instruction,
+				Instruction::Ifz(..) | Instruction::Goto(_) | Instruction::Return(_)
+			);
+			for line in self.instruction(i, instruction) {
+				let _ = writeln!(res, "\t{line}");
+			}
+		}
+		if !terminated {
+			res += "\tunreachable\n";
+		}
+		res + "}\n"
+	}
+	fn name(&self, index: usize) -> &str {
+		self.ident_table.0[index].as_str()
+	}
+	/// Pointer to the storage of `ident`, the first element for arrays
+	fn slot(&self, ident: Ident) -> String {
+		match ident {
+			Ident::Binded(name_index, scope_id) => format!("%{}.{scope_id}", self.name(name_index)),
+			Ident::Parameter(index) => {
+				let parameter = self.func.parameter()[index];
+				let name = self.name(parameter.name.table_index);
+				match parameter.is_array {
+					true => format!("%arg.{name}"),
+					false => format!("%arg.{name}.addr"),
+				}
+			}
+			Ident::Static(..) => static_name(self.function.id, ident, self.ident_table),
+			Ident::Literal(_) => unreachable!("Literals are only pushed"),
+		}
+	}
+	fn is_array(&self, ident: Ident) -> bool {
+		match ident {
+			Ident::Parameter(index) => self.func.parameter()[index].is_array,
+			_ => self.arrays.contains(&ident),
+		}
+	}
+	fn value(&mut self) -> String {
+		self.values += 1;
+		format!("%v{}", self.values - 1)
+	}
+	/// Value of `operand`, pushing the instructions loading it onto `res`
+	fn read(&mut self, operand: Operand, res: &mut Vec<String>) -> String {
+		let slot = match operand {
+			Operand::Immediate(value) => return value.to_string(),
+			Operand::Temporary(id) => format!("%t{id}"),
+			Operand::Ident(ident) => self.slot(ident),
+		};
+		let value = self.value();
+		res.push(format!("{value} = load i32, i32* {slot}"));
+		value
+	}
+	fn write(&self, operand: Operand, value: &str) -> String {
+		let slot = match operand {
+			Operand::Temporary(id) => format!("%t{id}"),
+			Operand::Ident(ident) => self.slot(ident),
+			Operand::Immediate(_) => unreachable!("Immediates are never assigned to"),
+		};
+		format!("store i32 {value}, i32* {slot}")
+	}
+	/// Pointer to the element `index` of the array `ident`
+	fn element(&mut self, ident: Ident, index: Operand, res: &mut Vec<String>) -> String {
+		let index = self.read(index, res);
+		let element = self.value();
+		res.push(format!(
+			"{element} = getelementptr inbounds i32, i32* {}, i32 {index}",
+			self.slot(ident)
+		));
+		element
+	}
+	fn instruction(&mut self, i: usize, instruction: Instruction) -> Vec<String> {
+		let mut res = Vec::new();
+		match instruction {
+			// Allocated in the entry block
+			Instruction::ArrayAlloc(..) => (),
+			Instruction::ArrayWrite(ident, index, value) => {
+				let element = self.element(ident, index, &mut res);
+				let value = self.read(value, &mut res);
+				res.push(format!("store i32 {value}, i32* {element}"));
+			}
+			Instruction::Ifz(operand, offset) => {
+				let value = self.read(operand, &mut res);
+				let zero = self.value();
+				res.push(format!("{zero} = icmp eq i32 {value}, 0"));
+				res.push(format!(
+					"br i1 {zero}, label %L{}, label %L{}",
+					i + offset,
+					i + 1
+				));
+			}
+			Instruction::Goto(offset) => {
+				res.push(format!("br label %L{}", (i as isize + offset) as usize));
+			}
+			Instruction::Return(operand) => match self.func.return_type() {
+				ReturnType::Int => {
+					let value = self.read(operand, &mut res);
+					res.push(format!("ret i32 {value}"));
+				}
+				ReturnType::Void => res.push("ret void".to_string()),
+			},
+			// Passed as an `i32*` like arrays, external functions being the
+			// only ones taking literals
+			Instruction::Push(Operand::Ident(Ident::Literal(index))) => {
+				let (_, bytes) = self
+					.function
+					.literals
+					.iter()
+					.find(|&&(literal, _)| literal == index)
+					.unwrap();
+				let size = bytes.len() + 1;
+				self.pushed.push((
+					"i32*",
+					format!("bitcast ([{size} x i8]* @str.{index} to i32*)"),
+				));
+			}
+			Instruction::Push(Operand::Ident(ident)) if self.is_array(ident) => {
+				self.pushed.push(("i32*", self.slot(ident)));
+			}
+			Instruction::Push(operand) => {
+				let value = self.read(operand, &mut res);
+				self.pushed.push(("i32", value));
+			}
+			Instruction::Expression(lhs, r_value) => {
+				let value = match r_value {
+					RValue::FuncCall(id, arg_count) => {
+						// Pushed in reverse, so the last push is the first argument
+						let first = self.pushed.len() - arg_count;
+						let arguments: Vec<String> = self
+							.pushed
+							.split_off(first)
+							.into_iter()
+							.rev()
+							.map(|(ty, value)| format!("{ty} {value}"))
+							.collect();
+						let callee = format!("@{}({})", self.name(id), arguments.join(", "));
+						let func = self.signatures[&id];
+						let return_type = return_type(func.return_type());
+						// A variadic callee is called through its function type
+						let callee_type = match func.is_variadic() {
+							true => format!("{return_type} {}", parameter_list(func)),
+							false => return_type.to_string(),
+						};
+						match func.return_type() {
+							ReturnType::Int => {
+								let value = self.value();
+								res.push(format!("{value} = call {callee_type} {callee}"));
+								value
+							}
+							// The result of a `void` call is never read
+							ReturnType::Void => {
+								res.push(format!("call {callee_type} {callee}"));
+								return res;
+							}
+						}
+					}
+					RValue::Assignment(operand) => self.read(operand, &mut res),
+					RValue::Operation(lhs, operation, rhs) => {
+						let (lhs, mut rhs) = (self.read(lhs, &mut res), self.read(rhs, &mut res));
+						if matches!(
+							operation,
+							BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight
+						) {
+							// Shifting by 32 or more is poison in LLVM, masking
+							// the count keeps x86's semantics
+							let count = self.value();
+							res.push(format!("{count} = and i32 {rhs}, 31"));
+							rhs = count;
+						}
+						let value = self.value();
+						match arithmetic(operation) {
+							Some(op_code) => {
+								res.push(format!("{value} = {op_code} i32 {lhs}, {rhs}"))
+							}
+							None => {
+								let condition = comparison(operation);
+								let flag = self.value();
+								res.push(format!("{flag} = icmp {condition} i32 {lhs}, {rhs}"));
+								res.push(format!("{value} = zext i1 {flag} to i32"));
+							}
+						}
+						value
+					}
+					RValue::ArrayAccess(ident, index) => {
+						let element = self.element(ident, index, &mut res);
+						let value = self.value();
+						res.push(format!("{value} = load i32, i32* {element}"));
+						value
+					}
+					RValue::BitwiseNot(operand) => {
+						let operand = self.read(operand, &mut res);
+						let value = self.value();
+						res.push(format!("{value} = xor i32 {operand}, -1"));
+						value
+					}
+					RValue::Negate(operand) => {
+						let operand = self.read(operand, &mut res);
+						let value = self.value();
+						res.push(format!("{value} = sub i32 0, {operand}"));
+						value
+					}
+				};
+				res.push(self.write(lhs, &value));
+			}
+		}
+		res
+	}
+}
+
+fn arithmetic(operation: BinaryOperation) -> Option<&'static str> {
+	use BinaryOperation::*;
+	Some(match operation {
+		Add => "add",
+		Sub => "sub",
+		Mul => "mul",
+		Div => "sdiv",
+		Mod => "srem",
+		ShiftLeft => "shl",
+		ShiftRight => "ashr",
+		And => "and",
+		Or => "or",
+		Xor => "xor",
+		_ => return None,
+	})
+}
+
+/// `icmp` condition of a comparison
+fn comparison(operation: BinaryOperation) -> &'static str {
+	use BinaryOperation::*;
+	match operation {
+		Less => "slt",
+		LessEqual => "sle",
+		Greater => "sgt",
+		GreaterEqual => "sge",
+		Equal => "eq",
+		NotEqual => "ne",
+		_ => unreachable!("Not a comparison"),
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate};
+
+	#[test]
+	fn function() {
+		let test_program = r"
+			int ext(int a[], int n);
+			int main(int n) {
+				int a[2], x;
+				a[0] = n;
+				x = ext(a, n);
+				if (x < 4) {
+					x = ~x;
+				}
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let ir = llvm_gen(&parsed, &generate(&parsed, table.0.len()), &table);
+		assert!(ir.starts_with("declare i32 @ext(i32*, i32)\n"));
+		assert!(ir.contains("define i32 @main(i32 %arg.n) {\n"));
+		assert!(ir.contains("\t%a.0.arr = alloca [2 x i32]\n"));
+		assert!(ir.contains(" = call i32 @ext(i32* %a.0, i32 "));
+		assert!(ir.contains(" = icmp slt i32 "));
+		assert!(ir.contains(" = xor i32 "));
+		assert!(ir.contains("\tret i32 "));
+	}
+
+	#[test]
+	fn variadic() {
+		let test_program = r#"
+			int printf(int format[], ...);
+			int main(int n) {
+				printf("%d %d", n, 3);
+				return 0;
+			}
+		"#;
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let ir = llvm_gen(&parsed, &generate(&parsed, table.0.len()), &table);
+		assert!(ir.contains("declare i32 @printf(i32*, ...)\n"));
+		assert!(ir.contains(" = call i32 (i32*, ...) @printf(i32* bitcast ("));
+		assert!(ir.contains(", i32 3)\n"));
+	}
+}
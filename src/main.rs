@@ -1,40 +1,408 @@
-#![feature(let_chains, if_let_guard)]
+use ezc::{
+	analyzer,
+	diagnostic::{Diagnostic, DiagnosticFormat},
+	interpreter, lexer, listing, llvm_gen, parser, preprocessor, tac_gen, x86_gen, CompileError,
+};
 
-mod analyzer;
-mod lexer;
-mod parser;
-mod tac_gen;
-mod x86_gen;
+/// Command line flags
+#[derive(Debug, Default)]
+struct Args {
+	/// Source file, the bundled `src/test.c` when absent
+	input: Option<String>,
+	/// Path of the emitted artifact, `-` for stdout. `ezc.asm` or `ezc.ll`
+	/// when absent, stdout for the other kinds
+	output: Option<String>,
+	/// Run `tac_gen::verify` after every stage producing TAC, along with
+	/// `tac_gen::verify_structured` on the freshly generated TAC
+	verify: bool,
+	/// Evaluate operations on constants at compile time
+	fold: bool,
+	/// Inline calls to small leaf functions
+	inline: bool,
+	/// Drop unreachable statements with `parser::simplify` before lowering
+	simplify: bool,
+	/// Hoist loop invariant operations out of their loops
+	licm: bool,
+	/// Passes to run in order, overriding `fold`, `inline` and `licm`
+	passes: Option<Vec<tac_gen::pipeline::Pass>>,
+	/// Print `tac_gen::pass_stats::table` of the passes ran to stderr
+	print_pass_stats: bool,
+	/// Print a summary of the program and the effect of the passes to stderr
+	/// once the assembly is generated
+	stats: bool,
+	/// Comment the generated assembly with the TAC it originates from
+	annotate: bool,
+	trap: x86_gen::TrapStrategy,
+	/// Trap on division by zero at runtime
+	div_guard: bool,
+	emit: Emit,
+	lexer: lexer::LexerOptions,
+	parser: parser::ParserOptions,
+	analyzer: analyzer::AnalyzerOptions,
+	/// Interpret `main` instead of compiling, exiting with its result
+	run: bool,
+	/// Arguments to `main` for `--run`, one per `--arg N`
+	arguments: Vec<i32>,
+	/// Emit a `_start` calling `main`, for linking without libc
+	freestanding: bool,
+	/// Place the locals of non-recursive functions in `.bss`
+	static_locals: bool,
+	/// Embed the source path and hash in the `.comment` section
+	record_source: bool,
+	/// Fail on functions whose stack frame exceeds this many bytes
+	max_stack: Option<usize>,
+	/// Count the calls of every function, printed to stderr on exit with
+	/// `--run`
+	profile: bool,
+	syntax: x86_gen::Syntax,
+	error_format: DiagnosticFormat,
+}
+
+/// What the compiler outputs
+#[derive(Debug, Default, PartialEq, Eq)]
+enum Emit {
+	/// Assembly written to `ezc.asm`
+	#[default]
+	Asm,
+	/// `listing::listing` printed to stdout
+	Listing,
+	/// `tac_gen::call_graph::to_json` printed to stdout
+	CallGraph,
+	/// `tac_gen::call_graph::dependencies` printed to stdout
+	Deps,
+	/// `parser::sexpr::sexpr` printed to stdout, without semantic analysis
+	Sexpr,
+	/// `llvm_gen::llvm_gen` written to `ezc.ll`
+	Llvm,
+	/// `lexer::LexerOutput::annotated` printed to stdout, without parsing
+	TokensAnnotated,
+}
+impl Args {
+	fn parse(mut args: impl Iterator<Item = String>) -> Self {
+		let mut res = Self::default();
+		let mut pedantic = false;
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--verify" => res.verify = true,
+				"--fold" => res.fold = true,
+				"--inline" => res.inline = true,
+				"--licm" => res.licm = true,
+				"--simplify" => res.simplify = true,
+				"--print-pass-stats" => res.print_pass_stats = true,
+				"--stats" => res.stats = true,
+				"--annotate" => res.annotate = true,
+				"--div-guard" => res.div_guard = true,
+				"--pedantic" => pedantic = true,
+				"--allow-implicit-int" => res.parser.allow_implicit_int = true,
+				"--nested-comments" => res.lexer.nested_comments = true,
+				"--no-recursion" => res.analyzer.no_recursion = true,
+				"--run" => res.run = true,
+				"--freestanding" => res.freestanding = true,
+				"--record-source" => res.record_source = true,
+				"--profile" => res.profile = true,
+				"-Os" | "--static-locals" => res.static_locals = true,
+				"--arg" => {
+					let value = args.next().expect("Missing value after '--arg'");
+					let value = value
+						.parse()
+						.unwrap_or_else(|_| panic!("Invalid argument value: '{value}'"));
+					res.arguments.push(value);
+				}
+				"-o" => res.output = Some(args.next().expect("Missing path after '-o'")),
+				"--max-stack" => {
+					let value = args.next().expect("Missing value after '--max-stack'");
+					let value = value
+						.parse()
+						.unwrap_or_else(|_| panic!("Invalid stack budget: '{value}'"));
+					res.max_stack = Some(value);
+				}
+				_ if let Some(passes) = arg.strip_prefix("--passes=") => {
+					res.passes =
+						Some(tac_gen::pipeline::parse(passes).unwrap_or_else(|err| panic!("{err}")))
+				}
+				_ if let Some(trap) = arg.strip_prefix("--trap=") => {
+					res.trap = trap.parse().unwrap_or_else(|err| panic!("{err}"))
+				}
+				_ if let Some(format) = arg.strip_prefix("--error-format=") => {
+					res.error_format = format.parse().unwrap_or_else(|err| panic!("{err}"))
+				}
+				_ if let Some(assembler) = arg.strip_prefix("--assembler=") => {
+					res.syntax = match assembler {
+						"gas" => x86_gen::Syntax::Intel,
+						"nasm" => x86_gen::Syntax::Nasm,
+						_ => panic!("Unknown assembler: '{assembler}'"),
+					}
+				}
+				_ if let Some(syntax) = arg.strip_prefix("--syntax=") => {
+					res.syntax = syntax.parse().unwrap_or_else(|err| panic!("{err}"))
+				}
+				_ if let Some(emit) = arg.strip_prefix("--emit=") => {
+					res.emit = match emit {
+						"asm" => Emit::Asm,
+						"listing" => Emit::Listing,
+						"callgraph" => Emit::CallGraph,
+						"deps" => Emit::Deps,
+						"sexpr" => Emit::Sexpr,
+						"llvm" => Emit::Llvm,
+						"tokens-annotated" => Emit::TokensAnnotated,
+						_ => panic!("Unknown emit kind: '{emit}'"),
+					}
+				}
+				_ if let Some(target) = arg.strip_prefix("--target=") => {
+					res.emit = match target {
+						"x86_64" => Emit::Asm,
+						"llvm" => Emit::Llvm,
+						_ => panic!("Unknown target: '{target}'"),
+					}
+				}
+				_ if !arg.starts_with('-') && res.input.is_none() => res.input = Some(arg),
+				_ => panic!("Unknown argument: '{arg}'"),
+			}
+		}
+		// Extensions enabled by their own flag still apply, before or after it
+		if pedantic {
+			res.lexer = lexer::LexerOptions {
+				nested_comments: res.lexer.nested_comments,
+				..lexer::LexerOptions::pedantic()
+			};
+			res.parser = parser::ParserOptions {
+				allow_implicit_int: res.parser.allow_implicit_int,
+				..parser::ParserOptions::pedantic()
+			};
+		}
+		res
+	}
+}
 
 fn main() {
 	env_logger::init();
-	let lexer_output = lexer::tokenize(include_str!("test.c"));
+	let args = Args::parse(std::env::args().skip(1));
+	let source = match &args.input {
+		Some(path) => std::fs::read_to_string(path)
+			.unwrap_or_else(|err| panic!("Failed to read '{path}': {err}")),
+		None => include_str!("test.c").to_string(),
+	};
+	let raw_source = source.clone();
+	let source = match preprocessor::preprocess(&source) {
+		Ok(source) => source,
+		Err(error) => report(&args, CompileError::preprocess(error)),
+	};
+	let lexer_output = match lexer::tokenize_with_options(&source, args.lexer) {
+		Ok(lexer_output) => lexer_output,
+		Err(error) => report(&args, CompileError::lex(&source, error)),
+	};
 	log::debug!("Tokens: {:#?}", lexer_output);
-	let (parsed, ident_table) = parser::parse(lexer_output.clone()).unwrap();
+	if args.emit == Emit::TokensAnnotated {
+		write_output(&args, None, &lexer_output.annotated(&source));
+		return;
+	}
+	let (mut parsed, ident_table) =
+		match parser::parse_with_options(lexer_output.clone(), args.parser) {
+			Ok(res) => res,
+			Err(error) => report(&args, CompileError::parse(&lexer_output, error)),
+		};
 	log::debug!("Parse Tree: {parsed:#?}");
 	log::debug!("Ident Table: {ident_table:#?}");
-	if let Err(kind) = analyzer::analyze(&parsed) {
-		use analyzer::SemanticError;
-		match kind {
-			SemanticError::UseBeforeDeclaration(ident)
-			| SemanticError::MultipleDeclaration(ident)
-			| SemanticError::ExpectedPrimitiveFoundArray(ident)
-			| SemanticError::ExpectedArrayFoundPrimitive(ident) => panic!(
-				"Err: '{kind:?}' at '{ident:?}' name: {:?}",
-				ident_table.0.get(ident.table_index)
-			),
-			SemanticError::UndefinedFunction(sig)
-			| SemanticError::InvalidArguments(sig)
-			| SemanticError::FunctionRedeclaration(sig) => panic!(
-				"Err: '{kind:?}' at '{sig:?}' name: {:?}",
-				ident_table.0.get(sig.table_index)
-			),
-			_ => panic!("Semantic Error: {kind:?}"),
+	if args.emit == Emit::Sexpr {
+		write_output(&args, None, &parser::sexpr::sexpr(&parsed, &ident_table));
+		return;
+	}
+	match analyzer::analyze_with_options(&parsed, args.analyzer) {
+		Ok(warnings) => {
+			for warning in warnings {
+				let diagnostic = Diagnostic::semantic_warning(&warning, &ident_table);
+				eprintln!("{}:{}", input_path(&args), diagnostic.labeled("warning"));
+			}
 		}
+		Err(errors) => report(&args, CompileError::semantic(errors, &ident_table)),
+	}
+	if args.simplify {
+		parser::simplify(&mut parsed);
+		log::debug!("Simplified: {parsed:#?}");
 	}
-	let tac_instructions = tac_gen::generate(&parsed, ident_table.0.len());
-	log::debug!("Code Gen: {tac_instructions:#?}");
-	let x86_asm = x86_gen::x86_gen(tac_instructions, ident_table);
+	let mut tac_instructions = tac_gen::generate_named(&parsed, &ident_table);
+	log::debug!("Code Gen:\n{}", dump(&tac_instructions));
+	if args.verify {
+		verify(&tac_instructions, "tac_gen::generate");
+		for function in &tac_instructions {
+			if let Err(kind) = tac_gen::verify_structured(function) {
+				panic!("Unstructured TAC in F{}: {kind:?}", function.id);
+			}
+		}
+	}
+	let passes = args.passes.clone().unwrap_or_else(|| {
+		use tac_gen::pipeline::Pass;
+		let enabled = [args.fold, args.inline, args.licm];
+		Pass::ALL
+			.into_iter()
+			.zip(enabled)
+			.filter_map(|(pass, enabled)| enabled.then_some(pass))
+			.collect()
+	});
+	let mut pass_stats = Vec::new();
+	for pass in passes {
+		pass_stats.push(tac_gen::pass_stats::instrument(
+			pass.name(),
+			&mut tac_instructions,
+			|functions| pass.run(functions),
+		));
+		log::debug!("After {}:\n{}", pass.name(), dump(&tac_instructions));
+		if args.verify {
+			verify(&tac_instructions, &format!("tac_gen::{}", pass.name()));
+		}
+	}
+	if args.print_pass_stats {
+		eprint!("{}", tac_gen::pass_stats::table(&pass_stats));
+	}
+	if args.emit == Emit::CallGraph {
+		let json = tac_gen::call_graph::to_json(&parsed, &tac_instructions, &ident_table);
+		write_output(&args, None, &format!("{json}\n"));
+		return;
+	}
+	if args.emit == Emit::Deps {
+		let dependencies = tac_gen::call_graph::dependencies(&tac_instructions, &ident_table);
+		write_output(&args, None, &dependencies.to_string());
+		return;
+	}
+	if args.emit == Emit::Llvm {
+		let llvm_ir = llvm_gen::llvm_gen(&parsed, &tac_instructions, &ident_table);
+		log::debug!("LLVM IR: {llvm_ir}");
+		write_output(&args, Some("ezc.ll"), &llvm_ir);
+		return;
+	}
+	if args.run {
+		let Some(main) = ident_table.0.iter().position(|name| name == "main") else {
+			panic!("No `main` to run");
+		};
+		match interpreter::run_profiled(&tac_instructions, main, &args.arguments) {
+			Ok((value, calls)) => {
+				if args.profile {
+					let mut calls: Vec<_> = calls.into_iter().collect();
+					calls.sort_unstable();
+					for (id, count) in calls {
+						eprintln!("{count:>8} {}", ident_table.0[id]);
+					}
+				}
+				std::process::exit(value)
+			}
+			Err(kind) => panic!("Runtime Error: {kind:?}"),
+		}
+	}
+	let x86_config = x86_gen::Config {
+		annotate: args.annotate,
+		trap: args.trap,
+		div_guard: args.div_guard,
+		freestanding: args.freestanding,
+		static_locals: args.static_locals,
+		syntax: args.syntax,
+		record_source: args.record_source.then(|| {
+			let path = args.input.as_deref().unwrap_or("src/test.c");
+			x86_gen::SourceRecord::new(path, &raw_source)
+		}),
+		profile: args.profile,
+	};
+	if let Some(max_stack) = args.max_stack {
+		let oversized =
+			x86_gen::oversized_frames(&tac_instructions, &ident_table, &x86_config, max_stack);
+		if !oversized.is_empty() {
+			let error = CompileError::stack(oversized, max_stack, &parsed, &ident_table);
+			report(&args, error);
+		}
+	}
+	if args.emit == Emit::Listing {
+		let listing = listing::listing(&source, &tac_instructions, &ident_table, &x86_config);
+		write_output(&args, None, &listing);
+		return;
+	}
+	// `x86_gen` consumes the TAC, so its metrics are taken beforehand
+	let tac_metrics = args.stats.then(|| {
+		let frames = x86_gen::frame_sizes(&tac_instructions, &ident_table, &x86_config);
+		let max_frame = frames.iter().map(|&(_, size)| size).max().unwrap_or(0);
+		let tac_count: usize = tac_instructions
+			.iter()
+			.map(|function| function.instructions.len())
+			.sum();
+		(tac_instructions.len(), tac_count, max_frame)
+	});
+	let x86_asm = x86_gen::x86_gen(tac_instructions, ident_table, &x86_config);
 	log::debug!("x86 Assembly: {x86_asm}");
-	std::fs::write("ezc.asm", x86_asm).unwrap();
+	if let Some((function_count, tac_count, max_frame)) = tac_metrics {
+		let mut summary = vec![
+			("functions".to_string(), function_count as isize),
+			("tac instructions".to_string(), tac_count as isize),
+			(
+				"x86 instructions".to_string(),
+				x86_gen::instruction_count(&x86_asm) as isize,
+			),
+			("max frame bytes".to_string(), max_frame as isize),
+		];
+		for stats in &pass_stats {
+			summary.push((format!("removed by {}", stats.pass), stats.removed()));
+		}
+		let width = summary
+			.iter()
+			.map(|(name, _)| name.len())
+			.max()
+			.unwrap_or(0);
+		for (name, value) in summary {
+			eprintln!("{name:width$}  {value:>6}");
+		}
+	}
+	write_output(&args, Some("ezc.asm"), &x86_asm);
+}
+
+/// Writes the artifact to `-o`, falling back to `default`, or stdout when
+/// neither names a path
+fn write_output(args: &Args, default: Option<&str>, contents: &str) {
+	match args.output.as_deref().or(default) {
+		None | Some("-") => print!("{contents}"),
+		Some(path) => std::fs::write(path, contents)
+			.unwrap_or_else(|err| panic!("Failed to write '{path}': {err}")),
+	}
+}
+
+/// Path diagnostics are prefixed by, that of the built-in `src/test.c`
+/// without an input
+fn input_path(args: &Args) -> &str {
+	args.input.as_deref().unwrap_or("src/test.c")
+}
+
+/// Aborts compilation with every line of the `error` prefixed by the input's path, JSON
+/// diagnostics are printed to stdout for tools to consume
+fn report(args: &Args, error: CompileError) -> ! {
+	match args.error_format {
+		DiagnosticFormat::Human => {
+			for line in error.to_string().lines() {
+				eprintln!("{}:{line}", input_path(args));
+			}
+		}
+		DiagnosticFormat::Json => {
+			for diagnostic in error.diagnostics() {
+				println!("{}", diagnostic.render(args.error_format));
+			}
+		}
+	}
+	// Exit code 1 for a malformed program, 2 for one that is well formed but
+	// semantically invalid and 3 for a valid one over the stack budget
+	std::process::exit(match error {
+		CompileError::Preprocess(..) | CompileError::Lex(..) | CompileError::Parse(..) => 1,
+		CompileError::Semantic(..) => 2,
+		CompileError::Stack(..) => 3,
+	})
+}
+
+/// Panics on the first function failing `tac_gen::verify` after `stage`
+fn verify(functions: &[tac_gen::Function], stage: &str) {
+	for function in functions {
+		if let Err(kind) = tac_gen::verify(function) {
+			panic!("Invalid TAC after '{stage}' in F{}: {kind:?}", function.id);
+		}
+	}
+}
+
+fn dump(functions: &[tac_gen::Function]) -> String {
+	functions
+		.iter()
+		.map(|function| function.to_string())
+		.collect()
 }
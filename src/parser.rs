@@ -4,23 +4,56 @@
 //! Grammar:
 //! ```c
 //! <Func>
+//! | <Inlining> <Func>
 //! | int Ident(<Parmeter>*) {<Stmts>*}
+//! | void Ident(<Parmeter>*) {<Stmts>*}
+//! | int Ident(<Parmeter>*);
+//! | void Ident(<Parmeter>*);
+//! | int Ident(<Parmeter>*, ...);
+//! | void Ident(<Parmeter>*, ...);
+//!
+//! <Inlining>
+//! | inline
+//! | noinline
 //!
 //! <Parameters>
 //! | int Ident
+//! | int Ident[]
 //! | int Ident, <Parameter>
+//! | int Ident[], <Parameter>
 //!
 //! <Stmts>
 //! | if (<Expression>) {<Stmts>*}
 //! | if (<Expression>) <Stmts>
+//! | if (<Expression>) {<Stmts>*} else {<Stmts>*}
+//! | if (<Expression>) <Stmts> else <Stmts>
 //! | while (<Expression>) {<Stmts>*}
 //! | while (<Expression>) <Stmts>
+//! | do {<Stmts>*} while (<Expression>);
+//! | do <Stmts> while (<Expression>);
+//! | for (<ForInit>; <Expression>; <ForStep>) {<Stmts>*}
+//! | for (<ForInit>; <Expression>; <ForStep>) <Stmts>
 //! | int <Decl>;
+//! | static int <Decl>;
 //! | Ident [<Expression>] = <Expression>;
 //! | Ident = <Expression>;
+//! | Ident = Ident = ... = <Expression>;
+//! | Ident <CompoundAssignment> <DirectValue>;
+//! | Ident(<Arguments>);
 //! | break;
 //! | continue;
 //! | return <Expression>;
+//! | return;
+//!
+//! <ForInit>, may be left empty
+//! | int <Decl>
+//! | <ForStep>
+//!
+//! <ForStep>, may be left empty
+//! | Ident [<Expression>] = <Expression>
+//! | Ident = <Expression>
+//! | Ident <CompoundAssignment> <DirectValue>
+//! | Ident(<Arguments>)
 //!
 //! <Decl>
 //! | Ident
@@ -33,70 +66,192 @@
 //! <Expression>
 //! | Ident(<Arguments>)
 //! | Ident[<DirectValue>]
+//! | <Operand>
+//! | <Operand> ? <DirectValue> : <DirectValue>
+//!
+//! <Operand>
 //! | <DirectValue>
-//! | <DirectValue> <BinaryOperation> <DirectValue>
+//! | (<Operand>)
+//! | <UnaryOperation> <Operand>
+//! | <Operand> <BinaryOperation> <Operand>
 //!
 //! <Arguments>
 //! | <DirectValue>
 //! | <DirectValue>, <Arguments>
+//! | Literal
+//! | Literal, <Arguments>
 //!
 //! <DirectValue>
 //! | Ident
+//! | -Ident
+//! | ~Ident
+//! | !Ident
+//! | Ident[<DirectValue>]
 //! | Const
 //!
+//! <UnaryOperation>
+//! | -, !, ~
+//!
 //! <BinaryOperation>
-//! | +, -, *, /, %, &, |, ^, <, <=, >, >=, ==, !=
+//! | +, -, *, /, %, <<, >>, &, |, ^, <, <=, >, >=, ==, !=, &&, ||
+//!
+//! <CompoundAssignment>
+//! | +=, -=, *=, /=, %=, <<=, >>=, &=, |=, ^=
 //!
 //! ```
-//! Where a `Program` is just `Vec<Func>`
+//! Where a `Program` is just `Vec<Func>`, along with the string literals
+//!
+//! Binary operations follow C's precedence, from the tightest binding tier
+//! `* / %` through `+ -`, `<< >>`, the relational operators, `== !=`, `&`,
+//! `^`, `|`, `&&` and finally `||`. Operations within a tier group from the left,
+//! parentheses overriding the grouping. Unary operations bind tighter than
+//! any of them, applied to a constant they're folded into it. `&&` and `||`
+//! short-circuit, their right operand only evaluated when the left one
+//! doesn't decide the result.
+//!
+//! Arrays are passed by reference, so an `int a[]` parameter aliases the
+//! caller's array. Writes through it are visible to the caller, which is how
+//! a function returns more than one value.
+//!
+//! A `for` without a condition loops till a `break`, a `continue` within its
+//! body runs the step before testing the condition again. Variables declared
+//! by the init are local to the loop.
+//!
+//! A `static int` keeps its value across calls, its initializer is evaluated
+//! once at compile time so it must be constant. Only scalars can be `static`.
+//!
+//! A string literal is passed as the address of its NUL terminated bytes,
+//! which only external functions can take as `int s[]` expects 4 byte
+//! elements.
 use std::iter::Peekable;
 
-use crate::lexer::{LexerOutput, Reserved, Symbol, SymbolTable, Token};
+use crate::lexer::{IntegerSuffix, LexerOutput, Reserved, Symbol, SymbolTable, Token};
+
+pub mod sexpr;
+pub mod simplify;
+pub use simplify::simplify;
 
 /// Returns a parsed `Program` along with an identifier table on successful parse
-/// If not, returns the `Symbol` where parsing failed
-pub fn parse(lexer_output: LexerOutput) -> Result<(Program, IdentNameTable), Option<Symbol>> {
+/// If not, returns where and why parsing failed
+pub fn parse(lexer_output: LexerOutput) -> Result<(Program, IdentNameTable), ParseError> {
+	parse_with_options(lexer_output, ParserOptions::default())
+}
+
+/// Toggles for the extensions to the grammar, `ParserOptions::pedantic` turns
+/// all of them off. Those of the lexer are `lexer::LexerOptions`
+#[derive(Clone, Copy, Debug)]
+pub struct ParserOptions {
+	/// Functions without a return type return `int`, as in `main() {}`. Off
+	/// by default
+	pub allow_implicit_int: bool,
+	/// `0b` and `0o` prefixed integer literals, C only has `0x`. On by default
+	pub allow_radix_prefixes: bool,
+}
+impl Default for ParserOptions {
+	fn default() -> Self {
+		Self {
+			allow_implicit_int: false,
+			allow_radix_prefixes: true,
+		}
+	}
+}
+impl ParserOptions {
+	/// Only the documented grammar, every extension rejected
+	pub fn pedantic() -> Self {
+		Self {
+			allow_implicit_int: false,
+			allow_radix_prefixes: false,
+		}
+	}
+}
+
+/// Why `parse` failed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+	/// The input ended, at `Token::Eof`, where `expected` was required. A
+	/// description such as `'}'` or `a value`
+	UnexpectedEof { expected: &'static str },
+	/// `Symbol` the parser failed at, `None` being past the end of the input,
+	/// and its index into `LexerOutput::symbol`
+	UnexpectedToken(Option<Symbol>, usize),
+}
+
+/// Same as `parse`, with the grammar relaxed by `options`
+pub fn parse_with_options(
+	lexer_output: LexerOutput,
+	options: ParserOptions,
+) -> Result<(Program, IdentNameTable), ParseError> {
 	let LexerOutput {
 		symbol_table: SymbolTable {
-			identifier, consts, ..
+			identifier,
+			consts,
+			literal,
+			..
 		},
 		symbol,
+		..
 	} = lexer_output;
 	let mut parser = Parser {
 		symbols: symbol.iter().copied().peekable(),
 		const_table: consts,
+		options,
+		eof_expected: None,
 	};
 	let mut functions = Vec::new();
 	while let Some(func) = parser.func() {
 		functions.push(func);
 	}
-	let res = Ok((Program(functions), IdentNameTable(identifier)));
-	if parser
-		.symbols
-		.next_if(|i| matches!(i, Symbol(Token::Eof, ..)))
-		.is_some()
-	{
-		res
-	} else {
-		Err(parser.symbols.next())
+	// A function cut short leaves nothing but `Token::Eof` after it either,
+	// only the expectation recorded tells the two apart
+	let index = symbol.len() - parser.symbols.len();
+	match (parser.symbols.next(), parser.eof_expected) {
+		(Some(Symbol(Token::Eof, _)), None) => {
+			Ok((Program(functions, literal), IdentNameTable(identifier)))
+		}
+		(Some(Symbol(Token::Eof, _)), Some(expected)) => {
+			Err(ParseError::UnexpectedEof { expected })
+		}
+		(symbol, _) => Err(ParseError::UnexpectedToken(symbol, index)),
 	}
 }
 
+/// Tuple struct of the functions and the string literals, in the order the
+/// lexer interned them
 #[derive(Clone, Debug)]
-pub struct Program(pub Vec<Func>);
+pub struct Program(pub Vec<Func>, pub Vec<String>);
+impl Program {
+	/// Signatures of every function sharing its name with another, in source
+	/// order. Cheaper than `analyzer::analyze` for spotting redeclarations
+	pub fn duplicate_functions(&self) -> Vec<FuncSignature> {
+		self.0
+			.iter()
+			.map(Func::name)
+			.filter(|sig| {
+				self.0
+					.iter()
+					.filter(|func| func.name().table_index == sig.table_index)
+					.count() > 1
+			})
+			.collect()
+	}
+}
 
 #[derive(Clone, Debug)]
 pub struct IdentNameTable(pub Vec<String>);
 
+/// Tuple struct of the statements and the line number each of them starts at
 #[derive(Clone, Debug)]
-pub struct Scope(pub Vec<Stmts>);
+pub struct Scope(pub Vec<Stmts>, pub Vec<usize>);
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Ident {
 	line_number: usize,
 	pub table_index: usize,
 }
 impl Ident {
+	pub fn line_number(&self) -> usize {
+		self.line_number
+	}
 	fn as_func_name(&self, parameter_count: usize) -> FuncSignature {
 		FuncSignature {
 			line_number: self.line_number,
@@ -106,43 +261,135 @@ impl Ident {
 	}
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct FuncSignature {
 	line_number: usize,
 	pub table_index: usize,
 	pub parameter_count: usize,
 }
+impl FuncSignature {
+	pub fn line_number(&self) -> usize {
+		self.line_number
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnType {
+	Int,
+	Void,
+}
+
+/// Leading attribute of a function, overriding the heuristics of
+/// `tac_gen::inline_leaf_functions`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Inlining {
+	#[default]
+	Auto,
+	/// `inline`, inlined whatever its size
+	Always,
+	/// `noinline`, never inlined
+	Never,
+}
 
-/// Tuple struct of the function's name as `Ident` and the respective `Scope`
+/// A function's signature and body, the latter empty when external
 #[derive(Clone, Debug)]
-pub struct Func(FuncSignature, Parameters, Scope);
+pub struct Func {
+	name: FuncSignature,
+	parameters: Parameters,
+	scope: Scope,
+	return_type: ReturnType,
+	is_external: bool,
+	inlining: Inlining,
+	is_variadic: bool,
+}
 impl Func {
-	fn new(name: Ident, parameters: Parameters, scope: Scope) -> Self {
-		Self(
-			FuncSignature {
+	fn new(
+		name: Ident,
+		(parameters, is_variadic): (Parameters, bool),
+		scope: Scope,
+		return_type: ReturnType,
+		is_external: bool,
+		inlining: Inlining,
+	) -> Self {
+		Self {
+			name: FuncSignature {
 				line_number: name.line_number,
 				table_index: name.table_index,
 				parameter_count: parameters.len(),
 			},
 			parameters,
 			scope,
-		)
+			return_type,
+			is_external,
+			inlining,
+			is_variadic,
+		}
 	}
 	pub fn name(&self) -> FuncSignature {
-		self.0
+		self.name
 	}
 	pub fn parameter(&self) -> &Parameters {
-		&self.1
+		&self.parameters
 	}
 	pub fn parameter_table_idx(&self) -> Vec<usize> {
-		self.parameter().iter().map(|i| i.table_index).collect()
+		self.parameter()
+			.iter()
+			.map(|i| i.name.table_index)
+			.collect()
 	}
 	pub fn scope(&self) -> &Scope {
-		&self.2
+		&self.scope
+	}
+	pub fn return_type(&self) -> ReturnType {
+		self.return_type
+	}
+	/// Declared without a body, defined in another translation unit
+	pub fn is_external(&self) -> bool {
+		self.is_external
+	}
+	pub fn inlining(&self) -> Inlining {
+		self.inlining
+	}
+	/// Declared with a trailing `...`, taking any number of arguments past
+	/// its parameters. Only external functions can be
+	pub fn is_variadic(&self) -> bool {
+		self.is_variadic
+	}
+	/// Declaration as written in the source, `int fib(int n)`, without the
+	/// inlining attribute
+	pub fn signature(&self, ident_table: &IdentNameTable) -> String {
+		let name = |ident: Ident| ident_table.0[ident.table_index].as_str();
+		let mut parameters: Vec<_> = self
+			.parameter()
+			.iter()
+			.map(|parameter| match parameter.is_array {
+				true => format!("int {}[]", name(parameter.name)),
+				false => format!("int {}", name(parameter.name)),
+			})
+			.collect();
+		if self.is_variadic() {
+			parameters.push("...".to_string());
+		}
+		let return_type = match self.return_type() {
+			ReturnType::Int => "int",
+			ReturnType::Void => "void",
+		};
+		format!(
+			"{return_type} {}({})",
+			ident_table.0[self.name().table_index],
+			parameters.join(", ")
+		)
 	}
 }
 
-pub type Parameters = Vec<Ident>;
+pub type Parameters = Vec<Parameter>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Parameter {
+	pub name: Ident,
+	/// Declared as `int name[]`, the caller passes the array's base address
+	pub is_array: bool,
+}
 
 #[derive(Clone, Debug)]
 pub enum Decl {
@@ -153,35 +400,104 @@ pub enum Decl {
 	Variable {
 		name: Ident,
 		init_val: Option<Expression>,
+		/// Declared `static`, the storage persisting across calls
+		is_static: bool,
 	},
 }
 
 #[derive(Clone, Debug)]
 pub enum Stmts {
-	If(Expression, Scope),
+	/// Condition, body and the optional `else` body
+	If(Expression, Scope, Option<Scope>),
 	While(Expression, Scope),
+	/// Body, ran once before the condition is first checked, and condition
+	DoWhile(Scope, Expression),
+	/// `init` and `step` are a single declaration, assignment or call, an
+	/// empty condition being the constant 1
+	For {
+		init: Option<Box<Stmts>>,
+		cond: Expression,
+		step: Option<Box<Stmts>>,
+		body: Scope,
+	},
 	Decl(Vec<Decl>),
 	Assignment(Ident, Expression),
 	ArrayAssignment(Ident, Expression, Expression),
+	/// Call with the returned value discarded
+	FuncCall(FuncSignature, Arguments),
 	Break,
 	Continue,
-	Return(Expression),
+	/// `None` for returning from a `void` function
+	Return(Option<Expression>),
 }
 
 #[derive(Clone, Debug)]
 pub enum Expression {
 	FuncCall(FuncSignature, Arguments),
-	ArrayAccess(Ident, DirectValue),
+	/// Index is an operand, as those of an `Expression::Binary`
+	ArrayAccess(Ident, Box<Expression>),
 	DirectValue(DirectValue),
-	Binary(DirectValue, BinaryOperation, DirectValue),
+	/// Operands are an `Expression::DirectValue`, `Expression::Unary` or
+	/// another `Expression::Binary`
+	Binary(Box<Expression>, BinaryOperation, Box<Expression>),
+	/// Operand is of the same kinds as those of an `Expression::Binary`
+	Unary(UnaryOperation, Box<Expression>),
+	/// Tuple struct of the condition, either an `Expression::DirectValue` or
+	/// an `Expression::Binary`, and the operands when it's nonzero and zero.
+	/// Only the selected value is evaluated
+	Ternary(Box<Expression>, Box<Expression>, Box<Expression>),
 }
 
-type Arguments = Vec<DirectValue>;
+/// Operands, as those of an `Expression::Binary`, or string literals
+type Arguments = Vec<Expression>;
 
 #[derive(Clone, Debug)]
 pub enum DirectValue {
 	Ident(Ident),
+	/// Element of the array at the index, usable as an operand unlike
+	/// `Expression::ArrayAccess`
+	ArrayElement(Ident, Box<Expression>),
 	Const(i32),
+	/// String literal, indexing `Program::1`, only ever a call argument
+	Literal(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOperation {
+	/// `-`, arithmetic negation
+	Negate,
+	/// `!`, 1 when the operand is zero and 0 otherwise
+	Not,
+	/// `~`, bitwise not
+	Complement,
+}
+impl UnaryOperation {
+	/// Same semantics as the generated code, wrapping on overflow
+	pub fn evaluate(self, value: i32) -> i32 {
+		match self {
+			Self::Negate => value.wrapping_neg(),
+			Self::Not => (value == 0) as i32,
+			Self::Complement => !value,
+		}
+	}
+	fn from_token(token: Token) -> Option<Self> {
+		match token {
+			Token::Minus => Some(Self::Negate),
+			Token::Bang => Some(Self::Not),
+			Token::Tilde => Some(Self::Complement),
+			_ => None,
+		}
+	}
+}
+impl std::fmt::Display for UnaryOperation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let symbol = match self {
+			Self::Negate => "-",
+			Self::Not => "!",
+			Self::Complement => "~",
+		};
+		write!(f, "{symbol}")
+	}
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -191,6 +507,9 @@ pub enum BinaryOperation {
 	Mul,
 	Div,
 	Mod,
+	ShiftLeft,
+	/// Arithmetic shift, the sign bit filling the vacated bits
+	ShiftRight,
 	And,
 	Or,
 	Xor,
@@ -200,7 +519,122 @@ pub enum BinaryOperation {
 	GreaterEqual,
 	Equal,
 	NotEqual,
+	/// `&&`, lowered into jumps by `tac_gen` so never part of an
+	/// `RValue::Operation`
+	LogicalAnd,
+	/// `||`, same as `LogicalAnd`
+	LogicalOr,
 }
+impl BinaryOperation {
+	/// Tier of the operation, higher binding tighter
+	pub fn precedence(self) -> u8 {
+		use BinaryOperation::*;
+		match self {
+			Mul | Div | Mod => 9,
+			Add | Sub => 8,
+			ShiftLeft | ShiftRight => 7,
+			Less | LessEqual | Greater | GreaterEqual => 6,
+			Equal | NotEqual => 5,
+			And => 4,
+			Xor => 3,
+			Or => 2,
+			LogicalAnd => 1,
+			LogicalOr => 0,
+		}
+	}
+	/// Same semantics as the generated code: wrapping on overflow, shift
+	/// counts masked to their low 5 bits and 0 or 1 for comparisons, `None` on
+	/// division or modulo by zero
+	pub fn evaluate(self, lhs: i32, rhs: i32) -> Option<i32> {
+		use BinaryOperation::*;
+		Some(match self {
+			Add => lhs.wrapping_add(rhs),
+			Sub => lhs.wrapping_sub(rhs),
+			Mul => lhs.wrapping_mul(rhs),
+			Div | Mod if rhs == 0 => return None,
+			Div => lhs.wrapping_div(rhs),
+			Mod => lhs.wrapping_rem(rhs),
+			ShiftLeft => lhs.wrapping_shl(rhs as u32),
+			ShiftRight => lhs.wrapping_shr(rhs as u32),
+			And => lhs & rhs,
+			Or => lhs | rhs,
+			Xor => lhs ^ rhs,
+			Less => (lhs < rhs) as i32,
+			LessEqual => (lhs <= rhs) as i32,
+			Greater => (lhs > rhs) as i32,
+			GreaterEqual => (lhs >= rhs) as i32,
+			Equal => (lhs == rhs) as i32,
+			NotEqual => (lhs != rhs) as i32,
+			LogicalAnd => (lhs != 0 && rhs != 0) as i32,
+			LogicalOr => (lhs != 0 || rhs != 0) as i32,
+		})
+	}
+}
+impl std::fmt::Display for BinaryOperation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let symbol = match self {
+			Self::Add => "+",
+			Self::Sub => "-",
+			Self::Mul => "*",
+			Self::Div => "/",
+			Self::Mod => "%",
+			Self::ShiftLeft => "<<",
+			Self::ShiftRight => ">>",
+			Self::And => "&",
+			Self::Or => "|",
+			Self::Xor => "^",
+			Self::Less => "<",
+			Self::LessEqual => "<=",
+			Self::Greater => ">",
+			Self::GreaterEqual => ">=",
+			Self::Equal => "==",
+			Self::NotEqual => "!=",
+			Self::LogicalAnd => "&&",
+			Self::LogicalOr => "||",
+		};
+		write!(f, "{symbol}")
+	}
+}
+/// The operation applied by a compound assignment token, `x += y` being
+/// `x = x + y`
+fn compound_op_to_binary(token: Token) -> Option<BinaryOperation> {
+	use Token::*;
+	match token {
+		PlusEqual => Some(BinaryOperation::Add),
+		MinusEqual => Some(BinaryOperation::Sub),
+		StarEqual => Some(BinaryOperation::Mul),
+		SlashEqual => Some(BinaryOperation::Div),
+		PercentEqual => Some(BinaryOperation::Mod),
+		AmpEqual => Some(BinaryOperation::And),
+		PipeEqual => Some(BinaryOperation::Or),
+		CaretEqual => Some(BinaryOperation::Xor),
+		LessLessEqual => Some(BinaryOperation::ShiftLeft),
+		GreaterGreaterEqual => Some(BinaryOperation::ShiftRight),
+		_ => None,
+	}
+}
+
+/// The init or step of a `for`, a lone declaration, assignment or call.
+/// Neither `static` declarations nor chained assignments, which expand into
+/// several statements, are allowed
+fn for_clause(stmts: Vec<Stmts>) -> Option<Box<Stmts>> {
+	let [stmt] = <[Stmts; 1]>::try_from(stmts).ok()?;
+	let allowed = match &stmt {
+		Stmts::Decl(decls) => !decls.iter().any(|decl| {
+			matches!(
+				decl,
+				Decl::Variable {
+					is_static: true,
+					..
+				}
+			)
+		}),
+		Stmts::Assignment(..) | Stmts::ArrayAssignment(..) | Stmts::FuncCall(..) => true,
+		_ => false,
+	};
+	allowed.then(|| Box::new(stmt))
+}
+
 impl BinaryOperation {
 	fn from_token(token: &Token) -> Option<BinaryOperation> {
 		use Token::*;
@@ -210,6 +644,8 @@ impl BinaryOperation {
 			Star => Some(Self::Mul),
 			Slash => Some(Self::Div),
 			Percent => Some(Self::Mod),
+			LessLess => Some(Self::ShiftLeft),
+			GreaterGreater => Some(Self::ShiftRight),
 			Amp => Some(Self::And),
 			Pipe => Some(Self::Or),
 			Caret => Some(Self::Xor),
@@ -219,27 +655,54 @@ impl BinaryOperation {
 			GreaterEqual => Some(Self::GreaterEqual),
 			EqualEqual => Some(Self::Equal),
 			BangEqual => Some(Self::NotEqual),
+			AmpAmp => Some(Self::LogicalAnd),
+			PipePipe => Some(Self::LogicalOr),
 			_ => None,
 		}
 	}
 }
 
-/// Manages the state of the input `Symbol` stream during parsing
+/// Manages the state of the input `Symbol` stream during parsing, the stream
+/// is cloned to look further ahead than `peek`
 #[derive(Debug)]
-struct Parser<I: Iterator<Item = Symbol> + std::fmt::Debug> {
+struct Parser<I: Iterator<Item = Symbol> + Clone + std::fmt::Debug> {
 	symbols: Peekable<I>,
 	const_table: Vec<String>,
+	options: ParserOptions,
+	/// What the innermost production failing at `Token::Eof` required
+	eof_expected: Option<&'static str>,
 }
-impl<I: Iterator<Item = Symbol> + std::fmt::Debug> Parser<I> {
+impl<I: Iterator<Item = Symbol> + Clone + std::fmt::Debug> Parser<I> {
 	fn peek(&mut self) -> Option<Symbol> {
 		self.symbols.peek().copied()
 	}
+	/// The `Symbol` `n` places after the current one without consuming any,
+	/// `peek_nth(0)` being the same as `peek`
+	fn peek_nth(&self, n: usize) -> Option<Symbol> {
+		self.symbols.clone().nth(n)
+	}
 	fn tk_peek(&mut self) -> Option<Token> {
 		self.peek().map(|s| s.0)
 	}
 	fn next_if_eq(&mut self, needle: Token) -> bool {
 		self.symbols.next_if(|&i| i.token() == needle).is_some()
 	}
+	/// Same as `next_if_eq` for a `needle` the production can't do without,
+	/// recorded as expected when the input ends before it
+	fn expect(&mut self, needle: Token, description: &'static str) -> bool {
+		let found = self.next_if_eq(needle);
+		if !found {
+			self.missing(description);
+		}
+		found
+	}
+	/// Records `description` as expected when at `Token::Eof`, the innermost
+	/// failing production being the most specific
+	fn missing(&mut self, description: &'static str) {
+		if matches!(self.tk_peek(), Some(Token::Eof) | None) {
+			self.eof_expected.get_or_insert(description);
+		}
+	}
 	fn next_if(&mut self, func: impl Fn(Token) -> bool) -> Option<Token> {
 		self.symbols
 			.next_if(|&i| func(i.token()))
@@ -256,19 +719,52 @@ impl<I: Iterator<Item = Symbol> + std::fmt::Debug> Parser<I> {
 		}
 	}
 	fn func(&mut self) -> Option<Func> {
-		let mut scope = Vec::new();
-		if self.next_if_eq(Token::Keyword(Reserved::Int))
-			&& let Some(id) = self.ident()
-			&& self.next_if_eq(Token::LeftParenthesis)
+		let inlining = if self.next_if_eq(Token::Keyword(Reserved::Inline)) {
+			Inlining::Always
+		} else if self.next_if_eq(Token::Keyword(Reserved::NoInline)) {
+			Inlining::Never
+		} else {
+			Inlining::Auto
+		};
+		let return_type = if self.next_if_eq(Token::Keyword(Reserved::Int)) {
+			ReturnType::Int
+		} else if self.next_if_eq(Token::Keyword(Reserved::Void)) {
+			ReturnType::Void
+		} else if self.options.allow_implicit_int
+			&& matches!(self.tk_peek(), Some(Token::Identifier(_)))
+			&& matches!(self.peek_nth(1), Some(Symbol(Token::LeftParenthesis, _)))
+		{
+			ReturnType::Int
+		} else {
+			return None;
+		};
+		if let Some(id) = self.ident()
+			&& self.expect(Token::LeftParenthesis, "'('")
 			&& let Some(parameter) = self.parameters()
-			&& self.next_if_eq(Token::RightParenthesis)
-			&& self.next_if_eq(Token::LeftBrace)
+			&& self.expect(Token::RightParenthesis, "')'")
 		{
-			while let Some(stmt) = self.stmts() {
-				scope.push(stmt);
+			if self.next_if_eq(Token::Semicolon) {
+				let scope = Scope(Vec::new(), Vec::new());
+				return Some(Func::new(id, parameter, scope, return_type, true, inlining));
+			}
+			// A body couldn't reach the arguments past the parameters
+			if parameter.1 {
+				self.missing("';'");
+				return None;
 			}
-			if self.next_if_eq(Token::RightBrace) {
-				Some(Func::new(id, parameter, Scope(scope)))
+			if !self.expect(Token::LeftBrace, "'{'") {
+				return None;
+			}
+			let scope = self.scope();
+			if self.expect(Token::RightBrace, "'}'") {
+				Some(Func::new(
+					id,
+					parameter,
+					scope,
+					return_type,
+					false,
+					inlining,
+				))
 			} else {
 				None
 			}
@@ -276,193 +772,396 @@ impl<I: Iterator<Item = Symbol> + std::fmt::Debug> Parser<I> {
 			None
 		}
 	}
-	fn decl(&mut self) -> Option<Vec<Decl>> {
+	fn decl(&mut self, is_static: bool) -> Option<Vec<Decl>> {
 		let mut res = Vec::new();
 		while !matches!(self.tk_peek(), Some(Token::Semicolon)) {
 			if !res.is_empty() && !self.next_if_eq(Token::Comma) {
+				self.missing("';'");
 				return None;
 			}
-			if let Some(name) = self.ident() {
-				if self.next_if_eq(Token::Equal) {
-					res.push(Decl::Variable {
-						name,
-						init_val: self.expression(),
-					});
-				} else if self.next_if_eq(Token::LeftSquare) {
-					res.push(Decl::Array {
-						name,
-						size: self.constant()? as u32,
-					});
-					if !self.next_if_eq(Token::RightSquare) {
-						return None;
-					}
-				} else {
-					res.push(Decl::Variable {
-						name,
-						init_val: None,
-					});
+			let name = self.ident()?;
+			if self.next_if_eq(Token::Equal) {
+				res.push(Decl::Variable {
+					name,
+					init_val: self.expression(),
+					is_static,
+				});
+			} else if !is_static && self.next_if_eq(Token::LeftSquare) {
+				res.push(Decl::Array {
+					name,
+					size: self.constant()? as u32,
+				});
+				if !self.expect(Token::RightSquare, "']'") {
+					return None;
 				}
 			} else {
-				return None;
+				res.push(Decl::Variable {
+					name,
+					init_val: None,
+					is_static,
+				});
 			}
 		}
 		Some(res)
 	}
-	fn parameters(&mut self) -> Option<Parameters> {
+	/// Along with whether they end in `...`
+	fn parameters(&mut self) -> Option<(Parameters, bool)> {
 		let mut res = Vec::new();
 		while !matches!(self.tk_peek(), Some(Token::RightParenthesis)) {
 			if !res.is_empty() && !self.next_if_eq(Token::Comma) {
+				self.missing("')'");
 				return None;
 			}
-			if self.next_if_eq(Token::Keyword(Reserved::Int))
-				&& let Some(ident) = self.ident()
+			if self.next_if_eq(Token::Ellipsis) {
+				return Some((res, true));
+			} else if self.next_if_eq(Token::Keyword(Reserved::Int))
+				&& let Some(name) = self.ident()
 			{
-				res.push(ident);
+				let is_array = self.next_if_eq(Token::LeftSquare);
+				if is_array && !self.next_if_eq(Token::RightSquare) {
+					return None;
+				}
+				res.push(Parameter { name, is_array });
 			} else {
 				return None;
 			}
 		}
-		Some(res)
+		Some((res, false))
 	}
 	fn arguments(&mut self) -> Option<Arguments> {
 		let mut res = Vec::new();
 		while !matches!(self.tk_peek(), Some(Token::RightParenthesis)) {
 			if !res.is_empty() && !self.next_if_eq(Token::Comma) {
+				self.missing("')'");
 				return None;
 			}
-			if let Some(direct_val) = self.direct_value() {
-				res.push(direct_val);
-			} else {
-				return None;
+			match self.tk_peek() {
+				Some(Token::Literal(index)) => {
+					self.symbols.next();
+					res.push(Expression::DirectValue(DirectValue::Literal(index)));
+				}
+				_ => res.push(self.primary()?),
 			}
 		}
 		Some(res)
 	}
-	fn stmts(&mut self) -> Option<Stmts> {
-		if self.next_if_eq(Token::Keyword(Reserved::If)) && self.next_if_eq(Token::LeftParenthesis)
+	/// Usually a single statement, but chained assignments expand into one
+	/// per target
+	fn stmts(&mut self) -> Option<Vec<Stmts>> {
+		let stmt = if self.next_if_eq(Token::Keyword(Reserved::If))
+			&& self.next_if_eq(Token::LeftParenthesis)
 		{
 			let expression = self.expression()?;
-			if !self.next_if_eq(Token::RightParenthesis) {
+			if !self.expect(Token::RightParenthesis, "')'") {
 				return None;
 			};
-			Some(Stmts::If(expression, Scope(self.stmts_body()?)))
+			let body = self.stmts_body()?;
+			let otherwise = match self.next_if_eq(Token::Keyword(Reserved::Else)) {
+				true => Some(self.stmts_body()?),
+				false => None,
+			};
+			Some(Stmts::If(expression, body, otherwise))
 		} else if self.next_if_eq(Token::Keyword(Reserved::While))
 			&& self.next_if_eq(Token::LeftParenthesis)
 		{
 			let expression = self.expression()?;
-			if !self.next_if_eq(Token::RightParenthesis) {
+			if !self.expect(Token::RightParenthesis, "')'") {
 				return None;
 			};
-			Some(Stmts::While(expression, Scope(self.stmts_body()?)))
+			Some(Stmts::While(expression, self.stmts_body()?))
+		} else if self.next_if_eq(Token::Keyword(Reserved::Do)) {
+			let body = self.stmts_body()?;
+			if !(self.expect(Token::Keyword(Reserved::While), "'while'")
+				&& self.expect(Token::LeftParenthesis, "'('"))
+			{
+				return None;
+			}
+			let expression = self.expression()?;
+			if !(self.expect(Token::RightParenthesis, "')'")
+				&& self.expect(Token::Semicolon, "';'"))
+			{
+				return None;
+			}
+			Some(Stmts::DoWhile(body, expression))
+		} else if self.next_if_eq(Token::Keyword(Reserved::For))
+			&& self.next_if_eq(Token::LeftParenthesis)
+		{
+			let init = match self.next_if_eq(Token::Semicolon) {
+				true => None,
+				false => Some(for_clause(self.stmts()?)?),
+			};
+			let cond = match self.next_if_eq(Token::Semicolon) {
+				true => Expression::DirectValue(DirectValue::Const(1)),
+				false => {
+					let cond = self.expression()?;
+					if !self.expect(Token::Semicolon, "';'") {
+						return None;
+					}
+					cond
+				}
+			};
+			let step = match self.next_if_eq(Token::RightParenthesis) {
+				true => None,
+				false => {
+					let Some(ident) = self.ident() else {
+						self.missing("')'");
+						return None;
+					};
+					let step = self.ident_stmts(ident, Token::RightParenthesis, "')'")?;
+					Some(for_clause(step)?)
+				}
+			};
+			let body = self.stmts_body()?;
+			Some(Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			})
 		} else if self.next_if_eq(Token::Keyword(Reserved::Int))
-			&& let Some(decl) = self.decl()
-			&& self.next_if_eq(Token::Semicolon)
+			&& let Some(decl) = self.decl(false)
+			&& self.expect(Token::Semicolon, "';'")
+		{
+			Some(Stmts::Decl(decl))
+		} else if self.next_if_eq(Token::Keyword(Reserved::Static))
+			&& self.next_if_eq(Token::Keyword(Reserved::Int))
+			&& let Some(decl) = self.decl(true)
+			&& self.expect(Token::Semicolon, "';'")
 		{
 			Some(Stmts::Decl(decl))
 		} else if let Some(ident) = self.ident() {
-			if self.next_if_eq(Token::Equal)
-				&& let Some(expression) = self.expression()
-				&& self.next_if_eq(Token::Semicolon)
-			{
-				Some(Stmts::Assignment(ident, expression))
-			} else if self.next_if_eq(Token::LeftSquare)
-				&& let Some(index) = self.expression()
-				&& self.next_if_eq(Token::RightSquare)
-				&& self.next_if_eq(Token::Equal)
-				&& let Some(r_value) = self.expression()
-				&& self.next_if_eq(Token::Semicolon)
-			{
-				Some(Stmts::ArrayAssignment(ident, index, r_value))
-			} else {
-				None
-			}
+			return self.ident_stmts(ident, Token::Semicolon, "';'");
 		} else if self.next_if_eq(Token::Keyword(Reserved::Break))
-			&& self.next_if_eq(Token::Semicolon)
+			&& self.expect(Token::Semicolon, "';'")
 		{
 			Some(Stmts::Break)
 		} else if self.next_if_eq(Token::Keyword(Reserved::Continue))
-			&& self.next_if_eq(Token::Semicolon)
+			&& self.expect(Token::Semicolon, "';'")
 		{
 			Some(Stmts::Continue)
+		} else if self.next_if_eq(Token::Keyword(Reserved::Return)) {
+			if self.next_if_eq(Token::Semicolon) {
+				Some(Stmts::Return(None))
+			} else {
+				Some(Stmts::Return(Some(self.expression()?)))
+					.take_if(|_| self.expect(Token::Semicolon, "';'"))
+			}
 		} else {
-			Some(Stmts::Return(
-				self.next_if_eq(Token::Keyword(Reserved::Return))
-					.then_some(())
-					.and_then(|_| self.expression())
-					.take_if(|_| self.next_if_eq(Token::Semicolon))?,
-			))
+			None
+		};
+		stmt.map(|stmt| vec![stmt])
+	}
+	/// Statement starting with `ident` and ending at `end`, usually a `;`
+	fn ident_stmts(
+		&mut self,
+		ident: Ident,
+		end: Token,
+		description: &'static str,
+	) -> Option<Vec<Stmts>> {
+		if self.next_if_eq(Token::Equal) {
+			self.assignment_chain(ident, end, description)
+		} else if let Some(token) = self.next_if(|tk| compound_op_to_binary(tk).is_some())
+			&& let Some(value) = self.primary()
+			&& self.expect(end, description)
+		{
+			let operation = compound_op_to_binary(token)?;
+			let expression = Expression::Binary(
+				Box::new(Expression::DirectValue(DirectValue::Ident(ident))),
+				operation,
+				Box::new(value),
+			);
+			Some(vec![Stmts::Assignment(ident, expression)])
+		} else if self.next_if_eq(Token::LeftSquare)
+			&& let Some(index) = self.expression()
+			&& self.expect(Token::RightSquare, "']'")
+			&& self.expect(Token::Equal, "'='")
+			&& let Some(r_value) = self.expression()
+			&& self.expect(end, description)
+		{
+			Some(vec![Stmts::ArrayAssignment(ident, index, r_value)])
+		} else if self.next_if_eq(Token::LeftParenthesis)
+			&& let Some(arguments) = self.arguments()
+			&& self.expect(Token::RightParenthesis, "')'")
+			&& self.expect(end, description)
+		{
+			Some(vec![Stmts::FuncCall(
+				ident.as_func_name(arguments.len()),
+				arguments,
+			)])
+		} else {
+			self.missing("'='");
+			None
 		}
 	}
+	/// Ident = <Expression>; where the <Expression> may be another `Ident =`.
+	/// Lowered right to left, so `a = b = 5;` becomes `b = 5; a = b;`
+	fn assignment_chain(
+		&mut self,
+		target: Ident,
+		end: Token,
+		description: &'static str,
+	) -> Option<Vec<Stmts>> {
+		let mut targets = vec![target];
+		let mut expression = self.expression()?;
+		while let Expression::DirectValue(DirectValue::Ident(ident)) = expression
+			&& self.next_if_eq(Token::Equal)
+		{
+			targets.push(ident);
+			expression = self.expression()?;
+		}
+		if !self.expect(end, description) {
+			return None;
+		}
+		let mut targets = targets.into_iter().rev();
+		let mut previous = targets.next()?;
+		let mut res = vec![Stmts::Assignment(previous, expression)];
+		for target in targets {
+			let value = Expression::DirectValue(DirectValue::Ident(previous));
+			res.push(Stmts::Assignment(target, value));
+			previous = target;
+		}
+		Some(res)
+	}
 	/// Equivalent to either:
 	/// { <Stmts>* } OR <Stmts>
 	/// Used for parsing the body for if and while
-	fn stmts_body(&mut self) -> Option<Vec<Stmts>> {
+	fn stmts_body(&mut self) -> Option<Scope> {
 		if self.next_if_eq(Token::LeftBrace) {
-			let mut stmts = Vec::new();
-			while let Some(stmt) = self.stmts() {
-				stmts.push(stmt);
-			}
-			Some(stmts).take_if(|_| self.next_if_eq(Token::RightBrace))
+			Some(self.scope()).take_if(|_| self.expect(Token::RightBrace, "'}'"))
 		} else {
-			Some(vec![self.stmts()?])
+			let line_number = self.peek()?.1;
+			let stmts = self.stmts()?;
+			let lines = vec![line_number; stmts.len()];
+			Some(Scope(stmts, lines))
+		}
+	}
+	/// <Stmts>* along with the line numbers they start at
+	fn scope(&mut self) -> Scope {
+		let mut res = Scope(Vec::new(), Vec::new());
+		while let Some(Symbol(_, line_number)) = self.peek()
+			&& let Some(stmts) = self.stmts()
+		{
+			res.1.extend(vec![line_number; stmts.len()]);
+			res.0.extend(stmts);
 		}
+		res
 	}
 	fn expression(&mut self) -> Option<Expression> {
-		let l_value = self.direct_value()?;
-		if let DirectValue::Ident(ident) = l_value {
-			if self.next_if_eq(Token::LeftParenthesis) {
-				if let Some(arguments) = self.arguments()
-					&& self.next_if_eq(Token::RightParenthesis)
-				{
-					return Some(Expression::FuncCall(
-						ident.as_func_name(arguments.len()),
-						arguments,
-					));
-				} else {
-					return None;
-				}
+		let l_value = self.primary()?;
+		if let Expression::DirectValue(DirectValue::Ident(ident)) = l_value
+			&& self.next_if_eq(Token::LeftParenthesis)
+		{
+			if let Some(arguments) = self.arguments()
+				&& self.expect(Token::RightParenthesis, "')'")
+			{
+				return Some(Expression::FuncCall(
+					ident.as_func_name(arguments.len()),
+					arguments,
+				));
+			} else {
+				return None;
 			}
-			if self.next_if_eq(Token::LeftSquare) {
-				if let Some(size) = self.direct_value()
-					&& self.next_if_eq(Token::RightSquare)
-				{
-					return Some(Expression::ArrayAccess(ident, size));
-				} else {
-					return None;
-				}
+		}
+		let expression = self.operand(l_value, 0)?;
+		if self.next_if_eq(Token::Question) {
+			let then = self.primary()?;
+			if !self.expect(Token::Colon, "':'") {
+				return None;
 			}
+			let otherwise = self.primary()?;
+			return Some(Expression::Ternary(
+				Box::new(expression),
+				Box::new(then),
+				Box::new(otherwise),
+			));
 		}
-		if let Some(binary_operation) = self.binary_operation() {
-			Some(Expression::Binary(
-				l_value,
-				binary_operation,
-				self.direct_value()?,
-			))
-		} else {
-			Some(Expression::DirectValue(l_value))
+		match expression {
+			Expression::DirectValue(DirectValue::ArrayElement(ident, index)) => {
+				Some(Expression::ArrayAccess(ident, index))
+			}
+			expression => Some(expression),
 		}
 	}
+	/// Precedence climbing, extends `lhs` with the operations of at least
+	/// `min_precedence` that follow it
+	fn operand(&mut self, mut lhs: Expression, min_precedence: u8) -> Option<Expression> {
+		while let Some(operation) = self.peek_binary_operation()
+			&& operation.precedence() >= min_precedence
+		{
+			self.symbols.next();
+			let mut rhs = self.primary()?;
+			// Tighter binding operations after the right operand take it as
+			// their left operand
+			while let Some(next) = self.peek_binary_operation()
+				&& next.precedence() > operation.precedence()
+			{
+				rhs = self.operand(rhs, next.precedence())?;
+			}
+			lhs = Expression::Binary(Box::new(lhs), operation, Box::new(rhs));
+		}
+		Some(lhs)
+	}
+	/// A `DirectValue`, a unary operation or an `<Operand>` grouped in
+	/// parentheses, which leave no trace in the tree
+	fn primary(&mut self) -> Option<Expression> {
+		// Constants are left for `direct_value` to fold
+		if let Some(operation) = self.tk_peek().and_then(UnaryOperation::from_token)
+			&& !matches!(self.peek_nth(1), Some(Symbol(Token::Const(_), _)))
+		{
+			self.symbols.next();
+			return Some(Expression::Unary(operation, Box::new(self.primary()?)));
+		}
+		if self.next_if_eq(Token::LeftParenthesis) {
+			let lhs = self.primary()?;
+			let grouped = self.operand(lhs, 0)?;
+			return Some(grouped).take_if(|_| self.expect(Token::RightParenthesis, "')'"));
+		}
+		self.direct_value().map(Expression::DirectValue)
+	}
 	fn direct_value(&mut self) -> Option<DirectValue> {
 		if let Some(val) = self.ident() {
+			if self.next_if_eq(Token::LeftSquare) {
+				let index = self.primary()?;
+				return Some(DirectValue::ArrayElement(val, Box::new(index)))
+					.take_if(|_| self.expect(Token::RightSquare, "']'"));
+			}
 			Some(DirectValue::Ident(val))
+		} else if let Some(operation) = self.tk_peek().and_then(UnaryOperation::from_token) {
+			// Only reached for constants, `primary` takes the other operands
+			self.symbols.next();
+			self.constant()
+				.map(|val| DirectValue::Const(operation.evaluate(val)))
 		} else {
-			self.constant().map(DirectValue::Const)
+			let res = self.constant().map(DirectValue::Const);
+			if res.is_none() {
+				self.missing("a value");
+			}
+			res
 		}
 	}
 	fn constant(&mut self) -> Option<i32> {
 		let sign = if self.next_if_eq(Token::Minus) { -1 } else { 1 };
 		match self.next_if(|i| matches!(i, Token::Const(_))) {
 			Some(Token::Const(symbol_idx)) => {
-				Some(sign * self.parse_const(self.const_table.get(symbol_idx)?)?)
+				// Every value is an `int` for now, so the suffix is dropped
+				let (value, _suffix) = self.parse_const(self.const_table.get(symbol_idx)?)?;
+				Some(sign * value)
 			}
 			_ => None,
 		}
 	}
-	fn binary_operation(&mut self) -> Option<BinaryOperation> {
-		self.next_if(|tk| BinaryOperation::from_token(&tk).is_some())
-			.map(|tk| BinaryOperation::from_token(&tk))?
+	fn peek_binary_operation(&mut self) -> Option<BinaryOperation> {
+		BinaryOperation::from_token(&self.tk_peek()?)
+	}
+	fn parse_const(&self, value: &str) -> Option<(i32, IntegerSuffix)> {
+		let (digits, suffix) = IntegerSuffix::split(value)?;
+		let radix_prefix = digits.trim_start_matches('0').starts_with(['b', 'o']);
+		if radix_prefix && !self.options.allow_radix_prefixes {
+			return None;
+		}
+		Some((Self::parse_digits(digits)?, suffix))
 	}
-	fn parse_const(&self, value: &str) -> Option<i32> {
+	fn parse_digits(value: &str) -> Option<i32> {
 		if let Ok(val) = value.parse::<i32>() {
 			Some(val)
 		} else {
@@ -477,3 +1176,520 @@ impl<I: Iterator<Item = Symbol> + std::fmt::Debug> Parser<I> {
 		}
 	}
 }
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::lexer::tokenize;
+
+	#[allow(dead_code)]
+	fn parser() -> Parser<std::vec::IntoIter<Symbol>> {
+		Parser {
+			symbols: Vec::new().into_iter().peekable(),
+			const_table: Vec::new(),
+			options: ParserOptions::default(),
+			eof_expected: None,
+		}
+	}
+
+	#[test]
+	fn integer_suffixes() {
+		let parser = parser();
+		assert_eq!(
+			Some((10, IntegerSuffix::Unsigned)),
+			parser.parse_const("10u")
+		);
+		assert_eq!(
+			Some((255, IntegerSuffix::UnsignedLong)),
+			parser.parse_const("0xFFUL")
+		);
+		assert_eq!(
+			Some((3, IntegerSuffix::LongLong)),
+			parser.parse_const("3ll")
+		);
+		assert_eq!(Some((5, IntegerSuffix::None)), parser.parse_const("0b101"));
+		assert_eq!(None, parser.parse_const("10z"));
+		assert_eq!(None, parser.parse_const("10uu"));
+		assert!(parse(tokenize("int main() { return 10u + 0xFFUL; }").unwrap()).is_ok());
+		assert!(tokenize("int main() { return 10z; }").is_err());
+	}
+
+	#[test]
+	fn implicit_int() {
+		let test_program = "main(int n) { return 0; }";
+		assert!(parse(tokenize(test_program).unwrap()).is_err());
+		let options = ParserOptions {
+			allow_implicit_int: true,
+			..Default::default()
+		};
+		let (Program(functions, _), _) =
+			parse_with_options(tokenize(test_program).unwrap(), options).unwrap();
+		assert_eq!(ReturnType::Int, functions[0].return_type());
+		assert_eq!(1, functions[0].parameter().len());
+	}
+
+	#[test]
+	fn chained_assignment() {
+		let test_program = "int main() { int a, b; a = b = 5; return a + b; }";
+		let (Program(functions, _), table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let name = |ident: &Ident| table.0[ident.table_index].as_str();
+		let stmts = &functions[0].scope().0;
+		assert_eq!(4, stmts.len());
+		let Stmts::Assignment(b, Expression::DirectValue(DirectValue::Const(5))) = &stmts[1] else {
+			panic!("Expected `b = 5`, found {:?}", stmts[1]);
+		};
+		assert_eq!("b", name(b));
+		let Stmts::Assignment(a, Expression::DirectValue(DirectValue::Ident(value))) = &stmts[2]
+		else {
+			panic!("Expected `a = b`, found {:?}", stmts[2]);
+		};
+		assert_eq!(("a", "b"), (name(a), name(value)));
+		assert!(parse(tokenize("int main() { int a; a = 1 = 2; }").unwrap()).is_err());
+	}
+
+	#[test]
+	fn ternary() {
+		let test_program = r"
+			int main(int c, int a, int b) {
+				int x;
+				x = c ? 1 : 0;
+				return c < a ? a : b;
+			}
+		";
+		let (Program(functions, _), table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let name = |ident: &Ident| table.0[ident.table_index].as_str();
+		let stmts = &functions[0].scope().0;
+		let Stmts::Assignment(x, Expression::Ternary(condition, then, otherwise)) = &stmts[1]
+		else {
+			panic!("Expected `x = c ? 1 : 0`, found {:?}", stmts[1]);
+		};
+		assert!(matches!(
+			(&**then, &**otherwise),
+			(
+				Expression::DirectValue(DirectValue::Const(1)),
+				Expression::DirectValue(DirectValue::Const(0))
+			)
+		));
+		assert!(
+			matches!(**condition, Expression::DirectValue(DirectValue::Ident(c)) if name(&c) == "c")
+		);
+		assert_eq!("x", name(x));
+		let Stmts::Return(Some(Expression::Ternary(condition, then, otherwise))) = &stmts[2] else {
+			panic!("Expected `return c < a ? a : b`, found {:?}", stmts[2]);
+		};
+		let (
+			Expression::DirectValue(DirectValue::Ident(then)),
+			Expression::DirectValue(DirectValue::Ident(otherwise)),
+		) = (&**then, &**otherwise)
+		else {
+			panic!("Expected `return c < a ? a : b`, found {:?}", stmts[2]);
+		};
+		assert!(matches!(
+			**condition,
+			Expression::Binary(_, BinaryOperation::Less, _)
+		));
+		assert_eq!(("a", "b"), (name(then), name(otherwise)));
+		assert!(parse(tokenize("int main(int c) { return c ? 1; }").unwrap()).is_err());
+		assert!(parse(tokenize("int main(int c) { return c ? 1 : 2 : 3; }").unwrap()).is_err());
+	}
+
+	#[test]
+	fn unary_operands() {
+		// Arguments, indices and the values of a ternary are operands like
+		// those of a binary operation, unary operations included
+		let test_program = r"
+			int main(int a[], int n) {
+				n = f(-a[0], !n, ~(n + 1));
+				n = a[-n] ? -n : !a[~n];
+				return n;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"(func int main ((array a) n) \
+			 (assign n (call f (- (index a 0)) (! n) (~ (+ n 1)))) \
+			 (assign n (? (index a (- n)) (- n) (! (index a (~ n))))) \
+			 (return n))\n",
+			sexpr::sexpr(&parsed, &table)
+		);
+	}
+
+	#[test]
+	fn ternary_not_label() {
+		// An identifier before the `:` is the ternary's, not a label
+		let test_program = r"
+			int main(int x, int y, int z) {
+				int a;
+				a = x ? y : z;
+				return a;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"(func int main (x y z) (decl a) (assign a (? x y z)) (return a))\n",
+			sexpr::sexpr(&parsed, &table)
+		);
+		// Labels don't exist yet, so neither does a statement starting with one
+		assert!(parse(tokenize("int main(int i) { loop: i = i + 1; }").unwrap()).is_err());
+	}
+
+	/// S-expression of `expression` returned from a function of `a`, `b` and
+	/// `c`
+	#[allow(dead_code)]
+	fn grouped(expression: &str) -> String {
+		let test_program = format!("int main(int a, int b, int c) {{ return {expression}; }}");
+		let (parsed, table) = parse(tokenize(&test_program).unwrap()).unwrap();
+		let sexpr = sexpr::sexpr(&parsed, &table);
+		let start = sexpr.find("(return ").unwrap() + "(return ".len();
+		sexpr[start..sexpr.len() - 3].to_string()
+	}
+
+	#[test]
+	fn precedence() {
+		assert_eq!("(+ 1 (* 2 3))", grouped("1 + 2 * 3"));
+		assert_eq!("(+ (* 1 2) 3)", grouped("1 * 2 + 3"));
+		assert_eq!("(- (- a b) c)", grouped("a - b - c"));
+		assert_eq!("(< (+ a (/ b 2)) c)", grouped("a + b / 2 < c"));
+		assert_eq!(
+			"(| (& (== a b) (!= b c)) (^ a (* b c)))",
+			grouped("a == b & b != c | a ^ b * c")
+		);
+		assert_eq!("(? (< (+ a b) c) a 0)", grouped("a + b < c ? a : 0"));
+		assert_eq!(
+			"(< (<< a 1) (>> (- b 1) c))",
+			grouped("a << 1 < b - 1 >> c")
+		);
+		assert_eq!("(>> (<< a b) c)", grouped("a << b >> c"));
+		assert_eq!("(|| a (&& b c))", grouped("a || b && c"));
+		assert_eq!("(|| (&& a b) c)", grouped("a && b || c"));
+		assert_eq!("(&& (| a b) (< b c))", grouped("a | b && b < c"));
+		assert_eq!("(? (|| a b) a 0)", grouped("a || b ? a : 0"));
+	}
+
+	#[test]
+	fn unary() {
+		assert_eq!("(- (+ a b))", grouped("-(a + b)"));
+		assert_eq!("(< (! a) b)", grouped("!a < b"));
+		assert_eq!("(* (- a) (~ b))", grouped("-a * ~b"));
+		assert_eq!("(- a (- b))", grouped("a - -b"));
+		assert_eq!("(! (! (< a b)))", grouped("!!(a < b)"));
+		assert_eq!("(~ -5)", grouped("~-5"));
+		assert_eq!("-5", grouped("-5"));
+		assert!(parse(tokenize("int main(int a) { return a !; }").unwrap()).is_err());
+	}
+
+	#[test]
+	fn parentheses() {
+		assert_eq!("(* (+ a b) c)", grouped("(a + b) * c"));
+		assert_eq!("(- a (- b c))", grouped("a - (b - c)"));
+		assert_eq!("a", grouped("((a))"));
+		assert_eq!(
+			"(* 2 (+ a (* b (- c 1))))",
+			grouped("2 * (a + b * (c - 1))")
+		);
+		assert_eq!(
+			"(? (> (* (+ a 1) 2) b) a c)",
+			grouped("((a + 1) * 2 > b) ? a : c")
+		);
+		let error = |source| parse(tokenize(source).unwrap()).unwrap_err();
+		assert_eq!(
+			ParseError::UnexpectedToken(Some(Symbol(Token::RightParenthesis, 1)), 9),
+			error("int main(int a) { return (); }")
+		);
+		assert_eq!(
+			ParseError::UnexpectedToken(Some(Symbol(Token::Semicolon, 2)), 12),
+			error("int main(int a) {\n\treturn (a + 1;\n}")
+		);
+		assert_eq!(
+			ParseError::UnexpectedEof { expected: "')'" },
+			error("int main(int a) {\n\treturn (a + 1")
+		);
+	}
+
+	#[test]
+	fn else_branch() {
+		let sexpr = |source: &str| {
+			let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+			sexpr::sexpr(&parsed, &table)
+		};
+		assert_eq!(
+			"(func int main (a) (if a (return 1) (else (return 2))))\n",
+			sexpr("int main(int a) { if (a) { return 1; } else { return 2; } }")
+		);
+		// A dangling `else` belongs to the innermost `if`
+		assert_eq!(
+			"(func int main (a) (if a (if (- a 1) (assign a 2) (else (assign a 3)))) (return a))\n",
+			sexpr("int main(int a) { if (a) if (a - 1) a = 2; else a = 3; return a; }")
+		);
+		assert_eq!(
+			"(func int main (a) (if a (return 1) (else (if (< a 0) (return 2) (else (return 3))))))\n",
+			sexpr("int main(int a) { if (a) return 1; else if (a < 0) return 2; else return 3; }")
+		);
+		assert!(parse(tokenize("int main(int a) { else return a; }").unwrap()).is_err());
+		assert_eq!(
+			Err(ParseError::UnexpectedEof { expected: "'}'" }),
+			parse(tokenize("int main(int a) { if (a) {} else {").unwrap()).map(|_| ())
+		);
+	}
+
+	#[test]
+	fn for_loop() {
+		let sexpr = |source: &str| {
+			let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+			sexpr::sexpr(&parsed, &table)
+		};
+		assert_eq!(
+			"(func int main (n) (decl s 0) (for ((decl i 0)) (< i n) ((assign s (+ s i))) (assign i (+ i 1))) (return s))\n",
+			sexpr("int main(int n) { int s = 0; for (int i = 0; i < n; s += i) i += 1; return s; }")
+		);
+		assert_eq!(
+			"(func int main (n) (for () 1 () (return n)))\n",
+			sexpr("int main(int n) { for (;;) return n; }")
+		);
+		let error = |source| parse(tokenize(source).unwrap()).unwrap_err();
+		// Only a single statement fits in either clause
+		assert!(matches!(
+			error("int main(int n) { for (n = n = 0; n; n += 1) {} }"),
+			ParseError::UnexpectedToken(..)
+		));
+		assert!(matches!(
+			error("int main(int n) { for (static int i = 0; n; n += 1) {} }"),
+			ParseError::UnexpectedToken(..)
+		));
+		assert!(matches!(
+			error("int main(int n) { for (; n; n += 1 {} }"),
+			ParseError::UnexpectedToken(..)
+		));
+	}
+
+	#[test]
+	fn do_while() {
+		let sexpr = |source: &str| {
+			let (parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+			sexpr::sexpr(&parsed, &table)
+		};
+		assert_eq!(
+			"(func int main (n) (do (assign n (- n 1)) (while (> n 0))) (return n))\n",
+			sexpr("int main(int n) { do { n -= 1; } while (n > 0); return n; }")
+		);
+		assert_eq!(
+			"(func int main (n) (do (assign n (- n 1)) (while n)) (return n))\n",
+			sexpr("int main(int n) { do n -= 1; while (n); return n; }")
+		);
+		let error = |source| parse(tokenize(source).unwrap()).unwrap_err();
+		assert!(matches!(
+			error("int main(int n) { do { n -= 1; } (n); return n; }"),
+			ParseError::UnexpectedToken(..)
+		));
+		assert_eq!(
+			ParseError::UnexpectedEof { expected: "';'" },
+			error("int main(int n) { do { n -= 1; } while (n)")
+		);
+	}
+
+	#[test]
+	fn variadic() {
+		let (parsed, _) = parse(tokenize("int f(int a, ...);\nvoid g(...);").unwrap()).unwrap();
+		assert!(parsed.0.iter().all(|func| func.is_variadic()));
+		assert_eq!(1, parsed.0[0].parameter().len());
+		let error = |source| parse(tokenize(source).unwrap()).unwrap_err();
+		// Only declarations can be variadic
+		assert_eq!(
+			ParseError::UnexpectedToken(Some(Symbol(Token::LeftBrace, 1)), 8),
+			error("int f(int a, ...) { return a; }")
+		);
+		assert_eq!(
+			ParseError::UnexpectedToken(Some(Symbol(Token::Comma, 1)), 7),
+			error("int f(int a, ..., int b);")
+		);
+	}
+
+	#[test]
+	fn compound_assignments() {
+		use BinaryOperation::*;
+		for (token, operation) in [
+			("+=", Add),
+			("-=", Sub),
+			("*=", Mul),
+			("/=", Div),
+			("%=", Mod),
+			("<<=", ShiftLeft),
+			(">>=", ShiftRight),
+			("&=", And),
+			("|=", Or),
+			("^=", Xor),
+		] {
+			let test_program = format!("int main(int y) {{ int x; x {token} -y; return x; }}");
+			let (Program(functions, _), _) = parse(tokenize(&test_program).unwrap()).unwrap();
+			let stmt = &functions[0].scope().0[1];
+			let Stmts::Assignment(target, Expression::Binary(lhs, op, rhs)) = stmt else {
+				panic!("'{token}' expanded to {stmt:?}");
+			};
+			let (
+				Expression::DirectValue(DirectValue::Ident(lhs)),
+				Expression::Unary(UnaryOperation::Negate, rhs),
+			) = (&**lhs, &**rhs)
+			else {
+				panic!("'{token}' expanded to {stmt:?}");
+			};
+			let Expression::DirectValue(DirectValue::Ident(rhs)) = &**rhs else {
+				panic!("'{token}' expanded to {stmt:?}");
+			};
+			assert_eq!((target, *op), (lhs, operation));
+			assert_ne!(target.table_index, rhs.table_index);
+		}
+		assert!(parse(tokenize("int main() { int x; x += 1 + 2; }").unwrap()).is_err());
+	}
+
+	#[test]
+	fn duplicate_functions() {
+		let test_program = r"
+			int f(int n) { return n; }
+			int main() { return 0; }
+			int f() { return 1; }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let duplicates = parsed.duplicate_functions();
+		assert_eq!(2, duplicates.len());
+		assert!(duplicates.iter().all(|sig| table.0[sig.table_index] == "f"));
+		assert_eq!(
+			vec![(2, 1), (4, 0)],
+			duplicates
+				.iter()
+				.map(|sig| (sig.line_number(), sig.parameter_count))
+				.collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn pedantic() {
+		let test_program = "int main() { return 0b101; }";
+		assert!(parse(tokenize(test_program).unwrap()).is_ok());
+		assert!(
+			parse_with_options(tokenize(test_program).unwrap(), ParserOptions::pedantic()).is_err()
+		);
+		let test_program = "int main() { return 0x1F; }";
+		assert!(
+			parse_with_options(tokenize(test_program).unwrap(), ParserOptions::pedantic()).is_ok()
+		);
+	}
+
+	#[test]
+	fn array_parameters() {
+		let test_program = "int sum(int a[], int n) { return n; }";
+		let (Program(functions, _), table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let parameters: Vec<_> = functions[0]
+			.parameter()
+			.iter()
+			.map(|parameter| {
+				(
+					table.0[parameter.name.table_index].as_str(),
+					parameter.is_array,
+				)
+			})
+			.collect();
+		assert_eq!(vec![("a", true), ("n", false)], parameters);
+		assert!(parse(tokenize("int sum(int a[3]) { return 0; }").unwrap()).is_err());
+	}
+
+	#[test]
+	fn peek_nth() {
+		let symbols = vec![
+			Symbol(Token::Identifier(0), 1),
+			Symbol(Token::Colon, 1),
+			Symbol(Token::Eof, 1),
+		];
+		let mut parser = Parser {
+			symbols: symbols.into_iter().peekable(),
+			..parser()
+		};
+		assert_eq!(Some(Symbol(Token::Colon, 1)), parser.peek_nth(1));
+		assert_eq!(parser.peek(), parser.peek_nth(0));
+		assert_eq!(None, parser.peek_nth(3));
+		assert!(parser.next_if_eq(Token::Identifier(0)));
+		assert_eq!(Some(Token::Colon), parser.tk_peek());
+		assert_eq!(Some(Symbol(Token::Eof, 1)), parser.peek_nth(1));
+	}
+
+	#[test]
+	fn evaluate() {
+		use BinaryOperation::*;
+		let cases = [
+			(Add, 7, 3, Some(10)),
+			(Add, i32::MAX, 1, Some(i32::MIN)),
+			(Sub, 7, 3, Some(4)),
+			(Sub, i32::MIN, 1, Some(i32::MAX)),
+			(Mul, -7, 3, Some(-21)),
+			(Mul, i32::MAX, 2, Some(-2)),
+			(Div, -7, 2, Some(-3)),
+			(Div, i32::MIN, -1, Some(i32::MIN)),
+			(Div, 7, 0, None),
+			(Mod, -7, 2, Some(-1)),
+			(Mod, i32::MIN, -1, Some(0)),
+			(Mod, 7, 0, None),
+			(ShiftLeft, 3, 2, Some(12)),
+			(ShiftLeft, 1, 31, Some(i32::MIN)),
+			(ShiftLeft, 1, 33, Some(2)),
+			(ShiftRight, -64, 3, Some(-8)),
+			(ShiftRight, i32::MIN, 31, Some(-1)),
+			(ShiftRight, 64, 34, Some(16)),
+			(And, 0b1100, 0b1010, Some(0b1000)),
+			(Or, 0b1100, 0b1010, Some(0b1110)),
+			(Xor, 0b1100, 0b1010, Some(0b0110)),
+			(And, -16, 0xFF, Some(0xF0)),
+			(Or, -256, 0xFF, Some(-1)),
+			(Xor, -1, 0x0F, Some(-16)),
+			(Less, -1, 0, Some(1)),
+			(Less, 0, 0, Some(0)),
+			(LessEqual, 0, 0, Some(1)),
+			(LessEqual, 1, 0, Some(0)),
+			(Greater, 1, -1, Some(1)),
+			(Greater, 0, 0, Some(0)),
+			(GreaterEqual, 0, 0, Some(1)),
+			(GreaterEqual, -1, 0, Some(0)),
+			(Equal, 5, 5, Some(1)),
+			(Equal, 5, -5, Some(0)),
+			(NotEqual, 5, -5, Some(1)),
+			(NotEqual, 5, 5, Some(0)),
+			(LogicalAnd, 2, -1, Some(1)),
+			(LogicalAnd, 2, 0, Some(0)),
+			(LogicalOr, 0, 0, Some(0)),
+			(LogicalOr, 0, -3, Some(1)),
+		];
+		for (operation, lhs, rhs, expected) in cases {
+			assert_eq!(
+				expected,
+				operation.evaluate(lhs, rhs),
+				"{lhs} {operation} {rhs}"
+			);
+		}
+	}
+
+	#[test]
+	fn truncated() {
+		let error = |source| parse(tokenize(source).unwrap()).unwrap_err();
+		assert_eq!(
+			ParseError::UnexpectedEof { expected: "'}'" },
+			error("int main() {")
+		);
+		assert_eq!(
+			ParseError::UnexpectedEof {
+				expected: "a value"
+			},
+			error("int main(int a) {\n\treturn a +")
+		);
+		assert_eq!(
+			ParseError::UnexpectedEof { expected: "';'" },
+			error("int main(int a) {\n\treturn a + 1")
+		);
+		assert_eq!(
+			ParseError::UnexpectedEof { expected: "')'" },
+			error("int main(int a) {\n\tif (a < 2")
+		);
+		assert_eq!(
+			ParseError::UnexpectedToken(Some(Symbol(Token::Semicolon, 2)), 10),
+			error("int main(int a) {\n\treturn a + ;\n}")
+		);
+	}
+}
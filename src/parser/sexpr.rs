@@ -0,0 +1,236 @@
+//! S-expression rendering of the AST
+//!
+//! A compact, diffable dump of a `Program` with every identifier resolved to
+//! its name, one function per line:
+//! ```text
+//! (func int main (n) (decl x 0) (if (< x 4) (assign x 2)) (return x))
+//! ```
+use super::{
+	Decl, DirectValue, Expression, Func, IdentNameTable, Inlining, Program, ReturnType, Scope,
+	Stmts,
+};
+
+pub fn sexpr(program: &Program, ident_table: &IdentNameTable) -> String {
+	let formatter = Formatter(ident_table, &program.1);
+	program
+		.0
+		.iter()
+		.map(|func| formatter.func(func) + "\n")
+		.collect()
+}
+
+/// Tuple struct of the identifier names and the string literals
+struct Formatter<'a>(&'a IdentNameTable, &'a [String]);
+impl Formatter<'_> {
+	fn name(&self, table_index: usize) -> &str {
+		&self.0 .0[table_index]
+	}
+	/// `(func int name (parameters) stmts...)`, with `extern` in place of
+	/// `func` for functions without a body and array parameters as `(array a)`.
+	/// An inlining attribute follows the keyword, `(func inline int name ...)`.
+	fn func(&self, func: &Func) -> String {
+		let return_type = match func.return_type() {
+			ReturnType::Int => "int",
+			ReturnType::Void => "void",
+		};
+		let mut parameters: Vec<String> = func
+			.parameter()
+			.iter()
+			.map(|parameter| match parameter.is_array {
+				true => format!("(array {})", self.name(parameter.name.table_index)),
+				false => self.name(parameter.name.table_index).to_string(),
+			})
+			.collect();
+		if func.is_variadic() {
+			parameters.push("...".to_string());
+		}
+		let inlining = match func.inlining() {
+			Inlining::Auto => "",
+			Inlining::Always => "inline ",
+			Inlining::Never => "noinline ",
+		};
+		let head = format!(
+			"{} {inlining}{return_type} {} ({})",
+			if func.is_external() { "extern" } else { "func" },
+			self.name(func.name().table_index),
+			parameters.join(" ")
+		);
+		list(std::iter::once(head).chain(self.scope(func.scope())))
+	}
+	fn scope(&self, scope: &Scope) -> Vec<String> {
+		scope.0.iter().flat_map(|stmt| self.stmt(stmt)).collect()
+	}
+	/// A declaration of several identifiers renders as one `decl` each
+	fn stmt(&self, stmt: &Stmts) -> Vec<String> {
+		let stmt = match stmt {
+			Stmts::If(expr, scope, otherwise) => list(
+				["if".to_string(), self.expression(expr)]
+					.into_iter()
+					.chain(self.scope(scope))
+					.chain(otherwise.iter().map(|otherwise| {
+						list(std::iter::once("else".to_string()).chain(self.scope(otherwise)))
+					})),
+			),
+			Stmts::While(expr, scope) => list(
+				["while".to_string(), self.expression(expr)]
+					.into_iter()
+					.chain(self.scope(scope)),
+			),
+			Stmts::DoWhile(scope, expr) => list(
+				std::iter::once("do".to_string())
+					.chain(self.scope(scope))
+					.chain([list(["while".to_string(), self.expression(expr)])]),
+			),
+			// The init and step are grouped, `()` when left empty
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				let clause = |clause: &Option<Box<Stmts>>| {
+					list(clause.iter().flat_map(|clause| self.stmt(clause)))
+				};
+				list(
+					[
+						"for".to_string(),
+						clause(init),
+						self.expression(cond),
+						clause(step),
+					]
+					.into_iter()
+					.chain(self.scope(body)),
+				)
+			}
+			Stmts::Decl(decls) => {
+				return decls
+					.iter()
+					.map(|decl| match decl {
+						Decl::Array { name, size } => {
+							format!("(decl (array {} {size}))", self.name(name.table_index))
+						}
+						Decl::Variable {
+							name,
+							init_val,
+							is_static,
+						} => {
+							let keyword = if *is_static { "static" } else { "decl" };
+							match init_val {
+								None => format!("({keyword} {})", self.name(name.table_index)),
+								Some(expr) => format!(
+									"({keyword} {} {})",
+									self.name(name.table_index),
+									self.expression(expr)
+								),
+							}
+						}
+					})
+					.collect();
+			}
+			Stmts::Assignment(ident, expr) => format!(
+				"(assign {} {})",
+				self.name(ident.table_index),
+				self.expression(expr)
+			),
+			Stmts::ArrayAssignment(ident, index, expr) => format!(
+				"(assign (index {} {}) {})",
+				self.name(ident.table_index),
+				self.expression(index),
+				self.expression(expr)
+			),
+			Stmts::FuncCall(sig, arguments) => self.call(sig.table_index, arguments),
+			Stmts::Break => "(break)".to_string(),
+			Stmts::Continue => "(continue)".to_string(),
+			Stmts::Return(Some(expr)) => format!("(return {})", self.expression(expr)),
+			Stmts::Return(None) => "(return)".to_string(),
+		};
+		vec![stmt]
+	}
+	fn expression(&self, expr: &Expression) -> String {
+		match expr {
+			Expression::FuncCall(sig, arguments) => self.call(sig.table_index, arguments),
+			Expression::ArrayAccess(ident, index) => format!(
+				"(index {} {})",
+				self.name(ident.table_index),
+				self.expression(index)
+			),
+			Expression::DirectValue(direct_value) => self.direct_value(direct_value),
+			Expression::Binary(lhs, op, rhs) => {
+				format!("({op} {} {})", self.expression(lhs), self.expression(rhs))
+			}
+			Expression::Unary(op, operand) => format!("({op} {})", self.expression(operand)),
+			Expression::Ternary(condition, then, otherwise) => format!(
+				"(? {} {} {})",
+				self.expression(condition),
+				self.expression(then),
+				self.expression(otherwise)
+			),
+		}
+	}
+	fn call(&self, table_index: usize, arguments: &[Expression]) -> String {
+		list(
+			["call".to_string(), self.name(table_index).to_string()]
+				.into_iter()
+				.chain(arguments.iter().map(|argument| self.expression(argument))),
+		)
+	}
+	fn direct_value(&self, direct_value: &DirectValue) -> String {
+		match direct_value {
+			DirectValue::Ident(ident) => self.name(ident.table_index).to_string(),
+			DirectValue::ArrayElement(ident, index) => format!(
+				"(index {} {})",
+				self.name(ident.table_index),
+				self.expression(index)
+			),
+			DirectValue::Const(value) => value.to_string(),
+			// As written in the source, escape sequences included
+			DirectValue::Literal(index) => format!("\"{}\"", self.1[*index]),
+		}
+	}
+}
+
+/// Parenthesized, space separated `items`
+fn list(items: impl IntoIterator<Item = String>) -> String {
+	format!("({})", items.into_iter().collect::<Vec<_>>().join(" "))
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse};
+
+	#[test]
+	fn program() {
+		let test_program = r#"
+			int ext(int a[], int n);
+			noinline void f() {}
+			int main(int n) {
+				int x = 0, a[2];
+				while (x < n) {
+					if (x < 4) {
+						x = 2;
+						break;
+					}
+					a[x] = -n;
+					x += a[0];
+				}
+				f();
+				x = puts("hi\n");
+				return ext(a, x);
+			}
+		"#;
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			concat!(
+				"(extern int ext ((array a) n))\n",
+				"(func noinline void f ())\n",
+				"(func int main (n) (decl x 0) (decl (array a 2)) ",
+				"(while (< x n) (if (< x 4) (assign x 2) (break)) ",
+				"(assign (index a x) (- n)) (assign x (+ x (index a 0)))) ",
+				"(call f) (assign x (call puts \"hi\\n\")) (return (call ext a x)))\n",
+			),
+			sexpr(&parsed, &table)
+		);
+	}
+}
@@ -0,0 +1,153 @@
+//! AST simplification
+//!
+//! Drops statements that can never run before lowering to TAC, should only
+//! be ran after `analyzer::analyze`, as the dropped code isn't checked anymore.
+use super::{DirectValue, Expression, Program, Scope, Stmts};
+
+/// Drops `if (0)` statements, replaces `if (1) S` with `S`, `if (0) S else T`
+/// with `T` and truncates every scope after its first `return`. Any nonzero
+/// constant counts as 1.
+///
+/// The selected body of an `if` declaring variables stays in its own scope.
+pub fn simplify(program: &mut Program) {
+	for func in program.0.iter_mut() {
+		simplify_scope(&mut func.scope);
+	}
+}
+
+fn simplify_scope(scope: &mut Scope) {
+	let Scope(stmts, lines) = std::mem::replace(scope, Scope(Vec::new(), Vec::new()));
+	for (stmt, line) in stmts.into_iter().zip(lines) {
+		match stmt {
+			Stmts::If(Expression::DirectValue(DirectValue::Const(value)), body, otherwise) => {
+				let Some(mut body) = (if value != 0 { Some(body) } else { otherwise }) else {
+					continue;
+				};
+				simplify_scope(&mut body);
+				if body.0.iter().any(|stmt| matches!(stmt, Stmts::Decl(_))) {
+					let condition = Expression::DirectValue(DirectValue::Const(1));
+					scope.0.push(Stmts::If(condition, body, None));
+					scope.1.push(line);
+				} else {
+					scope.0.append(&mut body.0);
+					scope.1.append(&mut body.1);
+				}
+			}
+			Stmts::If(expr, mut body, mut otherwise) => {
+				simplify_scope(&mut body);
+				if let Some(otherwise) = &mut otherwise {
+					simplify_scope(otherwise);
+				}
+				scope.0.push(Stmts::If(expr, body, otherwise));
+				scope.1.push(line);
+			}
+			Stmts::While(expr, mut body) => {
+				simplify_scope(&mut body);
+				scope.0.push(Stmts::While(expr, body));
+				scope.1.push(line);
+			}
+			Stmts::DoWhile(mut body, expr) => {
+				simplify_scope(&mut body);
+				scope.0.push(Stmts::DoWhile(body, expr));
+				scope.1.push(line);
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				mut body,
+			} => {
+				simplify_scope(&mut body);
+				scope.0.push(Stmts::For {
+					init,
+					cond,
+					step,
+					body,
+				});
+				scope.1.push(line);
+			}
+			stmt => {
+				scope.0.push(stmt);
+				scope.1.push(line);
+			}
+		}
+		if matches!(scope.0.last(), Some(Stmts::Return(_))) {
+			break;
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::{parse, sexpr::sexpr},
+	};
+
+	#[allow(dead_code)]
+	fn simplified(source: &str) -> String {
+		let (mut parsed, table) = parse(tokenize(source).unwrap()).unwrap();
+		simplify(&mut parsed);
+		sexpr(&parsed, &table)
+	}
+
+	#[test]
+	fn if_zero() {
+		assert_eq!(
+			"(func int main (n) (return n))\n",
+			simplified("int main(int n) { if (0) { n = 1; } return n; }")
+		);
+	}
+
+	#[test]
+	fn if_nonzero() {
+		assert_eq!(
+			"(func int main (n) (assign n 1) (return n))\n",
+			simplified("int main(int n) { if (-2) { n = 1; } return n; }")
+		);
+		// `x` stays local to the `if`
+		assert_eq!(
+			"(func int main (n) (if 1 (decl x 1) (assign n x)) (return n))\n",
+			simplified("int main(int n) { if (1) { int x = 1; n = x; } return n; }")
+		);
+	}
+
+	#[test]
+	fn constant_else() {
+		assert_eq!(
+			"(func int main (n) (assign n 2) (return n))\n",
+			simplified("int main(int n) { if (0) { n = 1; } else { n = 2; } return n; }")
+		);
+		assert_eq!(
+			"(func int main (n) (assign n 1) (return n))\n",
+			simplified("int main(int n) { if (3) n = 1; else n = 2; return n; }")
+		);
+		assert_eq!(
+			"(func int main (n) (if n (return 1) (else (assign n 2))) (return n))\n",
+			simplified("int main(int n) { if (n) { return 1; n = 3; } else n = 2; return n; }")
+		);
+	}
+
+	#[test]
+	fn after_return() {
+		assert_eq!(
+			"(func int main (n) (while n (return 1)) (return 0))\n",
+			simplified("int main(int n) { while (n) { return 1; n = 0; } return 0; n = 1; }")
+		);
+		// A `return` spliced out of an `if (1)` ends the enclosing scope
+		assert_eq!(
+			"(func int main (n) (assign n 1) (return n))\n",
+			simplified("int main(int n) { if (1) { n = 1; return n; } n = 2; return 0; }")
+		);
+	}
+
+	#[test]
+	fn lines() {
+		let test_program = "int main(int n) {\n\tif (1) {\n\t\tn = 1;\n\t}\n\treturn n;\n}";
+		let (mut parsed, _) = parse(tokenize(test_program).unwrap()).unwrap();
+		simplify(&mut parsed);
+		assert_eq!(vec![3, 5], parsed.0[0].scope().1);
+	}
+}
@@ -0,0 +1,213 @@
+//! Preprocessor
+//!
+//! Call `preprocessor::preprocess` on the source code before handing it to
+//! `lexer::tokenize`. Only supports integer object-like macros:
+//! ```c
+//! #define NAME 10
+//! ```
+//! Directive lines are replaced with empty lines so line numbers are preserved.
+//! Names are substituted outside of string literals and comments, a negative
+//! value being separated from a preceding `-` so the two don't lex as `--`.
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreprocessError {
+	/// Tuple struct of the line number and the offending directive
+	UnknownDirective(usize, String),
+	/// `#define` not of the form `#define NAME value` at the line number
+	MalformedDefine(usize),
+	/// Tuple struct of the line number and the name defined again with a
+	/// different value
+	Redefinition(usize, String),
+}
+
+fn is_identifier_symbol(char: char) -> bool {
+	char.is_alphanumeric() || char == '_'
+}
+
+pub fn preprocess(source: &str) -> Result<String, PreprocessError> {
+	let mut macros: HashMap<String, String> = HashMap::new();
+	let mut res = String::with_capacity(source.len());
+	// Within a `/* */` comment spanning several lines
+	let mut in_comment = false;
+	for (i, line) in source.split_inclusive('\n').enumerate() {
+		let line_number = i + 1;
+		if !in_comment && let Some(directive) = line.trim_start().strip_prefix('#') {
+			let mut words = directive.split_whitespace();
+			match words.next() {
+				Some("define") => (),
+				other => {
+					return Err(PreprocessError::UnknownDirective(
+						line_number,
+						other.unwrap_or_default().to_string(),
+					));
+				}
+			}
+			let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+				return Err(PreprocessError::MalformedDefine(line_number));
+			};
+			let is_name = name.starts_with(|c: char| !c.is_numeric())
+				&& name.chars().all(is_identifier_symbol);
+			let digits = value.strip_prefix('-').unwrap_or(value);
+			let is_integer = digits.starts_with(|c: char| c.is_numeric())
+				&& digits.chars().all(char::is_alphanumeric);
+			if !is_name || !is_integer {
+				return Err(PreprocessError::MalformedDefine(line_number));
+			}
+			if let Some(prev_value) = macros.insert(name.to_string(), value.to_string())
+				&& prev_value != value
+			{
+				return Err(PreprocessError::Redefinition(line_number, name.to_string()));
+			}
+			res.extend(line.strip_suffix('\n').map(|_| '\n'));
+			continue;
+		}
+		substitute(line, &macros, &mut in_comment, &mut res);
+	}
+	Ok(res)
+}
+
+/// Appends `line` to `res` with the macros substituted, `in_comment` tracking
+/// whether a `/* */` comment is left open at the end of the line
+fn substitute(
+	line: &str,
+	macros: &HashMap<String, String>,
+	in_comment: &mut bool,
+	res: &mut String,
+) {
+	let mut chars = line.chars().peekable();
+	while let Some(char) = chars.next() {
+		res.push(char);
+		if *in_comment {
+			if char == '*' && chars.next_if_eq(&'/').is_some() {
+				res.push('/');
+				*in_comment = false;
+			}
+			continue;
+		}
+		match char {
+			'/' if chars.next_if_eq(&'/').is_some() => {
+				res.push('/');
+				res.extend(chars.by_ref());
+			}
+			'/' if chars.next_if_eq(&'*').is_some() => {
+				res.push('*');
+				*in_comment = true;
+			}
+			'"' => {
+				while let Some(char) = chars.next() {
+					res.push(char);
+					match char {
+						'\\' => res.extend(chars.next()),
+						'"' => break,
+						_ => (),
+					}
+				}
+			}
+			// Substitute whole identifiers only, so `SIZE` doesn't match `SIZES`
+			char if is_identifier_symbol(char) => {
+				res.pop();
+				let mut word = char.to_string();
+				while let Some(char) = chars.next_if(|&c| is_identifier_symbol(c)) {
+					word.push(char);
+				}
+				let value = macros.get(&word).unwrap_or(&word);
+				if value.starts_with('-') && res.ends_with('-') {
+					res.push(' ');
+				}
+				res.push_str(value);
+			}
+			_ => (),
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse};
+
+	#[test]
+	fn array_size_and_expression() {
+		let source = r"#define SIZE 4
+#define OFFSET -1
+int main(int n) {
+	int a[SIZE], SIZES;
+	SIZES = n + OFFSET;
+	return SIZES;
+}
+";
+		let expected = r"
+
+int main(int n) {
+	int a[4], SIZES;
+	SIZES = n + -1;
+	return SIZES;
+}
+";
+		let preprocessed = preprocess(source).unwrap();
+		assert_eq!(expected, preprocessed);
+		assert!(parse(tokenize(&preprocessed).unwrap()).is_ok());
+	}
+
+	#[test]
+	fn token_boundaries() {
+		let source = "#define N -1\nint main(int x) {\n\tx = 1-N;\n\treturn x-N;\n}\n";
+		let preprocessed = preprocess(source).unwrap();
+		assert_eq!(
+			"\nint main(int x) {\n\tx = 1- -1;\n\treturn x- -1;\n}\n",
+			preprocessed
+		);
+		assert!(parse(tokenize(&preprocessed).unwrap()).is_ok());
+	}
+
+	#[test]
+	fn strings_and_comments() {
+		let source = r#"#define SIZE 3
+int puts(int s[]);
+int main() {
+	int r; // SIZE
+	r = puts("SIZE \" SIZE"); /* SIZE
+	SIZE */ r = SIZE;
+	return r;
+}
+"#;
+		let expected = r#"
+int puts(int s[]);
+int main() {
+	int r; // SIZE
+	r = puts("SIZE \" SIZE"); /* SIZE
+	SIZE */ r = 3;
+	return r;
+}
+"#;
+		assert_eq!(expected, preprocess(source).unwrap());
+		// A directive within a comment is left alone
+		assert_eq!(
+			Ok("/*\n#include <stdio.h>\n*/\n".to_string()),
+			preprocess("/*\n#include <stdio.h>\n*/\n")
+		);
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(Ok("\n".to_string()), preprocess("#define A 1\n#define A 1"));
+		assert_eq!(
+			Err(PreprocessError::Redefinition(2, "A".to_string())),
+			preprocess("#define A 1\n#define A 2\n")
+		);
+		assert_eq!(
+			Err(PreprocessError::MalformedDefine(1)),
+			preprocess("#define A(x) x\n")
+		);
+		assert_eq!(
+			Err(PreprocessError::MalformedDefine(1)),
+			preprocess("#define A b\n")
+		);
+		assert_eq!(
+			Err(PreprocessError::UnknownDirective(1, "include".to_string())),
+			preprocess("#include <stdio.h>\n")
+		);
+	}
+}
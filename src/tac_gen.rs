@@ -1,5 +1,25 @@
 //! Three Address Code Generation
-use crate::parser::{self, Decl, Program, Stmts};
+//!
+//! There is no boolean type, any nonzero `int` is true. `Ifz` branches when
+//! its operand is zero, whereas comparisons and `!` yield either 0 or 1.
+use std::{collections::HashSet, fmt, rc::Rc};
+
+use crate::{
+	analyzer, lexer,
+	parser::{self, Decl, Program, Stmts},
+};
+
+pub mod call_graph;
+pub mod fold;
+pub mod inline;
+pub mod licm;
+pub mod pass_stats;
+pub mod pipeline;
+pub mod verify;
+pub use fold::fold_constants;
+pub use inline::inline_leaf_functions;
+pub use licm::hoist_loop_invariants;
+pub use verify::{verify, verify_structured};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Ident {
@@ -7,14 +27,34 @@ pub enum Ident {
 	Binded(usize, usize),
 	/// Tuple struct with the index into the parameters vec
 	Parameter(usize),
+	/// Same as `Binded`, for a `static` variable shared by every call of the
+	/// function
+	Static(usize, usize),
+	/// String literal, indexing `Program::1`. Only ever pushed as an argument
+	Literal(usize),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Ident {
+	/// `name@scope_id` for a `Binded`, `static name@scope_id` for a `Static`,
+	/// `arg{index}` for a `Parameter` and `str{index}` for a `Literal`
+	pub fn describe(&self, ident_table: &parser::IdentNameTable) -> String {
+		Named(self, Some(&ident_table.0)).to_string()
+	}
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Operand {
 	Ident(Ident),
 	Temporary(usize),
 	Immediate(i32),
 }
+impl Operand {
+	/// Same as `Ident::describe`, with `t{id}` for a `Temporary` and the value
+	/// of an `Immediate`
+	pub fn describe(&self, ident_table: &parser::IdentNameTable) -> String {
+		Named(self, Some(&ident_table.0)).to_string()
+	}
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RValue {
@@ -23,6 +63,9 @@ pub enum RValue {
 	Assignment(Operand),
 	Operation(Operand, parser::BinaryOperation, Operand),
 	ArrayAccess(Ident, Operand),
+	BitwiseNot(Operand),
+	/// Arithmetic negation, wrapping on overflow
+	Negate(Operand),
 }
 
 type AddressOffset = usize;
@@ -37,33 +80,276 @@ pub enum Instruction {
 	Push(Operand),
 	Goto(isize),
 }
+impl Instruction {
+	/// Renders the instruction using the identifier `names`, if available
+	pub fn named<'a>(&'a self, names: Option<&'a [String]>) -> impl fmt::Display + 'a {
+		Named(self, names)
+	}
+	/// Every `Operand` of the instruction, both read and written
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Instruction::ArrayAlloc(..) | Instruction::Goto(_) => Vec::new(),
+			Instruction::ArrayWrite(_, index, r_val) => vec![index, r_val],
+			Instruction::Ifz(op, _) | Instruction::Return(op) | Instruction::Push(op) => vec![op],
+			Instruction::Expression(lhs, r_value) => {
+				let mut res = vec![lhs];
+				match r_value {
+					RValue::FuncCall(..) => (),
+					RValue::Assignment(op) | RValue::BitwiseNot(op) | RValue::Negate(op) => {
+						res.push(op)
+					}
+					RValue::Operation(l_op, _, r_op) => {
+						res.push(l_op);
+						res.push(r_op);
+					}
+					RValue::ArrayAccess(_, index) => res.push(index),
+				}
+				res
+			}
+		}
+	}
+	/// The array `Ident` operated upon, if any
+	pub fn array_mut(&mut self) -> Option<&mut Ident> {
+		match self {
+			Instruction::ArrayAlloc(ident, _)
+			| Instruction::ArrayWrite(ident, ..)
+			| Instruction::Expression(_, RValue::ArrayAccess(ident, _)) => Some(ident),
+			_ => None,
+		}
+	}
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Function {
 	pub id: usize,
+	/// Identifier names used only for `Display`, falls back to the indices
+	/// when absent
+	pub names: Option<Rc<Vec<String>>>,
+	/// Declaration the function was generated from, `int fib(int n)`,
+	/// attached along with the names
+	pub signature: Option<String>,
 	pub instructions: Vec<Instruction>,
+	/// Source line every instruction originates from, empty when unknown
+	pub lines: Vec<usize>,
+	/// Every `Ident::Static` of the function with its initial value
+	pub statics: Vec<(Ident, i32)>,
+	pub inlining: parser::Inlining,
+	/// Index and bytes, without the NUL terminator, of every `Ident::Literal`
+	/// the function passes
+	pub literals: Vec<(usize, Vec<u8>)>,
+	/// Id of every external function called, which follow the System V
+	/// calling convention rather than ezc's own, and whether it's variadic
+	pub externals: Vec<(usize, bool)>,
+}
+impl fmt::Display for Function {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let names = self.names.as_ref().map(|names| names.as_slice());
+		match names.and_then(|names| names.get(self.id)) {
+			Some(name) => writeln!(f, "{name}:")?,
+			None => writeln!(f, "F{}:", self.id)?,
+		}
+		for (i, instruction) in self.instructions.iter().enumerate() {
+			writeln!(f, "\t{i}: {}", Named(instruction, names))?;
+		}
+		Ok(())
+	}
+}
+
+/// Renders a TAC item using the identifier names, if available
+struct Named<'a, T>(&'a T, Option<&'a [String]>);
+impl fmt::Display for Named<'_, Ident> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self.0 {
+			Ident::Binded(name_index, scope_id) => {
+				match self.1.and_then(|names| names.get(name_index)) {
+					Some(name) => write!(f, "{name}@{scope_id}"),
+					None => write!(f, "I{name_index}@{scope_id}"),
+				}
+			}
+			Ident::Parameter(index) => write!(f, "arg{index}"),
+			Ident::Literal(index) => write!(f, "str{index}"),
+			Ident::Static(name_index, scope_id) => {
+				write!(f, "static ")?;
+				Named(&Ident::Binded(name_index, scope_id), self.1).fmt(f)
+			}
+		}
+	}
+}
+impl fmt::Display for Named<'_, Operand> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.0 {
+			Operand::Ident(ident) => Named(ident, self.1).fmt(f),
+			Operand::Temporary(id) => write!(f, "t{id}"),
+			Operand::Immediate(val) => write!(f, "{val}"),
+		}
+	}
+}
+impl fmt::Display for Named<'_, RValue> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let names = self.1;
+		match self.0 {
+			RValue::FuncCall(id, arg_count) => match names.and_then(|names| names.get(*id)) {
+				Some(name) => write!(f, "call {name}, {arg_count}"),
+				None => write!(f, "call F{id}, {arg_count}"),
+			},
+			RValue::Assignment(op) => Named(op, names).fmt(f),
+			RValue::Operation(lhs, op, rhs) => {
+				write!(f, "{} {op} {}", Named(lhs, names), Named(rhs, names))
+			}
+			RValue::ArrayAccess(ident, index) => {
+				write!(f, "{}[{}]", Named(ident, names), Named(index, names))
+			}
+			RValue::BitwiseNot(op) => write!(f, "~{}", Named(op, names)),
+			RValue::Negate(op) => write!(f, "-{}", Named(op, names)),
+		}
+	}
+}
+impl fmt::Display for Named<'_, Instruction> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let names = self.1;
+		match self.0 {
+			Instruction::ArrayAlloc(ident, size) => {
+				write!(f, "alloc {}[{size}]", Named(ident, names))
+			}
+			Instruction::ArrayWrite(ident, index, r_val) => write!(
+				f,
+				"{}[{}] = {}",
+				Named(ident, names),
+				Named(index, names),
+				Named(r_val, names)
+			),
+			Instruction::Ifz(op, offset) => write!(f, "ifz {} goto +{offset}", Named(op, names)),
+			Instruction::Expression(lhs, r_value) => {
+				write!(f, "{} = {}", Named(lhs, names), Named(r_value, names))
+			}
+			Instruction::Return(op) => write!(f, "return {}", Named(op, names)),
+			Instruction::Push(op) => write!(f, "push {}", Named(op, names)),
+			Instruction::Goto(offset) => write!(f, "goto {offset:+}"),
+		}
+	}
+}
+
+/// Same as `generate`, but attaches the identifier names to every `Function`
+/// for self-describing dumps
+pub fn generate_named(program: &Program, ident_table: &parser::IdentNameTable) -> Vec<Function> {
+	let names = Rc::new(ident_table.0.clone());
+	let mut functions = generate(program, ident_table.0.len());
+	for function in functions.iter_mut() {
+		function.names = Some(names.clone());
+		function.signature = program
+			.0
+			.iter()
+			.find(|func| func.name().table_index == function.id && !func.is_external())
+			.map(|func| func.signature(ident_table));
+	}
+	functions
 }
 
 /// Assumes the program is semantically sound, should only be ran after
 /// `analyzer::analyze` returns `Ok(())`
+/// External functions have no body and hence no `Function`
 pub fn generate(program: &Program, ident_count: usize) -> Vec<Function> {
 	program
 		.0
 		.iter()
+		.filter(|function| !function.is_external())
 		.map(|function| {
 			let mut generator = TACGen::new(ident_count, function.parameter_table_idx());
+			let (mut instructions, mut lines) = generator.generate_scope(function.scope());
+			let statics = generator.statics;
+			let mut literals: Vec<(usize, Vec<u8>)> = Vec::new();
+			for instruction in &instructions {
+				if let Instruction::Push(Operand::Ident(Ident::Literal(index))) = *instruction
+					&& !literals.iter().any(|&(seen, _)| seen == index)
+				{
+					literals.push((index, lexer::unescape(&program.1[index])));
+				}
+			}
+			let mut externals = Vec::new();
+			for instruction in &instructions {
+				if let Instruction::Expression(_, RValue::FuncCall(id, _)) = *instruction
+					&& !externals.iter().any(|&(seen, _)| seen == id)
+					&& let Some(func) = program
+						.0
+						.iter()
+						.find(|func| func.name().table_index == id && func.is_external())
+				{
+					externals.push((id, func.is_variadic()));
+				}
+			}
+			// Falling off the end of the body returns, so every function
+			// leaves through an explicit `Return`
+			if falls_through(&instructions) {
+				instructions.push(Instruction::Return(Operand::Immediate(0)));
+				lines.push(
+					function
+						.scope()
+						.1
+						.last()
+						.copied()
+						.unwrap_or(function.name().line_number()),
+				);
+			}
 			Function {
 				id: function.name().table_index,
-				instructions: generator.generate_scope(function.scope()),
+				names: None,
+				signature: None,
+				instructions,
+				lines,
+				statics,
+				inlining: function.inlining(),
+				literals,
+				externals,
 			}
 		})
 		.collect()
 }
 
+/// Whether control can reach past the last instruction
+fn falls_through(instructions: &[Instruction]) -> bool {
+	let len = instructions.len();
+	!matches!(instructions.last(), Some(Instruction::Return(_)))
+		|| instructions
+			.iter()
+			.enumerate()
+			.any(|(i, instruction)| match instruction {
+				Instruction::Goto(offset) => i as isize + offset == len as isize,
+				Instruction::Ifz(_, offset) => i + offset == len,
+				_ => false,
+			})
+}
+
+/// Temporaries below this are used by statements to hold results of
+/// expressions, scratch temporaries within an expression start from here
+const SCRATCH_TEMPORARY: usize = 2;
+
+/// Offsets of the `Goto`s lowered from `break` and `continue`, replaced once
+/// the enclosing loop is generated
+const PENDING_BREAK: isize = isize::MAX;
+const PENDING_CONTINUE: isize = isize::MIN;
+
+/// Replaces the pending `break` and `continue` jumps of a loop's `body` with
+/// ones landing at the given offsets from the start of the body. Those of
+/// nested loops are already resolved
+fn resolve_jumps(body: &mut [Instruction], break_target: isize, continue_target: isize) {
+	for (i, instruction) in body.iter_mut().enumerate() {
+		if let Instruction::Goto(offset) = instruction {
+			if *offset == PENDING_BREAK {
+				*offset = break_target - i as isize;
+			} else if *offset == PENDING_CONTINUE {
+				*offset = continue_target - i as isize;
+			}
+		}
+	}
+}
+
 struct TACGen {
 	parameters: Vec<usize>,
 	scope_id: usize,
 	scope_map: Vec<Vec<usize>>,
+	/// `name_index` and `scope_id` of the `static` variables declared so far
+	static_decls: HashSet<(usize, usize)>,
+	statics: Vec<(Ident, i32)>,
 }
 impl TACGen {
 	fn new(ident_count: usize, parameters: Vec<usize>) -> Self {
@@ -73,21 +359,32 @@ impl TACGen {
 			// TODO: Has rustc automatically pre-allocated required memory or
 			// is the vector being resized
 			scope_map: (0..ident_count).map(|_| Vec::new()).collect(),
+			static_decls: HashSet::new(),
+			statics: Vec::new(),
 		}
 	}
-	fn end_scope(&mut self) {
+	/// Enters a nested scope, returning its id to be passed to `end_scope`
+	fn begin_scope(&mut self) -> usize {
+		self.scope_id += 1;
+		self.scope_id
+	}
+	/// Forgets every declaration made since the scope `start` began, those of
+	/// the scopes nested within it included
+	fn end_scope(&mut self, start: usize) {
 		self.scope_id -= 1;
-		self.scope_map
-			.iter_mut()
-			.filter(|i| i.last() == Some(&self.scope_id))
-			.for_each(|i| {
-				i.pop();
-			})
+		for scopes in self.scope_map.iter_mut() {
+			while scopes.last().is_some_and(|&scope_id| scope_id >= start) {
+				scopes.pop();
+			}
+		}
 	}
 	fn generate_ident(&self, ident: &parser::Ident) -> Ident {
 		let name_index = ident.table_index;
-		if let Some(scope_id) = self.scope_map[name_index].last() {
-			Ident::Binded(name_index, *scope_id)
+		if let Some(&scope_id) = self.scope_map[name_index].last() {
+			match self.static_decls.contains(&(name_index, scope_id)) {
+				true => Ident::Static(name_index, scope_id),
+				false => Ident::Binded(name_index, scope_id),
+			}
 		} else {
 			Ident::Parameter(
 				self.parameters
@@ -97,124 +394,395 @@ impl TACGen {
 			)
 		}
 	}
-	fn generate_assignment(&mut self, lhs: Operand, rhs: &parser::Expression) -> Vec<Instruction> {
-		use parser::{DirectValue, Expression};
-		let to_operand = |direct_value: &DirectValue| -> Operand {
-			match direct_value {
-				DirectValue::Ident(ident) => Operand::Ident(self.generate_ident(ident)),
-				DirectValue::Const(value) => Operand::Immediate(*value),
+	/// Lowers the `DirectValue` into an `Operand`, anything but an identifier
+	/// or a constant is evaluated into the next `scratch` temporary, only live
+	/// till the end of the enclosing assignment
+	fn generate_operand(
+		&self,
+		direct_value: &parser::DirectValue,
+		scratch: &mut usize,
+		res: &mut Vec<Instruction>,
+	) -> Operand {
+		use parser::DirectValue;
+		let r_value = match direct_value {
+			DirectValue::Ident(ident) => return Operand::Ident(self.generate_ident(ident)),
+			DirectValue::Const(value) => return Operand::Immediate(*value),
+			DirectValue::Literal(index) => return Operand::Ident(Ident::Literal(*index)),
+			DirectValue::ArrayElement(ident, index) => {
+				let index = self.generate_nested(index, scratch, res);
+				RValue::ArrayAccess(self.generate_ident(ident), index)
 			}
 		};
+		let temporary = Operand::Temporary(*scratch);
+		*scratch += 1;
+		res.push(Instruction::Expression(temporary, r_value));
+		temporary
+	}
+	/// Lowers an operand of an `Expression::Binary` or `Expression::Unary`,
+	/// an argument, index or value of a ternary into an `Operand`, a nested
+	/// operation being evaluated into the next `scratch` temporary
+	fn generate_nested(
+		&self,
+		expression: &parser::Expression,
+		scratch: &mut usize,
+		res: &mut Vec<Instruction>,
+	) -> Operand {
+		if let parser::Expression::DirectValue(direct_value) = expression {
+			return self.generate_operand(direct_value, scratch, res);
+		}
+		let r_value = self.generate_operation(expression, scratch, res);
+		let temporary = Operand::Temporary(*scratch);
+		*scratch += 1;
+		res.push(Instruction::Expression(temporary, r_value));
+		temporary
+	}
+	/// `RValue` of a direct value, unary or binary operation, with the
+	/// operands evaluated into `res`
+	fn generate_operation(
+		&self,
+		expression: &parser::Expression,
+		scratch: &mut usize,
+		res: &mut Vec<Instruction>,
+	) -> RValue {
+		use parser::{BinaryOperation, Expression, UnaryOperation};
+		match expression {
+			Expression::DirectValue(direct_value) => {
+				RValue::Assignment(self.generate_operand(direct_value, scratch, res))
+			}
+			Expression::Binary(
+				l_value,
+				op @ (BinaryOperation::LogicalAnd | BinaryOperation::LogicalOr),
+				r_value,
+			) => self.short_circuit(l_value, *op, r_value, scratch, res),
+			Expression::Binary(l_value, op, r_value) => RValue::Operation(
+				self.generate_nested(l_value, scratch, res),
+				*op,
+				self.generate_nested(r_value, scratch, res),
+			),
+			Expression::Unary(op, operand) => {
+				let operand = self.generate_nested(operand, scratch, res);
+				match op {
+					UnaryOperation::Negate => RValue::Negate(operand),
+					UnaryOperation::Complement => RValue::BitwiseNot(operand),
+					// Comparisons yield either 0 or 1
+					UnaryOperation::Not => RValue::Operation(
+						operand,
+						parser::BinaryOperation::Equal,
+						Operand::Immediate(0),
+					),
+				}
+			}
+			_ => unreachable!("Operands are direct values, unary or binary operations"),
+		}
+	}
+	/// `lhs && rhs` or `lhs || rhs` selected into a temporary as 0 or 1, `rhs`
+	/// only evaluated when `lhs` doesn't decide the result. Like a ternary,
+	/// the only jump is a forward `Ifz` within the expression
+	fn short_circuit(
+		&self,
+		lhs: &parser::Expression,
+		operation: parser::BinaryOperation,
+		rhs: &parser::Expression,
+		scratch: &mut usize,
+		res: &mut Vec<Instruction>,
+	) -> RValue {
+		use parser::BinaryOperation;
+		let is_and = operation == BinaryOperation::LogicalAnd;
+		let result = Operand::Temporary(*scratch);
+		let flag = Operand::Temporary(*scratch + 1);
+		*scratch += 2;
+		// The result when `lhs` decides it
+		res.push(Instruction::Expression(
+			result,
+			RValue::Assignment(Operand::Immediate(!is_and as i32)),
+		));
+		let lhs = self.generate_nested(lhs, scratch, res);
+		let undecided = match is_and {
+			true => BinaryOperation::NotEqual,
+			false => BinaryOperation::Equal,
+		};
+		res.push(Instruction::Expression(
+			flag,
+			RValue::Operation(lhs, undecided, Operand::Immediate(0)),
+		));
+		let mut rhs_block = Vec::new();
+		let rhs = self.generate_nested(rhs, scratch, &mut rhs_block);
+		rhs_block.push(Instruction::Expression(
+			result,
+			RValue::Operation(rhs, BinaryOperation::NotEqual, Operand::Immediate(0)),
+		));
+		res.push(Instruction::Ifz(flag, rhs_block.len() + 1));
+		res.append(&mut rhs_block);
+		RValue::Assignment(result)
+	}
+	fn generate_assignment(&mut self, lhs: Operand, rhs: &parser::Expression) -> Vec<Instruction> {
+		use parser::Expression;
+		let mut scratch = SCRATCH_TEMPORARY;
 		let mut res = Vec::new();
 		let r_value = match rhs {
 			Expression::FuncCall(func, argument) => {
-				for direct_value in argument.iter().rev() {
-					res.push(Instruction::Push(to_operand(direct_value)));
+				// Every argument is evaluated left to right into its own
+				// temporary before the first push, identifiers and constants
+				// can't change in between so they're pushed as they are
+				let operands: Vec<Operand> = argument
+					.iter()
+					.map(|argument| self.generate_nested(argument, &mut scratch, &mut res))
+					.collect();
+				for operand in operands.into_iter().rev() {
+					res.push(Instruction::Push(operand));
 				}
 				RValue::FuncCall(func.table_index, argument.len())
 			}
-			Expression::DirectValue(r_value) => RValue::Assignment(to_operand(r_value)),
-			Expression::Binary(l_value, op, r_value) => {
-				RValue::Operation(to_operand(l_value), *op, to_operand(r_value))
+			Expression::DirectValue(r_value) => {
+				RValue::Assignment(self.generate_operand(r_value, &mut scratch, &mut res))
+			}
+			Expression::Binary(..) | Expression::Unary(..) => {
+				self.generate_operation(rhs, &mut scratch, &mut res)
 			}
 			Expression::ArrayAccess(ident, index) => {
-				RValue::ArrayAccess(self.generate_ident(ident), to_operand(index))
+				let index = self.generate_nested(index, &mut scratch, &mut res);
+				RValue::ArrayAccess(self.generate_ident(ident), index)
+			}
+			// Selected into a temporary, as `lhs` may be read by the condition
+			// or the values
+			Expression::Ternary(condition, then, otherwise) => {
+				let result = Operand::Temporary(scratch);
+				let flag = Operand::Temporary(scratch + 1);
+				scratch += 2;
+				let condition = self.generate_operation(condition, &mut scratch, &mut res);
+				let select = |value: &Expression, scratch: &mut usize| {
+					let mut block = Vec::new();
+					let operand = self.generate_nested(value, scratch, &mut block);
+					block.push(Instruction::Expression(result, RValue::Assignment(operand)));
+					block
+				};
+				let mut then_block = select(then, &mut scratch);
+				let mut else_block = select(otherwise, &mut scratch);
+				if else_block.len() == 1 {
+					// A plain identifier or constant is read unconditionally,
+					// leaving a single jump over the other value
+					res.append(&mut else_block);
+					res.push(Instruction::Expression(flag, condition));
+					res.push(Instruction::Ifz(flag, then_block.len() + 1));
+					res.append(&mut then_block);
+				} else {
+					let inverse = Operand::Temporary(scratch);
+					res.push(Instruction::Expression(flag, condition));
+					res.push(Instruction::Ifz(flag, then_block.len() + 1));
+					res.append(&mut then_block);
+					res.push(Instruction::Expression(
+						inverse,
+						RValue::Operation(
+							flag,
+							parser::BinaryOperation::Equal,
+							Operand::Immediate(0),
+						),
+					));
+					res.push(Instruction::Ifz(inverse, else_block.len() + 1));
+					res.append(&mut else_block);
+				}
+				RValue::Assignment(result)
 			}
 		};
 		res.push(Instruction::Expression(lhs, r_value));
 		res
 	}
-	fn generate_scope(&mut self, scope: &parser::Scope) -> Vec<Instruction> {
-		const PENDING_BREAK: isize = isize::MAX;
-		const PENDING_CONTINUE: isize = isize::MIN;
+	/// Returns the instructions along with the source line of each
+	fn generate_scope(&mut self, scope: &parser::Scope) -> (Vec<Instruction>, Vec<usize>) {
 		let mut instructions = Vec::new();
-		for stmt in scope.0.iter() {
-			let mut generated_instructions = match stmt {
-				Stmts::Decl(decls) => decls
-					.iter()
-					.flat_map(|decl| match decl {
-						Decl::Variable { name, init_val } => {
-							self.scope_map[name.table_index].push(self.scope_id);
-							if let Some(expr) = init_val {
-								self.generate_assignment(
-									Operand::Ident(self.generate_ident(name)),
-									expr,
-								)
-							} else {
-								Vec::new()
-							}
-						}
-						Decl::Array { name, size } => {
-							self.scope_map[name.table_index].push(self.scope_id);
-							vec![Instruction::ArrayAlloc(self.generate_ident(name), *size)]
-						}
-					})
-					.collect(),
-				Stmts::Assignment(ident, expr) => {
-					self.generate_assignment(Operand::Ident(self.generate_ident(ident)), expr)
-				}
-				Stmts::ArrayAssignment(ident, index, r_value) => {
-					let mut res = Vec::new();
-					res.append(&mut self.generate_assignment(Operand::Temporary(0), index));
-					res.append(&mut self.generate_assignment(Operand::Temporary(1), r_value));
-					res.push(Instruction::ArrayWrite(
-						self.generate_ident(ident),
-						Operand::Temporary(0),
-						Operand::Temporary(1),
-					));
-					res
-				}
-				Stmts::While(expr, scope) => {
-					self.scope_id += 1;
-					let mut sub_scope = self.generate_scope(scope);
-					let scope_len = sub_scope.len();
-					sub_scope
-						.iter_mut()
-						.enumerate()
-						.filter_map(|(i, inst)| {
-							if let Instruction::Goto(offset) = inst {
-								Some((i, offset))
-							} else {
-								None
-							}
-						})
-						.for_each(|(i, offset)| {
-							if *offset == PENDING_BREAK {
-								*offset = (scope_len - i) as isize + 1;
-							} else if *offset == PENDING_CONTINUE {
-								*offset = -(i as isize);
-							}
-						});
-					let mut while_block = self.generate_assignment(Operand::Temporary(0), expr);
-					while_block.push(Instruction::Ifz(Operand::Temporary(0), sub_scope.len() + 2));
-					let loop_back_instruction = Instruction::Goto(-(sub_scope.len() as isize) - 2);
-					while_block.append(&mut sub_scope);
-					while_block.push(loop_back_instruction);
-					self.end_scope();
-					while_block
-				}
-				Stmts::Return(expr) => {
-					let mut res = self.generate_assignment(Operand::Temporary(0), expr);
-					res.push(Instruction::Return(Operand::Temporary(0)));
-					res
-				}
-				Stmts::If(expr, scope) => {
-					self.scope_id += 1;
-					let mut sub_scope = self.generate_scope(scope);
-					let mut if_block = self.generate_assignment(Operand::Temporary(0), expr);
-
-					if_block.push(Instruction::Ifz(Operand::Temporary(0), sub_scope.len() + 1));
-					if_block.append(&mut sub_scope);
-					self.end_scope();
-					if_block
-				}
-				Stmts::Break => vec![Instruction::Goto(PENDING_BREAK)],
-				Stmts::Continue => vec![Instruction::Goto(PENDING_CONTINUE)],
-			};
-			instructions.append(&mut generated_instructions);
+		let mut lines = Vec::new();
+		for (stmt, &line) in scope.0.iter().zip(scope.1.iter()) {
+			let (mut stmt_instructions, mut stmt_lines) = self.generate_stmt(stmt, line);
+			instructions.append(&mut stmt_instructions);
+			lines.append(&mut stmt_lines);
 		}
 		self.scope_id += 1;
-		instructions
+		(instructions, lines)
+	}
+	/// Instructions of a single statement along with the source line of each
+	fn generate_stmt(&mut self, stmt: &Stmts, line: usize) -> (Vec<Instruction>, Vec<usize>) {
+		// Position and lines of the nested scope within the statement,
+		// the rest is attributed to the statement's own line
+		let mut sub_scope_lines = (0, Vec::new());
+		let instructions = match stmt {
+			Stmts::Decl(decls) => decls
+				.iter()
+				.flat_map(|decl| match decl {
+					Decl::Variable {
+						name,
+						init_val,
+						is_static: true,
+					} => {
+						self.scope_map[name.table_index].push(self.scope_id);
+						self.static_decls.insert((name.table_index, self.scope_id));
+						// Initialized once in the storage rather than on every call
+						let value = init_val.as_ref().map_or(Some(0), |expr| {
+							analyzer::const_eval(expr, &analyzer::ConstEnv::new())
+						});
+						self.statics.push((
+							self.generate_ident(name),
+							value.expect("Static initializers are constant"),
+						));
+						Vec::new()
+					}
+					Decl::Variable { name, init_val, .. } => {
+						self.scope_map[name.table_index].push(self.scope_id);
+						if let Some(expr) = init_val {
+							self.generate_assignment(
+								Operand::Ident(self.generate_ident(name)),
+								expr,
+							)
+						} else {
+							Vec::new()
+						}
+					}
+					Decl::Array { name, size } => {
+						self.scope_map[name.table_index].push(self.scope_id);
+						vec![Instruction::ArrayAlloc(self.generate_ident(name), *size)]
+					}
+				})
+				.collect(),
+			Stmts::Assignment(ident, expr) => {
+				self.generate_assignment(Operand::Ident(self.generate_ident(ident)), expr)
+			}
+			Stmts::ArrayAssignment(ident, index, r_value) => {
+				let mut res = Vec::new();
+				res.append(&mut self.generate_assignment(Operand::Temporary(0), index));
+				res.append(&mut self.generate_assignment(Operand::Temporary(1), r_value));
+				res.push(Instruction::ArrayWrite(
+					self.generate_ident(ident),
+					Operand::Temporary(0),
+					Operand::Temporary(1),
+				));
+				res
+			}
+			Stmts::While(expr, scope) => {
+				// Declarations of the body are out of scope in the condition
+				let mut while_block = self.generate_assignment(Operand::Temporary(0), expr);
+				let start = self.begin_scope();
+				let (mut sub_scope, sub_lines) = self.generate_scope(scope);
+				let scope_len = sub_scope.len();
+				// `continue` and the loop back jump to the start of the
+				// condition, which may span several instructions
+				let condition_len = while_block.len() + 1;
+				resolve_jumps(
+					&mut sub_scope,
+					scope_len as isize + 1,
+					-(condition_len as isize),
+				);
+				while_block.push(Instruction::Ifz(Operand::Temporary(0), sub_scope.len() + 2));
+				let loop_back_instruction =
+					Instruction::Goto(-((condition_len + sub_scope.len()) as isize));
+				sub_scope_lines = (while_block.len(), sub_lines);
+				while_block.append(&mut sub_scope);
+				while_block.push(loop_back_instruction);
+				self.end_scope(start);
+				while_block
+			}
+			Stmts::DoWhile(scope, expr) => {
+				let start = self.begin_scope();
+				let (mut do_block, sub_lines) = self.generate_scope(scope);
+				self.end_scope(start);
+				let mut condition = self.generate_assignment(Operand::Temporary(0), expr);
+				// The condition at the bottom, which `continue` jumps to,
+				// loops back to the body while nonzero
+				let (body_len, condition_len) = (do_block.len(), condition.len());
+				resolve_jumps(
+					&mut do_block,
+					(body_len + condition_len) as isize + 2,
+					body_len as isize,
+				);
+				condition.push(Instruction::Ifz(Operand::Temporary(0), 2));
+				condition.push(Instruction::Goto(
+					-((body_len + condition_len + 1) as isize),
+				));
+				sub_scope_lines = (0, sub_lines);
+				do_block.append(&mut condition);
+				do_block
+			}
+			Stmts::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				// The loop is a scope of its own, holding the declarations
+				// of `init`
+				let start = self.begin_scope();
+				let mut for_block = match init {
+					Some(init) => self.generate_stmt(init, line).0,
+					None => Vec::new(),
+				};
+				let condition_start = for_block.len();
+				for_block.append(&mut self.generate_assignment(Operand::Temporary(0), cond));
+				let body_start = self.begin_scope();
+				let (mut sub_scope, sub_lines) = self.generate_scope(body);
+				self.end_scope(body_start);
+				let mut step_block = match step {
+					Some(step) => self.generate_stmt(step, line).0,
+					None => Vec::new(),
+				};
+				// `continue` jumps to the step, followed by the jump back to
+				// the condition
+				let (body_len, step_len) = (sub_scope.len(), step_block.len());
+				resolve_jumps(
+					&mut sub_scope,
+					(body_len + step_len) as isize + 1,
+					body_len as isize,
+				);
+				for_block.push(Instruction::Ifz(
+					Operand::Temporary(0),
+					body_len + step_len + 2,
+				));
+				let loop_len = for_block.len() - condition_start + body_len + step_len;
+				sub_scope_lines = (for_block.len(), sub_lines);
+				for_block.append(&mut sub_scope);
+				for_block.append(&mut step_block);
+				for_block.push(Instruction::Goto(-(loop_len as isize)));
+				self.end_scope(start);
+				for_block
+			}
+			Stmts::FuncCall(sig, arguments) => self.generate_assignment(
+				Operand::Temporary(0),
+				&parser::Expression::FuncCall(*sig, arguments.clone()),
+			),
+			Stmts::Return(Some(expr)) => {
+				let mut res = self.generate_assignment(Operand::Temporary(0), expr);
+				res.push(Instruction::Return(Operand::Temporary(0)));
+				res
+			}
+			Stmts::Return(None) => vec![Instruction::Return(Operand::Immediate(0))],
+			Stmts::If(expr, scope, otherwise) => {
+				let mut if_block = self.generate_assignment(Operand::Temporary(0), expr);
+				let start = self.begin_scope();
+				let (mut sub_scope, mut sub_lines) = self.generate_scope(scope);
+				self.end_scope(start);
+				let mut else_block = Vec::new();
+				if let Some(otherwise) = otherwise {
+					let start = self.begin_scope();
+					let (mut else_scope, mut else_lines) = self.generate_scope(otherwise);
+					self.end_scope(start);
+					// The body ends by jumping over the `else` body, which
+					// the `Ifz` lands on
+					sub_scope.push(Instruction::Goto(else_scope.len() as isize + 1));
+					else_block.append(&mut else_scope);
+					sub_lines.push(line);
+					sub_lines.append(&mut else_lines);
+				}
+				if_block.push(Instruction::Ifz(Operand::Temporary(0), sub_scope.len() + 1));
+				sub_scope.append(&mut else_block);
+				sub_scope_lines = (if_block.len(), sub_lines);
+				if_block.append(&mut sub_scope);
+				if_block
+			}
+			Stmts::Break => vec![Instruction::Goto(PENDING_BREAK)],
+			Stmts::Continue => vec![Instruction::Goto(PENDING_CONTINUE)],
+		};
+		let (sub_scope_start, sub_lines) = sub_scope_lines;
+		let mut lines = vec![line; sub_scope_start];
+		lines.extend(sub_lines);
+		lines.resize(instructions.len(), line);
+		(instructions, lines)
 	}
 }
 
@@ -237,6 +805,8 @@ mod test {
 		";
 		let tac_expected = vec![Function {
 			id: 0,
+			names: None,
+			signature: None,
 			instructions: vec![
 				Instruction::Expression(
 					Operand::Ident(Ident::Binded(2, 0)),
@@ -248,8 +818,13 @@ mod test {
 				),
 				Instruction::Return(Operand::Temporary(0)),
 			],
+			lines: vec![4, 5, 5],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
 		}];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 	}
 
@@ -258,15 +833,23 @@ mod test {
 		let test_program = "int main(int n) {if (1) {}}";
 		let tac_expected = vec![Function {
 			id: 0,
+			names: None,
+			signature: None,
 			instructions: vec![
 				Instruction::Expression(
 					Operand::Temporary(0),
 					RValue::Assignment(Operand::Immediate(1)),
 				),
 				Instruction::Ifz(Operand::Temporary(0), 1),
+				Instruction::Return(Operand::Immediate(0)),
 			],
+			lines: vec![1, 1, 1],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
 		}];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 
 		let test_program = r"
@@ -281,6 +864,8 @@ mod test {
 		";
 		let tac_expected = vec![Function {
 			id: 0,
+			names: None,
+			signature: None,
 			instructions: vec![
 				Instruction::Expression(
 					Operand::Ident(Ident::Binded(2, 0)),
@@ -305,8 +890,13 @@ mod test {
 				),
 				Instruction::Return(Operand::Temporary(0)),
 			],
+			lines: vec![4, 5, 5, 6, 8, 8],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
 		}];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 
 		let test_program = r"
@@ -326,6 +916,8 @@ mod test {
 			";
 		let tac_expected = vec![Function {
 			id: 0,
+			names: None,
+			signature: None,
 			instructions: vec![
 				Instruction::Expression(
 					Operand::Ident(Ident::Binded(2, 0)),
@@ -375,16 +967,404 @@ mod test {
 				),
 				Instruction::Return(Operand::Temporary(0)),
 			],
+			lines: vec![4, 5, 5, 6, 7, 7, 8, 9, 10, 13, 13],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
 		}];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 	}
 
+	#[test]
+	fn if_else() {
+		let test_program = r"
+			int main(int n) {
+				int x;
+				if (n) {
+					x = 1;
+				} else {
+					x = 2;
+				}
+				return x;
+			}
+		";
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Assignment(Operand::Ident(Ident::Parameter(0))),
+				),
+				Instruction::Ifz(Operand::Temporary(0), 3),
+				Instruction::Expression(
+					Operand::Ident(Ident::Binded(2, 0)),
+					RValue::Assignment(Operand::Immediate(1)),
+				),
+				Instruction::Goto(2),
+				Instruction::Expression(
+					Operand::Ident(Ident::Binded(2, 0)),
+					RValue::Assignment(Operand::Immediate(2)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Assignment(Operand::Ident(Ident::Binded(2, 0))),
+				),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![4, 4, 5, 4, 7, 9, 9],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+
+		// A `break` ending the body is followed by the jump over the `else`
+		let test_program = r"
+			int main(int n) {
+				while (n) {
+					if (n < 3) {
+						break;
+					} else if (n < 5) {
+						n = n - 2;
+						continue;
+					} else
+						n = n - 1;
+				}
+				return n;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let tac = generate(&parsed, table.0.len());
+		assert_eq!(Ok(()), verify_structured(&tac[0]));
+	}
+
+	#[test]
+	fn for_loop() {
+		let test_program = r"
+			int main(int n) {
+				int i;
+				for (i = 0; i < n; i += 1)
+					continue;
+				return i;
+			}
+		";
+		let i = Operand::Ident(Ident::Binded(2, 0));
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(i, RValue::Assignment(Operand::Immediate(0))),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Operation(
+						i,
+						BinaryOperation::Less,
+						Operand::Ident(Ident::Parameter(0)),
+					),
+				),
+				Instruction::Ifz(Operand::Temporary(0), 4),
+				// `continue` lands on the step rather than the condition
+				Instruction::Goto(1),
+				Instruction::Expression(
+					i,
+					RValue::Operation(i, BinaryOperation::Add, Operand::Immediate(1)),
+				),
+				Instruction::Goto(-4),
+				Instruction::Expression(Operand::Temporary(0), RValue::Assignment(i)),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![4, 4, 4, 5, 4, 4, 6, 6],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn do_while() {
+		let test_program = r"
+			int main(int n) {
+				do {
+					n -= 1;
+					if (n == 5)
+						continue;
+				} while (n > 0);
+				return n;
+			}
+		";
+		let n = Operand::Ident(Ident::Parameter(0));
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				// The body comes first, only then is the condition tested
+				Instruction::Expression(
+					n,
+					RValue::Operation(n, BinaryOperation::Sub, Operand::Immediate(1)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Operation(n, BinaryOperation::Equal, Operand::Immediate(5)),
+				),
+				Instruction::Ifz(Operand::Temporary(0), 2),
+				Instruction::Goto(1),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Operation(n, BinaryOperation::Greater, Operand::Immediate(0)),
+				),
+				Instruction::Ifz(Operand::Temporary(0), 2),
+				Instruction::Goto(-6),
+				Instruction::Expression(Operand::Temporary(0), RValue::Assignment(n)),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![4, 5, 5, 6, 3, 3, 3, 8, 8],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn short_circuit() {
+		let test_program = r"
+			int main(int a, int b) {
+				return a && b / 2;
+			}
+		";
+		let a = Operand::Ident(Ident::Parameter(0));
+		let b = Operand::Ident(Ident::Parameter(1));
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Temporary(2),
+					RValue::Assignment(Operand::Immediate(0)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(3),
+					RValue::Operation(a, BinaryOperation::NotEqual, Operand::Immediate(0)),
+				),
+				// The division is skipped along with the rest of the right operand
+				Instruction::Ifz(Operand::Temporary(3), 3),
+				Instruction::Expression(
+					Operand::Temporary(4),
+					RValue::Operation(b, BinaryOperation::Div, Operand::Immediate(2)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(2),
+					RValue::Operation(
+						Operand::Temporary(4),
+						BinaryOperation::NotEqual,
+						Operand::Immediate(0),
+					),
+				),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Assignment(Operand::Temporary(2)),
+				),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![3; 7],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn shifts() {
+		let test_program = r"
+			int main(int x) {
+				return x << 2;
+			}
+		";
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Operation(
+						Operand::Ident(Ident::Parameter(0)),
+						BinaryOperation::ShiftLeft,
+						Operand::Immediate(2),
+					),
+				),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![3, 3],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn negation() {
+		let test_program = r"
+			int main(int y, int z) {
+				int x;
+				x = -y;
+				x = -y + z;
+				return x;
+			}
+		";
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Ident(Ident::Binded(3, 0)),
+					RValue::Negate(Operand::Ident(Ident::Parameter(0))),
+				),
+				Instruction::Expression(
+					Operand::Temporary(2),
+					RValue::Negate(Operand::Ident(Ident::Parameter(0))),
+				),
+				Instruction::Expression(
+					Operand::Ident(Ident::Binded(3, 0)),
+					RValue::Operation(
+						Operand::Temporary(2),
+						BinaryOperation::Add,
+						Operand::Ident(Ident::Parameter(1)),
+					),
+				),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Assignment(Operand::Ident(Ident::Binded(3, 0))),
+				),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![4, 5, 5, 6, 6],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn array_operands() {
+		let test_program = r"
+			int main(int i, int j) {
+				int a[4], b[4];
+				a[i + 1] = b[j] * 2;
+				return a[i];
+			}
+		";
+		let (a, b) = (Ident::Binded(3, 0), Ident::Binded(4, 0));
+		let tac_expected = vec![Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::ArrayAlloc(a, 4),
+				Instruction::ArrayAlloc(b, 4),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Operation(
+						Operand::Ident(Ident::Parameter(0)),
+						BinaryOperation::Add,
+						Operand::Immediate(1),
+					),
+				),
+				Instruction::Expression(
+					Operand::Temporary(2),
+					RValue::ArrayAccess(b, Operand::Ident(Ident::Parameter(1))),
+				),
+				Instruction::Expression(
+					Operand::Temporary(1),
+					RValue::Operation(
+						Operand::Temporary(2),
+						BinaryOperation::Mul,
+						Operand::Immediate(2),
+					),
+				),
+				Instruction::ArrayWrite(a, Operand::Temporary(0), Operand::Temporary(1)),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::ArrayAccess(a, Operand::Ident(Ident::Parameter(0))),
+				),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			lines: vec![3, 3, 4, 4, 4, 4, 5, 5],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		}];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn describe() {
+		let table = parser::IdentNameTable(vec!["main".into(), "x".into()]);
+		assert_eq!("x@2", Ident::Binded(1, 2).describe(&table));
+		assert_eq!("arg1", Ident::Parameter(1).describe(&table));
+		assert_eq!("x@0", Operand::Ident(Ident::Binded(1, 0)).describe(&table));
+		assert_eq!("arg0", Operand::Ident(Ident::Parameter(0)).describe(&table));
+		assert_eq!("t3", Operand::Temporary(3).describe(&table));
+		assert_eq!("-7", Operand::Immediate(-7).describe(&table));
+		// Out of the table's range
+		assert_eq!("I5@0", Ident::Binded(5, 0).describe(&table));
+	}
+
+	#[test]
+	fn display() {
+		let test_program = r"
+			int main(int n) {
+				int x;
+				x = n + 1;
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(
+			"main:\n\t0: x@0 = arg0 + 1\n\t1: t0 = x@0\n\t2: return t0\n",
+			generate_named(&parsed, &table)[0].to_string()
+		);
+		assert_eq!(
+			"F0:\n\t0: I2@0 = arg0 + 1\n\t1: t0 = I2@0\n\t2: return t0\n",
+			generate(&parsed, table.0.len())[0].to_string()
+		);
+	}
+
 	#[test]
 	fn while_loops() {
 		let test_program = "int main(int n) {while (1) {}}";
 		let tac_expected = vec![Function {
 			id: 0,
+			names: None,
+			signature: None,
 			instructions: vec![
 				Instruction::Expression(
 					Operand::Temporary(0),
@@ -392,9 +1372,140 @@ mod test {
 				),
 				Instruction::Ifz(Operand::Temporary(0), 2),
 				Instruction::Goto(-2),
+				Instruction::Return(Operand::Immediate(0)),
 			],
+			lines: vec![1, 1, 1, 1],
+			statics: Vec::new(),
+			inlining: parser::Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
 		}];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
+	}
+
+	#[test]
+	fn nested_scopes() {
+		let test_program = r"
+			int main(int a) {
+				int x;
+				x = 5;
+				if (a) {
+					int x;
+					x = 7;
+					if (a) {
+						a = 2;
+					}
+				}
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let x = table.0.iter().position(|name| name == "x").unwrap();
+		let instructions = &generate(&parsed, table.0.len())[0].instructions;
+		// The inner `x` is forgotten along with the nested `if`
+		assert_eq!(
+			Instruction::Expression(
+				Operand::Temporary(0),
+				RValue::Assignment(Operand::Ident(Ident::Binded(x, 0))),
+			),
+			instructions[instructions.len() - 2]
+		);
+	}
+
+	#[test]
+	fn condition_scope() {
+		let x_read = |test_program: &str| {
+			let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+			let x = table.0.iter().position(|name| name == "x").unwrap();
+			generate(&parsed, table.0.len())[0]
+				.instructions
+				.iter()
+				.find_map(|instruction| match instruction {
+					Instruction::Expression(
+						Operand::Temporary(0),
+						RValue::Assignment(Operand::Ident(Ident::Binded(index, scope))),
+					) if *index == x => Some(*scope),
+					_ => None,
+				})
+		};
+		// The condition reads the outer `x`, not the one its body declares
+		let test_program = r"
+			int main() {
+				int x = 1, r = 0;
+				if (x) {
+					int x = 0;
+					r = 5;
+				}
+				return r;
+			}
+		";
+		assert_eq!(Some(0), x_read(test_program));
+		let test_program = r"
+			int main() {
+				int x = 1, r = 0;
+				while (x) {
+					int x = 0;
+					r = r + 1;
+					break;
+				}
+				return r;
+			}
+		";
+		assert_eq!(Some(0), x_read(test_program));
+	}
+
+	#[test]
+	fn implicit_return() {
+		let test_program = r"
+			void set(int n) {
+				n = 1;
+			}
+			int main(int n) {
+				set(n);
+				return n;
+			}
+		";
+		let tac_expected = vec![
+			Function {
+				id: 0,
+				names: None,
+				signature: None,
+				instructions: vec![
+					Instruction::Expression(
+						Operand::Ident(Ident::Parameter(0)),
+						RValue::Assignment(Operand::Immediate(1)),
+					),
+					Instruction::Return(Operand::Immediate(0)),
+				],
+				lines: vec![3, 3],
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
+			},
+			Function {
+				id: 2,
+				names: None,
+				signature: None,
+				instructions: vec![
+					Instruction::Push(Operand::Ident(Ident::Parameter(0))),
+					Instruction::Expression(Operand::Temporary(0), RValue::FuncCall(0, 1)),
+					Instruction::Expression(
+						Operand::Temporary(0),
+						RValue::Assignment(Operand::Ident(Ident::Parameter(0))),
+					),
+					Instruction::Return(Operand::Temporary(0)),
+				],
+				lines: vec![6, 6, 7, 7],
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
+			},
+		];
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(analyze(&parsed).is_ok());
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 	}
 
@@ -411,6 +1522,8 @@ mod test {
 		let tac_expected = vec![
 			Function {
 				id: 0,
+				names: None,
+				signature: None,
 				instructions: vec![
 					Instruction::Expression(
 						Operand::Temporary(0),
@@ -418,17 +1531,63 @@ mod test {
 					),
 					Instruction::Return(Operand::Temporary(0)),
 				],
+				lines: vec![3, 3],
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
 			},
 			Function {
 				id: 2,
+				names: None,
+				signature: None,
 				instructions: vec![
 					Instruction::Push(Operand::Immediate(1)),
 					Instruction::Expression(Operand::Temporary(0), RValue::FuncCall(0, 1)),
 					Instruction::Return(Operand::Temporary(0)),
 				],
+				lines: vec![6, 6, 6],
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
 			},
 		];
-		let (parsed, table) = parse(tokenize(test_program)).unwrap();
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
 		assert_eq!(tac_expected, generate(&parsed, table.0.len()));
 	}
+
+	#[test]
+	fn argument_order() {
+		let test_program = r"
+			int sub(int a, int b) {
+				return a - b;
+			}
+			int main(int n) {
+				int a[2];
+				return sub(a[0], -n);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let main = &generate(&parsed, table.0.len())[1];
+		let a = Ident::Binded(1, 0);
+		assert_eq!(
+			vec![
+				Instruction::ArrayAlloc(a, 2),
+				Instruction::Expression(
+					Operand::Temporary(2),
+					RValue::ArrayAccess(a, Operand::Immediate(0)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(3),
+					RValue::Negate(Operand::Ident(Ident::Parameter(0))),
+				),
+				Instruction::Push(Operand::Temporary(3)),
+				Instruction::Push(Operand::Temporary(2)),
+				Instruction::Expression(Operand::Temporary(0), RValue::FuncCall(0, 2)),
+				Instruction::Return(Operand::Temporary(0)),
+			],
+			main.instructions
+		);
+	}
 }
@@ -0,0 +1,245 @@
+//! Call graph of the generated functions
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::{Function, Instruction, RValue};
+use crate::parser::{IdentNameTable, Program};
+
+/// Ids of the functions called by `function`, defined or not
+pub fn callees(function: &Function) -> HashSet<usize> {
+	call_sites(function).into_keys().collect()
+}
+
+/// Number of call sites in `function` for every callee, ordered by id
+pub fn call_sites(function: &Function) -> BTreeMap<usize, usize> {
+	let mut res = BTreeMap::new();
+	for instruction in &function.instructions {
+		if let Instruction::Expression(_, RValue::FuncCall(id, _)) = instruction {
+			*res.entry(*id).or_default() += 1;
+		}
+	}
+	res
+}
+
+/// Whether `function` makes no calls, letting the backend skip setting up for
+/// a callee
+pub fn is_leaf(function: &Function) -> bool {
+	call_sites(function).is_empty()
+}
+
+/// The call graph as JSON, with a node for every function of `program`,
+/// external ones included, and an edge for every caller and callee pair.
+/// External functions are never leaves, whatever they call is unknown:
+/// ```json
+/// {"nodes":[{"name":"f","parameters":1,"external":false,"leaf":true}],
+///  "edges":[{"caller":"main","callee":"f","calls":2}]}
+/// ```
+/// Identifiers never need escaping, so the JSON is built by hand
+pub fn to_json(program: &Program, functions: &[Function], ident_table: &IdentNameTable) -> String {
+	let name = |id: usize| ident_table.0[id].as_str();
+	let leaf = |id: usize| {
+		functions
+			.iter()
+			.find(|function| function.id == id)
+			.is_some_and(is_leaf)
+	};
+	let nodes: Vec<String> = program
+		.0
+		.iter()
+		.map(|func| {
+			let id = func.name().table_index;
+			format!(
+				r#"{{"name":"{}","parameters":{},"external":{},"leaf":{}}}"#,
+				name(id),
+				func.parameter().len(),
+				func.is_external(),
+				leaf(id)
+			)
+		})
+		.collect();
+	let edges: Vec<String> = functions
+		.iter()
+		.flat_map(|function| {
+			call_sites(function).into_iter().map(|(callee, calls)| {
+				format!(
+					r#"{{"caller":"{}","callee":"{}","calls":{calls}}}"#,
+					name(function.id),
+					name(callee)
+				)
+			})
+		})
+		.collect();
+	format!(
+		r#"{{"nodes":[{}],"edges":[{}]}}"#,
+		nodes.join(","),
+		edges.join(",")
+	)
+}
+
+/// Functions a translation unit provides and needs from others, there are no
+/// global variables to list yet
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Dependencies {
+	/// Functions with a body
+	pub defined: BTreeSet<String>,
+	/// Functions called but without a body, to be resolved by the linker
+	pub undefined: BTreeSet<String>,
+}
+impl std::fmt::Display for Dependencies {
+	/// One line per set, the names separated by spaces:
+	/// ```text
+	/// defined: main square
+	/// undefined: helper
+	/// ```
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let join = |names: &BTreeSet<String>| {
+			names
+				.iter()
+				.map(String::as_str)
+				.collect::<Vec<_>>()
+				.join(" ")
+		};
+		writeln!(f, "defined: {}", join(&self.defined))?;
+		writeln!(f, "undefined: {}", join(&self.undefined))
+	}
+}
+
+/// Dependencies of the translation unit `functions` were generated from
+pub fn dependencies(functions: &[Function], ident_table: &IdentNameTable) -> Dependencies {
+	let defined: HashSet<usize> = functions.iter().map(|function| function.id).collect();
+	let name = |&id: &usize| ident_table.0[id].clone();
+	Dependencies {
+		defined: defined.iter().map(name).collect(),
+		undefined: functions
+			.iter()
+			.flat_map(callees)
+			.filter(|id| !defined.contains(id))
+			.map(|id| name(&id))
+			.collect(),
+	}
+}
+
+/// Ids of the functions able to call themselves, directly or through others.
+///
+/// External functions are assumed to never call back into the program.
+pub fn recursive_functions(functions: &[Function]) -> HashSet<usize> {
+	let graph: HashMap<usize, HashSet<usize>> = functions
+		.iter()
+		.map(|function| (function.id, callees(function)))
+		.collect();
+	graph
+		.keys()
+		.copied()
+		.filter(|&id| {
+			let mut visited = HashSet::new();
+			let mut pending: Vec<usize> = graph[&id].iter().copied().collect();
+			while let Some(callee) = pending.pop() {
+				if callee == id {
+					return true;
+				}
+				if visited.insert(callee)
+					&& let Some(next) = graph.get(&callee)
+				{
+					pending.extend(next);
+				}
+			}
+			false
+		})
+		.collect()
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate};
+
+	#[test]
+	fn recursion() {
+		let test_program = r"
+			int leaf(int n) { return n; }
+			int even(int n) { if (n) { n = n - 1; return odd(n); } return 1; }
+			int odd(int n) { if (n) { n = n - 1; return even(n); } return 0; }
+			int fact(int n) { if (n) { int m; m = n - 1; m = fact(m); return n * m; } return 1; }
+			int main(int n) { n = leaf(n); n = even(n); return fact(n); }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let id = |name: &str| table.0.iter().position(|i| i == name).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		assert_eq!(
+			HashSet::from([id("even"), id("odd"), id("fact")]),
+			recursive_functions(&functions)
+		);
+	}
+
+	#[test]
+	fn leaves() {
+		let test_program = r"
+			int square(int n) { return n * n; }
+			int main(int n) {
+				if (n) {
+					n = square(n);
+				}
+				return n;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		assert!(is_leaf(&functions[0]));
+		assert!(!is_leaf(&functions[1]));
+	}
+
+	#[test]
+	fn undefined_dependency() {
+		let test_program = r"
+			int helper(int n);
+			int unused(int n);
+			int square(int n) { return n * n; }
+			int main(int n) {
+				n = square(n);
+				return helper(n);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let dependencies = dependencies(&functions, &table);
+		assert_eq!(
+			BTreeSet::from(["main".to_string(), "square".to_string()]),
+			dependencies.defined
+		);
+		assert_eq!(
+			BTreeSet::from(["helper".to_string()]),
+			dependencies.undefined
+		);
+		assert_eq!(
+			"defined: main square\nundefined: helper\n",
+			dependencies.to_string()
+		);
+	}
+
+	#[test]
+	fn json() {
+		let test_program = r"
+			int ext(int a, int b);
+			int square(int n) { return n * n; }
+			int main(int n) {
+				n = square(n);
+				n = square(n);
+				return ext(n, 1);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		assert_eq!(
+			concat!(
+				r#"{"nodes":["#,
+				r#"{"name":"ext","parameters":2,"external":true,"leaf":false},"#,
+				r#"{"name":"square","parameters":1,"external":false,"leaf":true},"#,
+				r#"{"name":"main","parameters":1,"external":false,"leaf":false}],"#,
+				r#""edges":["#,
+				r#"{"caller":"main","callee":"ext","calls":1},"#,
+				r#"{"caller":"main","callee":"square","calls":2}]}"#
+			),
+			to_json(&parsed, &functions, &table)
+		);
+	}
+}
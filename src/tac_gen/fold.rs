@@ -0,0 +1,93 @@
+//! Constant folding
+//!
+//! Operations on immediates are evaluated at compile time, leaving an
+//! assignment of the result. Comparisons fold to 0 or 1, so a constant
+//! condition ends up as an immediate assigned to the `Ifz` operand.
+use super::{Function, Instruction, Operand, RValue};
+
+/// Replaces every `RValue::Operation`, `RValue::BitwiseNot` and
+/// `RValue::Negate` on immediates in `function` with the
+/// `RValue::Assignment` of its value, bitwise operations acting on the two's
+/// complement bit pattern. Divisions by zero are left to trap at runtime.
+pub fn fold_constants(function: &mut Function) {
+	for instruction in function.instructions.iter_mut() {
+		let Instruction::Expression(_, r_value) = instruction else {
+			continue;
+		};
+		let value = match *r_value {
+			RValue::Operation(Operand::Immediate(lhs), operation, Operand::Immediate(rhs)) => {
+				operation.evaluate(lhs, rhs)
+			}
+			RValue::BitwiseNot(Operand::Immediate(value)) => Some(!value),
+			RValue::Negate(Operand::Immediate(value)) => Some(value.wrapping_neg()),
+			_ => None,
+		};
+		if let Some(value) = value {
+			*r_value = RValue::Assignment(Operand::Immediate(value));
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate};
+
+	/// Value assigned by `return <expression>;` once folded, `None` when left
+	/// unfolded
+	#[allow(dead_code)]
+	fn folded(expression: &str) -> Option<i32> {
+		let test_program = format!("int main() {{ return {expression}; }}");
+		let (parsed, table) = parse(tokenize(&test_program).unwrap()).unwrap();
+		let mut function = generate(&parsed, table.0.len()).remove(0);
+		fold_constants(&mut function);
+		match function.instructions[0] {
+			Instruction::Expression(_, RValue::Assignment(Operand::Immediate(value))) => {
+				Some(value)
+			}
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn comparisons() {
+		assert_eq!(Some(0), folded("5 < 4"));
+		assert_eq!(Some(1), folded("4 < 5"));
+		assert_eq!(Some(1), folded("4 <= 4"));
+		assert_eq!(Some(0), folded("5 <= 4"));
+		assert_eq!(Some(1), folded("5 > 4"));
+		assert_eq!(Some(0), folded("4 > 4"));
+		assert_eq!(Some(1), folded("4 >= 4"));
+		assert_eq!(Some(0), folded("-4 >= 4"));
+		assert_eq!(Some(1), folded("3 == 3"));
+		assert_eq!(Some(0), folded("3 == -3"));
+		assert_eq!(Some(1), folded("3 != 4"));
+		assert_eq!(Some(0), folded("3 != 3"));
+	}
+
+	#[test]
+	fn arithmetic() {
+		assert_eq!(Some(7), folded("3 + 4"));
+		assert_eq!(Some(-2), folded("-7 / 3"));
+		assert_eq!(Some(i32::MIN), folded("2147483647 + 1"));
+		assert_eq!(None, folded("1 / 0"));
+		assert_eq!(None, folded("1 % 0"));
+	}
+
+	#[test]
+	fn bitwise() {
+		assert_eq!(Some(0xFF), folded("0xF0 ^ 0x0F"));
+		assert_eq!(Some(0), folded("0x5A ^ 0x5A"));
+		assert_eq!(Some(-16), folded("-1 ^ 0x0F"));
+		assert_eq!(Some(0x30), folded("0xF0 & 0x3C"));
+		assert_eq!(Some(0xF0), folded("-16 & 0xFF"));
+		assert_eq!(Some(-8), folded("-8 & -3"));
+		assert_eq!(Some(0xFF), folded("0xF0 | 0x0F"));
+		assert_eq!(Some(-1), folded("-256 | 0xFF"));
+		assert_eq!(Some(-1), folded("~0"));
+		assert_eq!(Some(0), folded("~-1"));
+		assert_eq!(Some(i32::MIN), folded("~0x7FFFFFFF"));
+		assert_eq!(Some(-0xF1), folded("~0xF0"));
+	}
+}
@@ -0,0 +1,364 @@
+//! Inlining of small leaf functions at their call sites
+use std::collections::HashMap;
+
+use super::{call_graph::is_leaf, Function, Ident, Instruction, Operand, RValue};
+use crate::parser::Inlining;
+
+/// Maximum instruction count of a function for it to be considered for inlining
+pub const INLINE_THRESHOLD: usize = 8;
+/// Maximum instruction count a caller is allowed to grow to by inlining
+pub const INLINE_BUDGET: usize = 512;
+
+/// Replaces calls to leaf functions having at most `threshold` instructions
+/// with their body, as long as the caller stays within `budget` instructions.
+/// Functions marked `inline` are inlined regardless of both, and those marked
+/// `noinline` never are.
+///
+/// Only leaf functions are inlined, so recursion is never expanded.
+pub fn inline_leaf_functions(functions: &mut [Function], threshold: usize, budget: usize) {
+	let candidates: HashMap<usize, Function> = functions
+		.iter()
+		.filter(|function| match function.inlining {
+			Inlining::Auto => function.instructions.len() <= threshold && is_inlinable(function),
+			Inlining::Always => is_inlinable(function),
+			Inlining::Never => false,
+		})
+		.map(|function| (function.id, function.clone()))
+		.collect();
+	for function in functions.iter_mut() {
+		while let Some((call_site, callee)) = function.instructions.iter().enumerate().find_map(
+			|(i, instruction)| match instruction {
+				Instruction::Expression(_, RValue::FuncCall(id, _)) => {
+					candidates.get(id).map(|callee| (i, callee))
+				}
+				_ => None,
+			},
+		) {
+			if callee.inlining != Inlining::Always
+				&& function.instructions.len() + callee.instructions.len() > budget
+			{
+				break;
+			}
+			inline_call(function, call_site, callee);
+		}
+	}
+}
+
+fn is_inlinable(function: &Function) -> bool {
+	is_leaf(function)
+		&& !has_array_parameter(function)
+		// Statics belong to the callee, not to every caller
+		&& function.statics.is_empty()
+}
+
+/// Parameters are inlined as temporaries, which can't be indexed
+fn has_array_parameter(function: &Function) -> bool {
+	function
+		.instructions
+		.iter()
+		.copied()
+		.any(|mut instruction| matches!(instruction.array_mut(), Some(Ident::Parameter(_))))
+}
+
+/// Every `Ident` referenced by the instruction
+fn idents(mut instruction: Instruction) -> Vec<Ident> {
+	let mut res: Vec<Ident> = instruction
+		.operands_mut()
+		.into_iter()
+		.filter_map(|operand| match operand {
+			Operand::Ident(ident) => Some(*ident),
+			_ => None,
+		})
+		.collect();
+	res.extend(instruction.array_mut().map(|ident| *ident));
+	res
+}
+
+/// One more than the largest `Temporary` id used
+pub(super) fn temporary_span(instructions: &[Instruction]) -> usize {
+	instructions
+		.iter()
+		.copied()
+		.flat_map(|mut instruction| {
+			instruction
+				.operands_mut()
+				.into_iter()
+				.filter_map(|operand| match operand {
+					Operand::Temporary(id) => Some(*id + 1),
+					_ => None,
+				})
+				.collect::<Vec<_>>()
+		})
+		.max()
+		.unwrap_or(0)
+}
+
+fn inline_call(function: &mut Function, call_site: usize, callee: &Function) {
+	let instructions = &mut function.instructions;
+	let Instruction::Expression(lhs, RValue::FuncCall(_, arg_count)) = instructions[call_site]
+	else {
+		unreachable!("Call site must be a `FuncCall`")
+	};
+	// Callee's temporaries and locals are shifted past the ones of the
+	// caller, parameters become temporaries after the callee's own
+	let temporary_offset = temporary_span(instructions);
+	let parameter_offset = temporary_offset + temporary_span(&callee.instructions);
+	let scope_offset = instructions
+		.iter()
+		.copied()
+		.flat_map(idents)
+		.filter_map(|ident| match ident {
+			Ident::Binded(_, scope_id) => Some(scope_id + 1),
+			Ident::Parameter(_) | Ident::Static(..) | Ident::Literal(_) => None,
+		})
+		.max()
+		.unwrap_or(0);
+
+	// Arguments are pushed in reverse, so the push closest to the call is the
+	// first argument
+	let mut argument = 0;
+	for instruction in instructions[..call_site].iter_mut().rev() {
+		if argument == arg_count {
+			break;
+		}
+		if let Instruction::Push(op) = *instruction {
+			*instruction = Instruction::Expression(
+				Operand::Temporary(parameter_offset + argument),
+				RValue::Assignment(op),
+			);
+			argument += 1;
+		}
+	}
+
+	let rename_ident = |ident: Ident| match ident {
+		Ident::Binded(name_index, scope_id) => Ident::Binded(name_index, scope_id + scope_offset),
+		Ident::Parameter(_) => unreachable!("Parameters are only renamed as operands"),
+		Ident::Static(..) => unreachable!("Callees with statics are never inlined"),
+		Ident::Literal(_) => unreachable!("Literals are only passed in calls, absent in leaves"),
+	};
+	let rename = |operand: &mut Operand| {
+		*operand = match *operand {
+			Operand::Ident(Ident::Parameter(index)) => Operand::Temporary(parameter_offset + index),
+			Operand::Ident(ident) => Operand::Ident(rename_ident(ident)),
+			Operand::Temporary(id) => Operand::Temporary(temporary_offset + id),
+			Operand::Immediate(val) => Operand::Immediate(val),
+		}
+	};
+
+	// A `Return` before the end expands into an assignment and a jump past
+	// the inlined body
+	let callee_len = callee.instructions.len();
+	let is_early_return = |i: usize, instruction: &Instruction| {
+		matches!(instruction, Instruction::Return(_)) && i + 1 != callee_len
+	};
+	let mut positions = Vec::with_capacity(callee_len + 1);
+	let mut body_len = 0;
+	for (i, instruction) in callee.instructions.iter().enumerate() {
+		positions.push(body_len);
+		body_len += if is_early_return(i, instruction) {
+			2
+		} else {
+			1
+		};
+	}
+	positions.push(body_len);
+
+	let mut body = Vec::with_capacity(body_len);
+	for (i, instruction) in callee.instructions.iter().enumerate() {
+		let mut instruction = *instruction;
+		instruction.operands_mut().into_iter().for_each(rename);
+		if let Some(ident) = instruction.array_mut() {
+			*ident = rename_ident(*ident);
+		}
+		match instruction {
+			Instruction::Return(op) => {
+				body.push(Instruction::Expression(lhs, RValue::Assignment(op)));
+				if is_early_return(i, &instruction) {
+					body.push(Instruction::Goto((body_len - body.len()) as isize));
+				}
+			}
+			Instruction::Goto(offset) => {
+				let target = positions[(i as isize + offset) as usize];
+				body.push(Instruction::Goto(target as isize - positions[i] as isize));
+			}
+			Instruction::Ifz(op, offset) => {
+				body.push(Instruction::Ifz(op, positions[i + offset] - positions[i]));
+			}
+			instruction => body.push(instruction),
+		}
+	}
+	// The inlined body is attributed to the line of the call
+	if let Some(&line) = function.lines.get(call_site) {
+		let body_lines = std::iter::repeat_n(line, body.len());
+		function.lines.splice(call_site..=call_site, body_lines);
+	}
+	splice(instructions, call_site, body);
+}
+
+/// Replaces the instruction at `index` with `replacement`, retargeting the
+/// jumps around it. The replaced instruction must not be a jump.
+fn splice(instructions: &mut Vec<Instruction>, index: usize, replacement: Vec<Instruction>) {
+	let delta = replacement.len() as isize - 1;
+	let moved = |position: usize| {
+		if position > index {
+			position as isize + delta
+		} else {
+			position as isize
+		}
+	};
+	for (i, instruction) in instructions.iter_mut().enumerate() {
+		match instruction {
+			Instruction::Goto(offset) => {
+				let target = (i as isize + *offset) as usize;
+				*offset = moved(target) - moved(i);
+			}
+			Instruction::Ifz(_, offset) => {
+				*offset = (moved(i + *offset) - moved(i)) as usize;
+			}
+			_ => (),
+		}
+	}
+	instructions.splice(index..=index, replacement);
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::{parse, BinaryOperation},
+		tac_gen::{generate, verify},
+	};
+
+	#[test]
+	fn inline_add() {
+		let test_program = r"
+			int add(int a, int b) {
+				return a + b;
+			}
+			int main(int n) {
+				int x;
+				x = add(n, 2);
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		inline_leaf_functions(&mut functions, INLINE_THRESHOLD, INLINE_BUDGET);
+		let expected = vec![
+			Instruction::Expression(
+				Operand::Temporary(3),
+				RValue::Assignment(Operand::Immediate(2)),
+			),
+			Instruction::Expression(
+				Operand::Temporary(2),
+				RValue::Assignment(Operand::Ident(Ident::Parameter(0))),
+			),
+			Instruction::Expression(
+				Operand::Temporary(1),
+				RValue::Operation(
+					Operand::Temporary(2),
+					BinaryOperation::Add,
+					Operand::Temporary(3),
+				),
+			),
+			Instruction::Expression(
+				Operand::Ident(Ident::Binded(5, 0)),
+				RValue::Assignment(Operand::Temporary(1)),
+			),
+			Instruction::Expression(
+				Operand::Temporary(0),
+				RValue::Assignment(Operand::Ident(Ident::Binded(5, 0))),
+			),
+			Instruction::Return(Operand::Temporary(0)),
+		];
+		assert_eq!(expected, functions[1].instructions);
+		assert!(functions.iter().all(|function| verify(function).is_ok()));
+	}
+
+	#[test]
+	fn early_return_and_jumps() {
+		let test_program = r"
+			int min(int a, int b) {
+				if (a < b) {
+					return a;
+				}
+				return b;
+			}
+			int main(int n) {
+				int x;
+				x = 0;
+				while (x < 3) {
+					x = min(n, x);
+					x = x + 1;
+				}
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		inline_leaf_functions(&mut functions, INLINE_THRESHOLD, INLINE_BUDGET);
+		assert!(is_leaf(&functions[1]));
+		assert!(functions.iter().all(|function| verify(function).is_ok()));
+		assert!(functions[1]
+			.instructions
+			.iter()
+			.any(|i| matches!(i, Instruction::Goto(offset) if *offset > 0)));
+	}
+
+	#[test]
+	fn budget() {
+		let test_program = r"
+			int add(int a, int b) {
+				return a + b;
+			}
+			int main(int n) {
+				return add(n, 2);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		let expected = functions.clone();
+		inline_leaf_functions(&mut functions, INLINE_THRESHOLD, 4);
+		assert_eq!(expected, functions);
+		inline_leaf_functions(&mut functions, 1, INLINE_BUDGET);
+		assert_eq!(expected, functions);
+	}
+
+	#[test]
+	fn attributes() {
+		let test_program = r"
+			inline int clamp(int n) {
+				if (n > 9) { n = 9; }
+				if (n < 0) { n = 0; }
+				return n;
+			}
+			noinline int add(int a, int b) {
+				return a + b;
+			}
+			int main(int n) {
+				n = clamp(n);
+				return add(n, 2);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		let calls = |function: &Function| {
+			function
+				.instructions
+				.iter()
+				.filter_map(|i| match i {
+					Instruction::Expression(_, RValue::FuncCall(id, _)) => Some(*id),
+					_ => None,
+				})
+				.collect::<Vec<_>>()
+		};
+		let add = table.0.iter().position(|name| name == "add").unwrap();
+		inline_leaf_functions(&mut functions, 1, 1);
+		assert_eq!(vec![add], calls(&functions[2]));
+		inline_leaf_functions(&mut functions, INLINE_THRESHOLD, INLINE_BUDGET);
+		assert_eq!(vec![add], calls(&functions[2]));
+		assert!(functions.iter().all(|function| verify(function).is_ok()));
+	}
+}
@@ -0,0 +1,220 @@
+//! Loop-invariant code motion
+//!
+//! A loop is the range from the target of a backward `Goto` up to the `Goto`
+//! itself. An `RValue::Operation` in it whose operands are never assigned to
+//! within the range is computed once into a fresh temporary right before the
+//! loop, the original instruction being left as an assignment from it.
+use std::collections::HashSet;
+
+use super::{inline::temporary_span, Function, Instruction, Operand, RValue};
+use crate::parser::BinaryOperation;
+
+/// Hoists loop invariant operations out of every loop of `function`, innermost
+/// first, until none are left.
+///
+/// Loops containing a `FuncCall` or an `ArrayWrite` are left untouched, as are
+/// divisions, which could trap in a loop never entered.
+pub fn hoist_loop_invariants(function: &mut Function) {
+	while let Some((start, end, index)) = find_invariant(&function.instructions) {
+		let Instruction::Expression(lhs, r_value) = function.instructions[index] else {
+			unreachable!("Only expressions are hoisted")
+		};
+		let temporary = Operand::Temporary(temporary_span(&function.instructions));
+		function.instructions[index] = Instruction::Expression(lhs, RValue::Assignment(temporary));
+		insert_preheader(
+			&mut function.instructions,
+			start,
+			end,
+			Instruction::Expression(temporary, r_value),
+		);
+		if let Some(&line) = function.lines.get(index) {
+			function.lines.insert(start, line);
+		}
+	}
+}
+
+/// Bounds of the loop and the index of the first invariant operation found
+fn find_invariant(instructions: &[Instruction]) -> Option<(usize, usize, usize)> {
+	let mut loops: Vec<(usize, usize)> = instructions
+		.iter()
+		.enumerate()
+		.filter_map(|(end, instruction)| match instruction {
+			Instruction::Goto(offset) if *offset < 0 => {
+				Some(((end as isize + offset) as usize, end))
+			}
+			_ => None,
+		})
+		.filter(|&(start, end)| is_single_entry(instructions, start, end))
+		.collect();
+	// Innermost loops span the fewest instructions
+	loops.sort_by_key(|(start, end)| end - start);
+	loops.into_iter().find_map(|(start, end)| {
+		let body = &instructions[start..=end];
+		if body.iter().any(|instruction| {
+			matches!(
+				instruction,
+				Instruction::ArrayWrite(..) | Instruction::Expression(_, RValue::FuncCall(..))
+			)
+		}) {
+			return None;
+		}
+		let written: HashSet<Operand> = body
+			.iter()
+			.filter_map(|instruction| match instruction {
+				Instruction::Expression(lhs, _) => Some(*lhs),
+				_ => None,
+			})
+			.collect();
+		let is_invariant = |operand: &Operand| {
+			matches!(operand, Operand::Immediate(_)) || !written.contains(operand)
+		};
+		body.iter()
+			.position(|instruction| match instruction {
+				Instruction::Expression(_, RValue::Operation(lhs, operation, rhs)) => {
+					!matches!(operation, BinaryOperation::Div | BinaryOperation::Mod)
+						&& is_invariant(lhs)
+						&& is_invariant(rhs)
+				}
+				_ => false,
+			})
+			.map(|index| (start, end, start + index))
+	})
+}
+
+/// Whether control only enters `start..=end` through `start`
+fn is_single_entry(instructions: &[Instruction], start: usize, end: usize) -> bool {
+	instructions
+		.iter()
+		.enumerate()
+		.filter(|(i, _)| !(start..=end).contains(i))
+		.all(|(i, instruction)| {
+			let target = match instruction {
+				Instruction::Goto(offset) => i as isize + offset,
+				Instruction::Ifz(_, offset) => (i + offset) as isize,
+				_ => return true,
+			};
+			!(start as isize + 1..=end as isize).contains(&target)
+		})
+}
+
+/// Inserts `preheader` before the loop at `start..=end`. Jumps entering the
+/// loop land on it, while jumps within the loop skip it.
+fn insert_preheader(
+	instructions: &mut Vec<Instruction>,
+	start: usize,
+	end: usize,
+	preheader: Instruction,
+) {
+	let moved = |position: usize| {
+		if position >= start {
+			position as isize + 1
+		} else {
+			position as isize
+		}
+	};
+	let retarget = |i: usize, target: usize| {
+		if target == start && !(start..=end).contains(&i) {
+			start as isize
+		} else {
+			moved(target)
+		}
+	};
+	for (i, instruction) in instructions.iter_mut().enumerate() {
+		match instruction {
+			Instruction::Goto(offset) => {
+				let target = (i as isize + *offset) as usize;
+				*offset = retarget(i, target) - moved(i);
+			}
+			Instruction::Ifz(_, offset) => {
+				*offset = (retarget(i, i + *offset) - moved(i)) as usize;
+			}
+			_ => (),
+		}
+	}
+	instructions.insert(start, preheader);
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::parse,
+		tac_gen::{generate, verify, Ident},
+	};
+
+	#[test]
+	fn hoist_sum() {
+		let test_program = r"
+			int main(int b, int c) {
+				int i, x;
+				i = 0;
+				while (i < 10) {
+					x = b + c;
+					i = i + x;
+				}
+				return i;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		hoist_loop_invariants(&mut functions[0]);
+		let i = Operand::Ident(Ident::Binded(3, 0));
+		let x = Operand::Ident(Ident::Binded(4, 0));
+		let b_plus_c = RValue::Operation(
+			Operand::Ident(Ident::Parameter(0)),
+			BinaryOperation::Add,
+			Operand::Ident(Ident::Parameter(1)),
+		);
+		let expected = vec![
+			Instruction::Expression(i, RValue::Assignment(Operand::Immediate(0))),
+			Instruction::Expression(Operand::Temporary(1), b_plus_c),
+			Instruction::Expression(
+				Operand::Temporary(0),
+				RValue::Operation(i, BinaryOperation::Less, Operand::Immediate(10)),
+			),
+			Instruction::Ifz(Operand::Temporary(0), 4),
+			Instruction::Expression(x, RValue::Assignment(Operand::Temporary(1))),
+			Instruction::Expression(i, RValue::Operation(i, BinaryOperation::Add, x)),
+			Instruction::Goto(-4),
+			Instruction::Expression(Operand::Temporary(0), RValue::Assignment(i)),
+			Instruction::Return(Operand::Temporary(0)),
+		];
+		assert_eq!(expected, functions[0].instructions);
+		assert_eq!(expected.len(), functions[0].lines.len());
+		assert!(verify(&functions[0]).is_ok());
+	}
+
+	#[test]
+	fn variant_and_unsafe() {
+		let test_program = r"
+			int f(int n) { return n; }
+			int main(int b, int c) {
+				int i, a[2];
+				i = 0;
+				while (i < 10) {
+					b = b + c;
+					i = i + 1;
+				}
+				while (i < 20) {
+					a[0] = b + c;
+					i = i + 1;
+				}
+				while (i < 30) {
+					i = f(i);
+					i = i + c;
+				}
+				while (i < 40) {
+					i = b / c;
+				}
+				return i;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		let expected = functions[1].clone();
+		hoist_loop_invariants(&mut functions[1]);
+		assert_eq!(expected, functions[1]);
+	}
+}
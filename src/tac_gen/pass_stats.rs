@@ -0,0 +1,125 @@
+//! Instrumentation of the passes transforming the TAC
+//!
+//! Every pass ran through `instrument` logs its effect at `info` level, the
+//! collected `PassStats` can be rendered as a table with `table`.
+use super::Function;
+
+/// Instruction counts across every function before and after a pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassStats {
+	pub pass: &'static str,
+	pub before: usize,
+	pub after: usize,
+}
+impl PassStats {
+	/// Instructions removed by the pass, negative when it added some
+	pub fn removed(&self) -> isize {
+		self.before as isize - self.after as isize
+	}
+}
+
+/// Runs `pass` over `functions`, logging the number of instructions it removed
+/// or added
+pub fn instrument(
+	pass: &'static str,
+	functions: &mut [Function],
+	run: impl FnOnce(&mut [Function]),
+) -> PassStats {
+	let before = instruction_count(functions);
+	run(functions);
+	let stats = PassStats {
+		pass,
+		before,
+		after: instruction_count(functions),
+	};
+	match stats.removed() {
+		removed if removed >= 0 => log::info!("{pass}: removed {removed} instructions"),
+		removed => log::info!("{pass}: added {} instructions", -removed),
+	}
+	stats
+}
+
+fn instruction_count(functions: &[Function]) -> usize {
+	functions
+		.iter()
+		.map(|function| function.instructions.len())
+		.sum()
+}
+
+/// One row per pass, in the order they ran
+pub fn table(stats: &[PassStats]) -> String {
+	let width = stats
+		.iter()
+		.map(|stats| stats.pass.len())
+		.chain(std::iter::once("pass".len()))
+		.max()
+		.unwrap_or_default();
+	let mut res = format!("{:width$}  before   after  removed\n", "pass");
+	for stats in stats {
+		res += &format!(
+			"{:width$}  {:>6}  {:>6}  {:>7}\n",
+			stats.pass,
+			stats.before,
+			stats.after,
+			stats.removed()
+		);
+	}
+	res
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::parse,
+		tac_gen::{generate, inline::*},
+	};
+
+	#[test]
+	fn instrumented_pass() {
+		let test_program = r"
+			int add(int a, int b) {
+				return a + b;
+			}
+			int main(int n) {
+				return add(n, 2);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		let before = instruction_count(&functions);
+		let stats = instrument("inline_leaf_functions", &mut functions, |functions| {
+			inline_leaf_functions(functions, INLINE_THRESHOLD, INLINE_BUDGET)
+		});
+		assert_eq!(before, stats.before);
+		assert_eq!(instruction_count(&functions), stats.after);
+		// The call is replaced by both instructions of `add`
+		assert_eq!(-1, stats.removed());
+	}
+
+	#[test]
+	fn stats_table() {
+		let stats = [
+			PassStats {
+				pass: "inline_leaf_functions",
+				before: 12,
+				after: 14,
+			},
+			PassStats {
+				pass: "licm",
+				before: 14,
+				after: 11,
+			},
+		];
+		assert_eq!(
+			concat!(
+				"pass                   before   after  removed\n",
+				"inline_leaf_functions      12      14       -2\n",
+				"licm                       14      11        3\n",
+			),
+			table(&stats)
+		);
+	}
+}
@@ -0,0 +1,112 @@
+//! Optimization passes as data
+//!
+//! `--passes=inline,fold` runs exactly the listed passes, in that order, in
+//! place of the ones toggled by `--fold`, `--inline` and `--licm`.
+use super::{fold_constants, hoist_loop_invariants, inline::*, inline_leaf_functions, Function};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+	/// `fold_constants`
+	Fold,
+	/// `inline_leaf_functions` with the default threshold and budget
+	Inline,
+	/// `hoist_loop_invariants`
+	Licm,
+}
+impl std::str::FromStr for Pass {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|pass| pass.flag() == s)
+			.ok_or_else(|| {
+				let valid: Vec<&str> = Self::ALL.iter().map(Pass::flag).collect();
+				format!("Unknown pass: '{s}', expected one of {}", valid.join(", "))
+			})
+	}
+}
+impl Pass {
+	/// In the order the `--fold`, `--inline` and `--licm` flags run them
+	pub const ALL: [Self; 3] = [Self::Fold, Self::Inline, Self::Licm];
+	/// Name in the pass list
+	pub fn flag(&self) -> &'static str {
+		match self {
+			Self::Fold => "fold",
+			Self::Inline => "inline",
+			Self::Licm => "licm",
+		}
+	}
+	/// Name of the function implementing the pass, as reported by `pass_stats`
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Fold => "fold_constants",
+			Self::Inline => "inline_leaf_functions",
+			Self::Licm => "hoist_loop_invariants",
+		}
+	}
+	pub fn run(&self, functions: &mut [Function]) {
+		match self {
+			Self::Fold => functions.iter_mut().for_each(fold_constants),
+			Self::Inline => inline_leaf_functions(functions, INLINE_THRESHOLD, INLINE_BUDGET),
+			Self::Licm => functions.iter_mut().for_each(hoist_loop_invariants),
+		}
+	}
+}
+
+/// Parses a comma separated list of passes, which may repeat
+pub fn parse(list: &str) -> Result<Vec<Pass>, String> {
+	list.split(',')
+		.filter(|pass| !pass.is_empty())
+		.map(str::parse)
+		.collect()
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::parse as parse_program,
+		tac_gen::{generate, pass_stats, Instruction, RValue},
+	};
+
+	#[test]
+	fn custom_order() {
+		assert_eq!(
+			Ok(vec![Pass::Licm, Pass::Fold, Pass::Licm]),
+			parse("licm,fold,licm")
+		);
+		assert_eq!(
+			Err("Unknown pass: 'dce', expected one of fold, inline, licm".to_string()),
+			parse("fold,dce")
+		);
+		let test_program = r"
+			int add(int a, int b) { return a + b; }
+			int main() {
+				int x;
+				x = 2 * 3;
+				return add(x, 1);
+			}
+		";
+		let (parsed, table) = parse_program(tokenize(test_program).unwrap()).unwrap();
+		let mut functions = generate(&parsed, table.0.len());
+		let stats: Vec<_> = parse("fold")
+			.unwrap()
+			.iter()
+			.map(|pass| pass_stats::instrument(pass.name(), &mut functions, |f| pass.run(f)))
+			.collect();
+		assert_eq!(
+			vec!["fold_constants"],
+			stats.iter().map(|stats| stats.pass).collect::<Vec<_>>()
+		);
+		// Folded but not inlined
+		let main = &functions[1].instructions;
+		assert!(main
+			.iter()
+			.any(|i| matches!(i, Instruction::Expression(_, RValue::FuncCall(..)))));
+		assert!(!main
+			.iter()
+			.any(|i| matches!(i, Instruction::Expression(_, RValue::Operation(..)))));
+	}
+}
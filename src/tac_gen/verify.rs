@@ -0,0 +1,585 @@
+//! TAC Verifier
+//!
+//! Sanity checks a generated `Function`, meant to be ran after every pass
+//! touching the instructions to catch passes producing invalid TAC.
+//!
+//! `verify_structured` additionally checks the jumps nest like the `if` and
+//! loop statements they are lowered from.
+use std::collections::HashSet;
+
+use super::{Function, Ident, Instruction, Operand, RValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+	/// Jump at `index` lands outside of the function
+	JumpOutOfRange { index: usize, target: isize },
+	/// Temporary read at `index` is not written on any path leading to it
+	UndefinedTemporary { index: usize, temporary: usize },
+	/// Array operation at `index` on an `Ident` never allocated as an array
+	UndeclaredArray { index: usize, ident: Ident },
+	/// Jump at `index` escapes the `if` or `while` it belongs to
+	UnstructuredJump { index: usize, target: isize },
+}
+
+/// Returns the first defect found in `func`, if any
+pub fn verify(func: &Function) -> Result<(), VerifyError> {
+	let instructions = &func.instructions;
+	let len = instructions.len() as isize;
+	for (index, instruction) in instructions.iter().enumerate() {
+		let target = match instruction {
+			Instruction::Goto(offset) => index as isize + offset,
+			Instruction::Ifz(_, offset) => (index + offset) as isize,
+			_ => continue,
+		};
+		if !(0..=len).contains(&target) {
+			return Err(VerifyError::JumpOutOfRange { index, target });
+		}
+	}
+
+	// Forward dataflow of everything possibly defined at the entry of each
+	// instruction, a definition on any incoming path is enough
+	let mut defined_at: Vec<Option<HashSet<Defined>>> = vec![None; instructions.len()];
+	if !instructions.is_empty() {
+		defined_at[0] = Some(HashSet::new());
+	}
+	let mut worklist = vec![0];
+	while let Some(index) = worklist.pop() {
+		let Some(mut defined) = defined_at.get(index).cloned().flatten() else {
+			continue;
+		};
+		match instructions[index] {
+			Instruction::ArrayAlloc(ident, _) => {
+				defined.insert(Defined::Array(ident));
+			}
+			Instruction::Expression(Operand::Temporary(id), _) => {
+				defined.insert(Defined::Temporary(id));
+			}
+			_ => (),
+		}
+		for successor in successors(instructions, index) {
+			let Some(entry) = defined_at.get_mut(successor) else {
+				continue;
+			};
+			let changed = match entry {
+				Some(existing) => {
+					let before = existing.len();
+					existing.extend(defined.iter().copied());
+					existing.len() != before
+				}
+				None => {
+					*entry = Some(defined.clone());
+					true
+				}
+			};
+			if changed {
+				worklist.push(successor);
+			}
+		}
+	}
+
+	for (index, instruction) in instructions.iter().enumerate() {
+		// Unreachable instructions can't read anything
+		let Some(defined) = &defined_at[index] else {
+			continue;
+		};
+		let read_temporary = |operand: &Operand| match operand {
+			Operand::Temporary(temporary) if !defined.contains(&Defined::Temporary(*temporary)) => {
+				Err(VerifyError::UndefinedTemporary {
+					index,
+					temporary: *temporary,
+				})
+			}
+			_ => Ok(()),
+		};
+		// Array parameters are allocated by the caller
+		let read_array = |ident: &Ident| {
+			if matches!(ident, Ident::Parameter(_)) || defined.contains(&Defined::Array(*ident)) {
+				Ok(())
+			} else {
+				Err(VerifyError::UndeclaredArray {
+					index,
+					ident: *ident,
+				})
+			}
+		};
+		match instruction {
+			Instruction::ArrayAlloc(..) | Instruction::Goto(_) => (),
+			Instruction::ArrayWrite(ident, array_index, r_val) => {
+				read_array(ident)?;
+				read_temporary(array_index)?;
+				read_temporary(r_val)?;
+			}
+			Instruction::Ifz(op, _) | Instruction::Return(op) | Instruction::Push(op) => {
+				read_temporary(op)?
+			}
+			Instruction::Expression(_, r_value) => match r_value {
+				RValue::FuncCall(..) => (),
+				RValue::Assignment(op) | RValue::BitwiseNot(op) | RValue::Negate(op) => {
+					read_temporary(op)?
+				}
+				RValue::Operation(lhs, _, rhs) => {
+					read_temporary(lhs)?;
+					read_temporary(rhs)?;
+				}
+				RValue::ArrayAccess(ident, array_index) => {
+					read_array(ident)?;
+					read_temporary(array_index)?;
+				}
+			},
+		}
+	}
+	Ok(())
+}
+
+/// Checks every jump of `func` belongs to a properly nested region: an `Ifz`
+/// skips the body of an `if` or exits a loop, and a `Goto` either loops back,
+/// breaks or continues an enclosing loop, or skips an `else` body.
+///
+/// Only holds for the output of `generate`, as passes like inlining introduce
+/// jumps of their own. Out of range jumps are left to `verify`.
+pub fn verify_structured(func: &Function) -> Result<(), VerifyError> {
+	structured_block(
+		&func.instructions,
+		0,
+		func.instructions.len(),
+		&mut Vec::new(),
+	)
+}
+
+/// Jump targets of a `while` enclosing a block
+struct Loop {
+	/// First instruction of the condition
+	start: usize,
+	/// First instruction of the body
+	body: usize,
+	/// Instruction right after the loop
+	exit: usize,
+	/// Start of the code without jumps out of it ending the body, the step of
+	/// a `for` or condition of a `do` which its `continue` lands within
+	step: usize,
+	/// The jump back to the condition
+	back_edge: usize,
+}
+
+/// Checks the jumps within `start..end`, where `loops` enclose the block
+fn structured_block(
+	instructions: &[Instruction],
+	start: usize,
+	end: usize,
+	loops: &mut Vec<Loop>,
+) -> Result<(), VerifyError> {
+	let mut index = start;
+	while index < end {
+		// A `do` loop ends with its condition, an `Ifz` leaving the loop and
+		// the jump back to the start of the body, the last one landing here
+		let do_back_edge = (index + 2..end).rev().find(|&i| {
+			matches!(instructions[i], Instruction::Goto(offset) if i as isize + offset == index as isize)
+		});
+		if let Some(back_edge) = do_back_edge
+			&& matches!(instructions[back_edge - 1], Instruction::Ifz(_, 2))
+		{
+			let condition_end = back_edge - 1;
+			loops.push(Loop {
+				start: index,
+				body: index,
+				exit: back_edge + 1,
+				step: jump_free_tail(instructions, index, condition_end),
+				back_edge,
+			});
+			let body = structured_block(instructions, index, condition_end, loops);
+			loops.pop();
+			body?;
+			index = back_edge + 1;
+			continue;
+		}
+		match instructions[index] {
+			Instruction::Ifz(_, offset) => {
+				let target = index + offset;
+				if offset == 0 || target > end {
+					return Err(VerifyError::UnstructuredJump {
+						index,
+						target: target as isize,
+					});
+				}
+				// A `while` ends with a jump back to its condition, whose own
+				// jumps, those of ternaries, stay within it
+				let back_edge = target - 1;
+				let loop_start = match instructions[back_edge] {
+					Instruction::Goto(offset) if back_edge > index => {
+						Some(back_edge as isize + offset).filter(|&loop_start| {
+							let condition = loop_start as usize..index;
+							(start as isize..=index as isize).contains(&loop_start)
+								&& instructions[condition.clone()].iter().enumerate().all(
+									|(i, instruction)| match *instruction {
+										Instruction::Ifz(_, offset) => {
+											condition.start + i + offset <= index
+										}
+										Instruction::Goto(_) => false,
+										_ => true,
+									},
+								)
+						})
+					}
+					_ => None,
+				};
+				// The body of an `if` with an `else` ends with a jump over the
+				// `else` body, a `break` ending it would leave the block
+				let else_end = match instructions[back_edge] {
+					Instruction::Goto(offset) if back_edge > index && offset > 0 => {
+						Some(back_edge + offset as usize).filter(|&else_end| else_end <= end)
+					}
+					_ => None,
+				};
+				match (loop_start, else_end) {
+					(Some(loop_start), _) => {
+						loops.push(Loop {
+							start: loop_start as usize,
+							body: index + 1,
+							exit: target,
+							step: jump_free_tail(instructions, index + 1, back_edge),
+							back_edge,
+						});
+						let body = structured_block(instructions, index + 1, back_edge, loops);
+						loops.pop();
+						body?;
+						index = target;
+					}
+					// A `continue` ending the body jumps to the loop's step exactly
+					// like an `else` skip, so read it as one only when that fits
+					(None, Some(else_end))
+						if structured_block(instructions, index + 1, back_edge, loops)
+							.and_then(|_| structured_block(instructions, target, else_end, loops))
+							.is_ok() =>
+					{
+						index = else_end;
+					}
+					(None, _) => {
+						structured_block(instructions, index + 1, target, loops)?;
+						index = target;
+					}
+				}
+			}
+			Instruction::Goto(offset) => {
+				let target = index as isize + offset;
+				if !loops.iter().any(|enclosing| {
+					[enclosing.start, enclosing.body, enclosing.exit].contains(&(target as usize))
+						|| (enclosing.step..=enclosing.back_edge).contains(&(target as usize))
+				}) {
+					return Err(VerifyError::UnstructuredJump { index, target });
+				}
+				index += 1;
+			}
+			_ => index += 1,
+		}
+	}
+	Ok(())
+}
+
+/// Start of the instructions ending `start..end` without any jump out of
+/// them, `end` when the last one is a jump
+fn jump_free_tail(instructions: &[Instruction], start: usize, end: usize) -> usize {
+	(start..end)
+		.rev()
+		.take_while(|&i| match instructions[i] {
+			Instruction::Goto(_) => false,
+			Instruction::Ifz(_, offset) => i + offset <= end,
+			_ => true,
+		})
+		.last()
+		.unwrap_or(end)
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum Defined {
+	Temporary(usize),
+	Array(Ident),
+}
+
+/// Indices of the instructions control can flow to after executing `index`,
+/// `instructions.len()` denotes falling off the end of the function
+fn successors(instructions: &[Instruction], index: usize) -> Vec<usize> {
+	match instructions[index] {
+		Instruction::Goto(offset) => vec![(index as isize + offset) as usize],
+		Instruction::Ifz(_, offset) => vec![index + 1, index + offset],
+		Instruction::Return(_) => Vec::new(),
+		_ => vec![index + 1],
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{
+		lexer::tokenize,
+		parser::{parse, Inlining},
+		tac_gen::generate,
+	};
+
+	#[test]
+	fn generated_code_verifies() {
+		let test_program = r"
+			int main(int n) {
+				int x, a[4];
+				x = 0;
+				while (x < 4) {
+					if (x == 2) {
+						break;
+					}
+					a[x] = x;
+					x = x + 1;
+				}
+				return a[1];
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		for func in generate(&parsed, table.0.len()) {
+			assert_eq!(Ok(()), verify(&func));
+		}
+	}
+
+	#[test]
+	fn structured_jumps() {
+		let test_program = r"
+			int main(int n) {
+				int x, y;
+				x = 0;
+				while (x < n) {
+					if (x == 2) {
+						x = x + 1;
+						continue;
+					}
+					y = 0;
+					while (1) {
+						if (y == x) {
+							break;
+						}
+						y = y + 1;
+					}
+					if (y > 5) {
+						break;
+					}
+					x = x + 1;
+				}
+				if (x) {
+					return x;
+				}
+				return 0;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let mut func = generate(&parsed, table.0.len()).remove(0);
+		assert_eq!(Ok(()), verify_structured(&func));
+		// The `break` out of the outer loop redirected into the following `if`,
+		// still within range
+		let (index, offset) = func
+			.instructions
+			.iter_mut()
+			.enumerate()
+			.filter_map(|(index, instruction)| match instruction {
+				Instruction::Goto(offset) if *offset > 0 => Some((index, offset)),
+				_ => None,
+			})
+			.nth(1)
+			.unwrap();
+		*offset += 5;
+		let target = index as isize + *offset;
+		assert_eq!(Ok(()), verify(&func));
+		assert_eq!(
+			Err(VerifyError::UnstructuredJump { index, target }),
+			verify_structured(&func)
+		);
+	}
+
+	#[test]
+	fn for_continue() {
+		// The `continue` ending the `if` is laid out the same as a jump over
+		// an `else`
+		let test_program = r"
+			int main(int n) {
+				int total, k;
+				total = 0;
+				for (k = 0; k < n; k += 1) {
+					if (k > 3) {
+						if (k == 4)
+							continue;
+					}
+					total = total + k;
+				}
+				return total;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let func = generate(&parsed, table.0.len()).remove(0);
+		assert_eq!(Ok(()), verify_structured(&func));
+	}
+
+	#[test]
+	fn do_loops() {
+		let test_program = r"
+			int main(int n) {
+				do {
+					do {
+						n -= 1;
+						if (n == 3)
+							continue;
+						if (n == 7)
+							break;
+					} while (n > 5);
+					if (n == 1)
+						break;
+				} while (n > 0);
+				return n;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let func = generate(&parsed, table.0.len()).remove(0);
+		assert_eq!(Ok(()), verify_structured(&func));
+	}
+
+	#[test]
+	fn jump_out_of_range() {
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![Instruction::Goto(-1)],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::JumpOutOfRange {
+				index: 0,
+				target: -1
+			}),
+			verify(&func)
+		);
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::Assignment(Operand::Immediate(1)),
+				),
+				Instruction::Ifz(Operand::Temporary(0), 3),
+			],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::JumpOutOfRange {
+				index: 1,
+				target: 4
+			}),
+			verify(&func)
+		);
+	}
+
+	#[test]
+	fn undefined_temporary() {
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![Instruction::Return(Operand::Temporary(0))],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::UndefinedTemporary {
+				index: 0,
+				temporary: 0
+			}),
+			verify(&func)
+		);
+		// Only written after the read
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::Expression(
+					Operand::Ident(Ident::Binded(0, 0)),
+					RValue::Assignment(Operand::Temporary(1)),
+				),
+				Instruction::Expression(
+					Operand::Temporary(1),
+					RValue::Assignment(Operand::Immediate(1)),
+				),
+			],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::UndefinedTemporary {
+				index: 0,
+				temporary: 1
+			}),
+			verify(&func)
+		);
+	}
+
+	#[test]
+	fn undeclared_array() {
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![Instruction::ArrayWrite(
+				Ident::Binded(1, 0),
+				Operand::Immediate(0),
+				Operand::Immediate(0),
+			)],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::UndeclaredArray {
+				index: 0,
+				ident: Ident::Binded(1, 0)
+			}),
+			verify(&func)
+		);
+		let func = Function {
+			id: 0,
+			names: None,
+			signature: None,
+			instructions: vec![
+				Instruction::ArrayAlloc(Ident::Binded(1, 0), 2),
+				Instruction::Expression(
+					Operand::Temporary(0),
+					RValue::ArrayAccess(Ident::Binded(1, 1), Operand::Immediate(0)),
+				),
+			],
+			lines: Vec::new(),
+			statics: Vec::new(),
+			inlining: Inlining::Auto,
+			literals: Vec::new(),
+			externals: Vec::new(),
+		};
+		assert_eq!(
+			Err(VerifyError::UndeclaredArray {
+				index: 1,
+				ident: Ident::Binded(1, 1)
+			}),
+			verify(&func)
+		);
+	}
+}
@@ -1,10 +1,15 @@
 //! x86 backend
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
+mod att;
+mod nasm;
+pub use att::att_syntax;
+pub use nasm::{nasm_lines, nasm_syntax};
+
 use crate::{
 	parser::{self, BinaryOperation},
-	tac_gen::{self, Function, Ident, Operand, RValue},
+	tac_gen::{self, call_graph, Function, Ident, Operand, RValue},
 };
 
 const PRELUDE: &str = r".intel_mnemonic
@@ -12,13 +17,160 @@ const PRELUDE: &str = r".intel_mnemonic
 .text
 ";
 
+const ATT_PRELUDE: &str = r".att_syntax
+.text
+";
+
 /// stack offset to access arguments, comprises of 8 bytes for the return address
 /// of the caller and 8 bytes for caller's `rbp`
 const ARGUMENTS_STACK_OFFSET: usize = 16;
 
+/// Every argument takes a full stack slot, wide enough to hold the base
+/// address of an array passed by reference
+const ARGUMENT_SIZE: usize = 8;
+
+/// Registers holding the first integer arguments of a call in the System V
+/// ABI, the following ones being passed on the stack
+const ARGUMENT_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// Bytes below `rsp` the System V ABI guarantees signal handlers leave
+/// untouched, a leaf function's locals fitting it need no stack reserved
+const RED_ZONE_SIZE: usize = 128;
+
+/// Knobs for the generated assembly
+#[derive(Debug, Default)]
+pub struct Config {
+	/// Precede the assembly of every TAC instruction with a comment of it
+	pub annotate: bool,
+	/// How runtime checks abort the program
+	pub trap: TrapStrategy,
+	/// Trap on division or modulo by zero instead of raising `SIGFPE`
+	pub div_guard: bool,
+	/// Emit a `_start` passing the argument count to `main` and exiting with
+	/// its result, for linking without libc
+	pub freestanding: bool,
+	/// Allocate the locals of non-recursive functions in `.bss` rather than on
+	/// the stack, making them non-reentrant
+	pub static_locals: bool,
+	pub syntax: Syntax,
+	/// Embed the source's path and hash in the `.comment` section
+	pub record_source: Option<SourceRecord>,
+	/// Count the calls of every function in a 64 bit global, see
+	/// `profile_label`
+	pub profile: bool,
+}
+
+/// Provenance of the generated assembly, tracing the object back to its source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRecord {
+	pub path: String,
+	/// FNV-1a hash of the source, unlike `std::hash` stable across compiler
+	/// builds so recompiling the same source reproduces the same output
+	pub hash: u64,
+}
+impl SourceRecord {
+	pub fn new(path: &str, source: &str) -> Self {
+		let hash = source.bytes().fold(0xcbf29ce484222325, |hash: u64, byte| {
+			(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+		});
+		Self {
+			path: path.to_string(),
+			hash,
+		}
+	}
+	/// Mergeable string section, the linker keeps one copy of each string
+	fn section(&self) -> String {
+		let path = self.path.replace('\\', "\\\\").replace('"', "\\\"");
+		format!(
+			"\n.section .comment, \"MS\", @progbits, 1\n\t.string \"ezc: {path} fnv1a:{:016x}\"\n.text\n",
+			self.hash
+		)
+	}
+}
+
+/// Assembler syntax of the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+	#[default]
+	Intel,
+	/// Rewritten from the Intel syntax by `att_syntax`
+	Att,
+	/// Rewritten from the Intel syntax by `nasm_syntax`
+	Nasm,
+}
+impl std::str::FromStr for Syntax {
+	type Err = String;
+	/// Parses one of `intel`, `att` or `nasm`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"intel" => Ok(Self::Intel),
+			"att" => Ok(Self::Att),
+			"nasm" => Ok(Self::Nasm),
+			_ => Err(format!("Unknown syntax: '{s}'")),
+		}
+	}
+}
+
+/// Mechanism shared by every trap site to abort the program
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TrapStrategy {
+	/// Invalid opcode, for freestanding targets
+	#[default]
+	Ud2,
+	/// libc's `abort`, for hosted targets
+	CallAbort,
+	/// `exit` syscall with the given status, for hosted targets without libc
+	ExitSyscall(i32),
+}
+impl std::str::FromStr for TrapStrategy {
+	type Err = String;
+	/// Parses one of `ud2`, `abort`, `exit` or `exit:<code>`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.split_once(':') {
+			None if s == "ud2" => Ok(Self::Ud2),
+			None if s == "abort" => Ok(Self::CallAbort),
+			None if s == "exit" => Ok(Self::ExitSyscall(1)),
+			Some(("exit", code)) => code
+				.parse()
+				.map(Self::ExitSyscall)
+				.map_err(|_| format!("Invalid exit code: '{code}'")),
+			_ => Err(format!("Unknown trap strategy: '{s}'")),
+		}
+	}
+}
+impl TrapStrategy {
+	fn instructions(&self) -> Vec<String> {
+		match self {
+			Self::Ud2 => vec![format!("ud2")],
+			Self::CallAbort => vec![format!("call abort@PLT")],
+			Self::ExitSyscall(code) => vec![
+				format!("mov %edi, {code}"),
+				format!("mov %eax, 60"),
+				format!("syscall"),
+			],
+		}
+	}
+}
+
 pub fn x86_gen(
 	tac_instruction: Vec<tac_gen::Function>,
 	ident_table: parser::IdentNameTable,
+	config: &Config,
+) -> String {
+	x86_gen_with(tac_instruction, ident_table, config, |_, _, generate| {
+		generate()
+	})
+}
+
+/// Same as `x86_gen`, the assembly of every function obtained through
+/// `function_text`. It's handed the function, whether its locals are static
+/// and a closure generating its assembly, letting callers reuse assembly
+/// generated before, see `cache::Cache`
+pub fn x86_gen_with(
+	tac_instruction: Vec<tac_gen::Function>,
+	ident_table: parser::IdentNameTable,
+	config: &Config,
+	mut function_text: impl FnMut(&Function, bool, &dyn Fn() -> String) -> String,
 ) -> String {
 	let mut res = PRELUDE.to_string();
 
@@ -34,158 +186,573 @@ pub fn x86_gen(
 		})
 		.as_str();
 
-	for tac_gen::Function {
+	if config.freestanding {
+		let Some(main) = ident_table.0.iter().position(|name| name == "main") else {
+			panic!("No `main` to call from `_start`");
+		};
+		let _ = write!(
+			res,
+			r"
+.global _start
+_start:
+	mov %rax, QWORD PTR [%rsp]
+	push %rax
+	call F{main}
+	mov %edi, %eax
+	mov %eax, 60
+	syscall"
+		);
+	}
+
+	// Calls always target the `F{id}` label, which for the external functions
+	// is an alias of the symbol resolved by the linker
+	let defined: HashSet<usize> = tac_instruction.iter().map(|function| function.id).collect();
+	let mut external: Vec<usize> = tac_instruction
+		.iter()
+		.flat_map(|function| function.instructions.iter())
+		.filter_map(|instruction| match instruction {
+			tac_gen::Instruction::Expression(_, RValue::FuncCall(id, _)) => Some(*id),
+			_ => None,
+		})
+		.filter(|id| !defined.contains(id))
+		.collect();
+	external.sort_unstable();
+	external.dedup();
+	for id in external {
+		let _ = write!(res, "\n.set F{id}, {}", ident_table.0[id]);
+	}
+
+	let static_locals = statically_allocated(&tac_instruction, config);
+	for function in tac_instruction.iter() {
+		let is_static = static_locals.contains(&function.id);
+		let generate = || function_asm(function, &ident_table, config, is_static);
+		res += &function_text(function, is_static, &generate);
+	}
+	res += &literals(&tac_instruction);
+	if let Some(record) = &config.record_source {
+		res += &record.section();
+	}
+	match config.syntax {
+		Syntax::Intel => res,
+		Syntax::Att => ATT_PRELUDE.to_string() + &att_syntax(&res[PRELUDE.len()..]),
+		Syntax::Nasm => nasm_syntax(&res[PRELUDE.len()..]),
+	}
+}
+
+/// Text of `function_gen`'s assembly, each TAC instruction preceded by a
+/// comment of it when annotating
+fn function_asm(
+	function: &Function,
+	ident_table: &parser::IdentNameTable,
+	config: &Config,
+	static_locals: bool,
+) -> String {
+	let FunctionAsm {
+		prologue,
+		body,
+		epilogue,
+		..
+	} = function_gen(function, ident_table, config, static_locals);
+	let mut res = prologue;
+	for (i, (tac, asm_set)) in function.instructions.iter().zip(body).enumerate() {
+		if config.annotate || log::log_enabled!(log::Level::Debug) {
+			let _ = writeln!(res, "\n# {i}: {}", tac.named(Some(&ident_table.0)));
+		}
+		for instruction in asm_set {
+			let _ = writeln!(res, "\t{instruction}");
+		}
+	}
+	res + &epilogue
+}
+
+/// Assembly of a single function
+#[derive(Debug)]
+pub struct FunctionAsm {
+	/// Labels and the stack frame setup
+	pub prologue: String,
+	/// Instructions generated for every TAC instruction, in order
+	pub body: Vec<Vec<String>>,
+	/// Stack frame teardown, every `Return` jumps here
+	pub epilogue: String,
+	/// Bytes of the frame holding the locals and temporaries, zero when
+	/// they live in `.bss`
+	pub stack_usage: usize,
+}
+
+/// Ids of the functions whose locals live in `.bss`, the non-recursive ones
+/// when `Config::static_locals` is set
+pub fn statically_allocated(functions: &[Function], config: &Config) -> HashSet<usize> {
+	if !config.static_locals {
+		return HashSet::new();
+	}
+	let recursive = tac_gen::call_graph::recursive_functions(functions);
+	functions
+		.iter()
+		.map(|function| function.id)
+		.filter(|id| !recursive.contains(id))
+		.collect()
+}
+
+/// `static_locals` places the locals at fixed addresses below the `S{id}`
+/// label, see `statically_allocated`
+pub fn function_gen(
+	function: &Function,
+	ident_table: &parser::IdentNameTable,
+	config: &Config,
+	static_locals: bool,
+) -> FunctionAsm {
+	let Function {
 		id: func_id,
 		instructions,
-	} in tac_instruction.iter()
-	{
-		let func_name = ident_table.0[*func_id].as_str();
-		res += format!(
+		..
+	} = function;
+	let func_name = ident_table.0[*func_id].as_str();
+	let mut if_count = 0;
+	let mut goto_count = 0;
+	// Stores the list of instructions
+	let mut if_jumps = Vec::new();
+	let mut goto_jumps = Vec::new();
+	let mut allocator = StackAllocator {
+		static_label: static_locals.then(|| format!("S{func_id}")),
+		statics: function
+			.statics
+			.iter()
+			.map(|&(ident, _)| (ident, static_label(func_name, ident, ident_table)))
+			.collect(),
+		externals: function.externals.iter().copied().collect(),
+		..Default::default()
+	};
+	use tac_gen::Instruction;
+	for (i, instruction) in instructions.iter().enumerate() {
+		match instruction {
+			Instruction::Goto(offset) => {
+				goto_jumps.push(i as isize + *offset);
+			}
+			Instruction::Ifz(_, offset) => {
+				if_jumps.push(i + *offset);
+			}
+			_ => continue,
+		}
+	}
+	// A comparison only feeding the following `Ifz` sets the flags for a
+	// conditional jump directly, instead of materializing the boolean. Not
+	// possible when the `Ifz` is a jump target, as the flags would be stale.
+	// `Temporary(0)` is never read past the statement it is written in, any
+	// other temporary must not be read elsewhere.
+	let is_jump_target =
+		|index: usize| if_jumps.contains(&index) || goto_jumps.contains(&(index as isize));
+	let mut temporary_reads: HashMap<usize, usize> = HashMap::new();
+	for mut instruction in instructions.iter().copied() {
+		// The first operand of an `Expression` is the one written
+		let written = matches!(instruction, Instruction::Expression(..)) as usize;
+		for operand in instruction.operands_mut().into_iter().skip(written) {
+			if let Operand::Temporary(id) = *operand {
+				*temporary_reads.entry(id).or_default() += 1;
+			}
+		}
+	}
+	let fused: HashMap<usize, BinaryOperation> = instructions
+		.windows(2)
+		.enumerate()
+		.filter_map(|(i, pair)| match pair {
+			[Instruction::Expression(
+				Operand::Temporary(written),
+				RValue::Operation(_, operation, _),
+			), Instruction::Ifz(Operand::Temporary(read), _)]
+				if written == read
+					&& (*read == 0 || temporary_reads[read] == 1)
+					&& inverse_jump(*operation).is_some()
+					&& !is_jump_target(i + 1) =>
+			{
+				Some((i + 1, *operation))
+			}
+			_ => None,
+		})
+		.collect();
+	// A fused `Ifz` skipping a single plain assignment, the shape of
+	// `if (a < b) x = y;`, selects the value with a conditional move instead
+	// of branching around it. Keyed by the index of the `Ifz`.
+	let selects: HashMap<usize, (Operand, Operand)> = fused
+		.keys()
+		.filter_map(|&i| match instructions[i..] {
+			[Instruction::Ifz(_, 2), Instruction::Expression(dest, RValue::Assignment(src)), ..]
+				if !is_jump_target(i + 1) =>
+			{
+				Some((i, (dest, src)))
+			}
+			_ => None,
+		})
+		.collect();
+	let mut asm_instructions: Vec<Vec<String>> = instructions
+		.iter()
+		.enumerate()
+		.map(|(i, tac)| match tac {
+			Instruction::Expression(_, RValue::Operation(lhs, _, rhs))
+				if fused.contains_key(&(i + 1)) =>
+			{
+				vec![
+					format!("mov %eax, {}", allocator.parse_operand(*lhs)),
+					format!("cmp %eax, {}", allocator.parse_operand(*rhs)),
+				]
+			}
+			Instruction::Ifz(_, _) if let Some(&(dest, src)) = selects.get(&i) => {
+				if_count += 1;
+				let dest = allocator.parse_operand(dest);
+				vec![
+					format!("mov %ecx, {}", allocator.parse_operand(src)),
+					format!("mov %eax, {dest}"),
+					format!("{} %eax, %ecx", conditional_move(fused[&i]).unwrap()),
+					format!("mov {dest}, %eax"),
+				]
+			}
+			Instruction::Expression(..) if i > 0 && selects.contains_key(&(i - 1)) => Vec::new(),
+			Instruction::Ifz(_, _) if let Some(&operation) = fused.get(&i) => {
+				if_count += 1;
+				let jump = inverse_jump(operation).unwrap();
+				vec![format!("{jump} L{}_{func_id}", if_count - 1)]
+			}
+			Instruction::ArrayWrite(name, index, r_val) => {
+				let mut asm = allocator.array_address(*name, *index);
+				asm.push(format!("mov %eax, {}", allocator.parse_operand(*r_val)));
+				asm.push("mov DWORD PTR [%rsi], %eax".to_string());
+				asm
+			}
+			Instruction::ArrayAlloc(name, size) => {
+				allocator.array_alloc(*name, *size);
+				Vec::new()
+			}
+			Instruction::Return(op) => vec![
+				format!("mov %eax, {}", allocator.parse_operand(*op)),
+				format!("jmp END_{func_id}"),
+			],
+			Instruction::Push(op) => {
+				vec![allocator.load_argument(*op), format!("push %rax")]
+			}
+			Instruction::Expression(op, r_value) => allocator.expression_gen(*op, *r_value, config),
+			Instruction::Ifz(op, _) => {
+				if_count += 1;
+				vec![
+					format!("cmp {}, 0", allocator.parse_operand(*op)),
+					format!("je L{}_{func_id}", if_count - 1),
+				]
+			}
+			Instruction::Goto(_) => {
+				goto_count += 1;
+				vec![format!("jmp G{}_{func_id}", goto_count - 1)]
+			}
+		})
+		.collect();
+	if_jumps
+		.iter()
+		.enumerate()
+		.for_each(|(label_id, &tac_index)| {
+			if let Some(asm) = asm_instructions.get_mut(tac_index) {
+				asm.insert(0, format!("L{label_id}_{func_id}:"));
+			} else if let Some(last) = asm_instructions.last_mut() {
+				last.push(format!("L{label_id}_{func_id}:"));
+			}
+		});
+	goto_jumps
+		.iter()
+		.enumerate()
+		.for_each(|(label_id, &tac_index)| {
+			let tac_index = tac_index as usize;
+			if let Some(asm) = asm_instructions.get_mut(tac_index) {
+				asm.insert(0, format!("G{label_id}_{func_id}:"));
+			} else if let Some(last) = asm_instructions.last_mut() {
+				last.push(format!("G{label_id}_{func_id}:"));
+			};
+		});
+	// Parameters are still addressed through `rbp`, only the stack space
+	// reserved for the locals goes away
+	let in_red_zone = call_graph::is_leaf(function) && allocator.stack_usage <= RED_ZONE_SIZE;
+	let (reserve, release, bss) = match &allocator.static_label {
+		Some(label) => (
+			String::new(),
+			String::new(),
+			format!(
+				r".bss
+	.skip {}
+{label}:
+.text
+",
+				allocator.stack_usage
+			),
+		),
+		None if in_red_zone => (String::new(), String::new(), String::new()),
+		None => (
+			format!("\tsub %rsp, {}\n", allocator.stack_usage),
+			format!("\tadd %rsp, {}\n", allocator.stack_usage),
+			String::new(),
+		),
+	};
+	let data = match function.statics.is_empty() {
+		true => String::new(),
+		false => {
+			let values: String = function
+				.statics
+				.iter()
+				.map(|(ident, value)| format!("{}:\n\t.long {value}\n", allocator.statics[ident]))
+				.collect();
+			format!(".data\n{values}.text\n")
+		}
+	};
+	let (count, counter) = match config.profile {
+		true => {
+			let label = profile_label(func_name);
+			(
+				format!("\tadd QWORD PTR [%rip + {label}], 1\n"),
+				format!(".bss\n.global {label}\n{label}:\n\t.skip 8\n.text\n"),
+			)
+		}
+		false => (String::new(), String::new()),
+	};
+	let header = match &function.signature {
+		Some(signature) => format!("# {signature}\n"),
+		None => String::new(),
+	};
+	// The CFI directives track the frame setup, so unwinders can walk
+	// through the function. The CFA stays relative to `rbp` in the body,
+	// unaffected by the pushes of arguments.
+	FunctionAsm {
+		prologue: format!(
 			r"
-{func_name}:
+{header}{func_name}:
 F{func_id}:
+	.cfi_startproc
 	push %rbp
+	.cfi_def_cfa_offset 16
+	.cfi_offset %rbp, -16
 	mov %rbp, %rsp
-"
-		)
-		.as_str();
-		let mut if_count = 0;
-		let mut goto_count = 0;
-		// Stores the list of instructions
-		let mut if_jumps = Vec::new();
-		let mut goto_jumps = Vec::new();
-		let mut allocator = StackAllocator::default();
-		use tac_gen::Instruction;
-		for (i, instruction) in instructions.iter().enumerate() {
-			match instruction {
-				Instruction::Goto(offset) => {
-					goto_jumps.push(i as isize + *offset);
-				}
-				Instruction::Ifz(_, offset) => {
-					if_jumps.push(i + *offset);
-				}
-				_ => continue,
-			}
-		}
-		let mut asm_instructions: Vec<Vec<String>> = instructions
+	.cfi_def_cfa_register %rbp
+{reserve}{count}",
+		),
+		body: asm_instructions,
+		epilogue: format!(
+			r"END_{func_id}:
+{release}	pop %rbp
+	.cfi_def_cfa %rsp, 8
+	ret
+	.cfi_endproc
+{bss}{data}{counter}",
+		),
+		stack_usage: match allocator.static_label {
+			Some(_) => 0,
+			None => allocator.stack_usage,
+		},
+	}
+}
+
+/// Ids and frame sizes of the functions whose frame exceeds `max_stack`
+/// bytes, in order of definition
+pub fn oversized_frames(
+	functions: &[Function],
+	ident_table: &parser::IdentNameTable,
+	config: &Config,
+	max_stack: usize,
+) -> Vec<(usize, usize)> {
+	frame_sizes(functions, ident_table, config)
+		.into_iter()
+		.filter(|&(_, stack_usage)| stack_usage > max_stack)
+		.collect()
+}
+
+/// Ids and frame sizes of every function, in order of definition
+pub fn frame_sizes(
+	functions: &[Function],
+	ident_table: &parser::IdentNameTable,
+	config: &Config,
+) -> Vec<(usize, usize)> {
+	let static_locals = statically_allocated(functions, config);
+	functions
+		.iter()
+		.map(|function| {
+			let asm = function_gen(
+				function,
+				ident_table,
+				config,
+				static_locals.contains(&function.id),
+			);
+			(function.id, asm.stack_usage)
+		})
+		.collect()
+}
+
+/// Number of instructions in the generated `asm`, of any `Syntax`. Labels,
+/// directives, data and comments aren't counted
+pub fn instruction_count(asm: &str) -> usize {
+	asm.lines()
+		.filter(|line| line.starts_with([' ', '\t']))
+		.map(str::trim)
+		.filter(|line| line.starts_with(|char: char| char.is_ascii_lowercase()))
+		.filter(|line| !line.ends_with(':'))
+		.filter(|line| !matches!(line.split(' ').next(), Some("resb" | "dd" | "db")))
+		.count()
+}
+
+/// Global holding the number of calls to the function with `--profile`, for
+/// the program to read or dump on exit as `extern long __ezc_calls_main;`
+pub fn profile_label(func_name: &str) -> String {
+	format!("__ezc_calls_{func_name}")
+}
+
+/// `STR{index}` label of the string literal, its length without the NUL
+/// terminator being `STR{index}_len`
+fn literal_label(index: usize) -> String {
+	format!("STR{index}")
+}
+
+/// `.rodata` holding every string literal passed by the `functions` once, NUL
+/// terminated
+fn literals(functions: &[Function]) -> String {
+	let mut literals: Vec<&(usize, Vec<u8>)> = functions
+		.iter()
+		.flat_map(|function| &function.literals)
+		.collect();
+	if literals.is_empty() {
+		return String::new();
+	}
+	literals.sort_unstable_by_key(|(index, _)| *index);
+	literals.dedup_by_key(|(index, _)| *index);
+	let mut res = "\n.section .rodata\n".to_string();
+	for (index, bytes) in literals {
+		// Anything but printable ASCII is spelled as an octal escape, which
+		// GAS and NASM read the same
+		let string: String = bytes
 			.iter()
-			.enumerate()
-			.map(|(i, tac)| {
-				let mut asm = Vec::new();
-				if log::log_enabled!(log::Level::Debug) {
-					asm.push(format!("\n# {i}: {tac:?}"));
-				}
-				asm.append(&mut match tac {
-					Instruction::ArrayWrite(name, index, r_val) => vec![
-						format!("mov %edi, {}", allocator.parse_operand(*index)),
-						format!("shl %rdi"),
-						format!("shl %rdi"),
-						format!("mov %rsi, %rbp"),
-						format!("sub %rsi, {}", allocator.ident_table.get(name).unwrap()),
-						format!("add %rsi, %rdi"),
-						format!("mov %eax, {}", allocator.parse_operand(*r_val)),
-						format!("mov DWORD PTR [%rsi], %eax"),
-					],
-					Instruction::ArrayAlloc(name, size) => {
-						allocator.array_alloc(*name, *size);
-						Vec::new()
-					}
-					Instruction::Return(op) => vec![
-						format!("mov %eax, {}", allocator.parse_operand(*op)),
-						format!("jmp END_{func_id}"),
-					],
-					Instruction::Push(op) => vec![
-						format!("mov %eax, {}", allocator.parse_operand(*op)),
-						format!("sub %rsp, {}", INTEGER_SIZE),
-						format!("mov DWORD PTR [%rsp], %eax"),
-					],
-					Instruction::Expression(op, r_value) => allocator.expression_gen(*op, *r_value),
-					Instruction::Ifz(op, _) => {
-						if_count += 1;
-						vec![
-							format!("cmp {}, 0", allocator.parse_operand(*op)),
-							format!("je L{}_{func_id}", if_count - 1),
-						]
-					}
-					Instruction::Goto(_) => {
-						goto_count += 1;
-						vec![format!("jmp G{}_{func_id}", goto_count - 1)]
-					}
-				});
-				asm
+			.map(|&byte| match byte {
+				b'"' | b'\\' => format!("\\{}", byte as char),
+				b' '..=b'~' => (byte as char).to_string(),
+				_ => format!("\\{byte:03o}"),
 			})
 			.collect();
-		if_jumps
-			.iter()
-			.enumerate()
-			.for_each(|(label_id, &tac_index)| {
-				if let Some(asm) = asm_instructions.get_mut(tac_index) {
-					asm.insert(0, format!("L{label_id}_{func_id}:"));
-				} else if let Some(last) = asm_instructions.last_mut() {
-					last.push(format!("L{label_id}_{func_id}:"));
-				}
-			});
-		goto_jumps
-			.iter()
-			.enumerate()
-			.for_each(|(label_id, &tac_index)| {
-				let tac_index = tac_index as usize;
-				if let Some(asm) = asm_instructions.get_mut(tac_index) {
-					asm.insert(0, format!("G{label_id}_{func_id}:"));
-				} else if let Some(last) = asm_instructions.last_mut() {
-					last.push(format!("G{label_id}_{func_id}:"));
-				};
-			});
-		res += format!("	sub %rsp, {}\n", allocator.stack_usage).as_str();
-		res.push_str(
-			asm_instructions
-				.iter()
-				.flat_map(|asm_set| {
-					asm_set
-						.iter()
-						.map(|instruction| format!("\t{instruction}\n"))
-				})
-				.collect::<String>()
-				.as_str(),
+		let _ = write!(
+			res,
+			"{label}:\n\t.string \"{string}\"\n.type {label}, @object\n.size {label}, {}\n.equ {label}_len, {}\n",
+			bytes.len() + 1,
+			bytes.len(),
+			label = literal_label(*index),
 		);
-		res += format!(
-			r"END_{func_id}:
-	add %rsp, {}
-	pop %rbp
-	ret
-",
-			allocator.stack_usage
-		)
-		.as_str();
 	}
-	res
+	res + ".text\n"
+}
+
+/// `function.name.scope_id` label of the storage of a `static` variable, as
+/// every function may have its own `count`
+fn static_label(function: &str, ident: Ident, ident_table: &parser::IdentNameTable) -> String {
+	let Ident::Static(name_index, scope_id) = ident else {
+		unreachable!("Only statics have a label")
+	};
+	format!("{function}.{}.{scope_id}", ident_table.0[name_index])
+}
+
+/// Conditional move taken when the comparison `operation` is true
+fn conditional_move(operation: BinaryOperation) -> Option<&'static str> {
+	match operation {
+		BinaryOperation::Less => Some("cmovl"),
+		BinaryOperation::LessEqual => Some("cmovle"),
+		BinaryOperation::Greater => Some("cmovg"),
+		BinaryOperation::GreaterEqual => Some("cmovge"),
+		BinaryOperation::Equal => Some("cmove"),
+		BinaryOperation::NotEqual => Some("cmovne"),
+		_ => None,
+	}
+}
+
+/// Conditional jump taken when the comparison `operation` is false
+fn inverse_jump(operation: BinaryOperation) -> Option<&'static str> {
+	match operation {
+		BinaryOperation::Less => Some("jge"),
+		BinaryOperation::LessEqual => Some("jg"),
+		BinaryOperation::Greater => Some("jle"),
+		BinaryOperation::GreaterEqual => Some("jl"),
+		BinaryOperation::Equal => Some("jne"),
+		BinaryOperation::NotEqual => Some("je"),
+		_ => None,
+	}
 }
 
 const INTEGER_SIZE: usize = 4;
 
+/// Call of an external function through the System V calling convention, its
+/// arguments pushed right to left like those of any other call. The first six
+/// are popped into `ARGUMENT_REGISTERS`, the others are copied below a 16 byte
+/// aligned `%rsp`, above which the unaligned one is saved to be restored after
+/// the call along with dropping the pushed arguments. Variadic functions are
+/// told through `%al` that no vector registers hold arguments
+fn system_v_call(func_id: usize, arg_count: usize, variadic: bool) -> Vec<String> {
+	let in_registers = arg_count.min(ARGUMENT_REGISTERS.len());
+	let on_stack = arg_count - in_registers;
+	let mut res: Vec<String> = ARGUMENT_REGISTERS[..in_registers]
+		.iter()
+		.map(|register| format!("pop {register}"))
+		.collect();
+	res.push("mov %rax, %rsp".to_string());
+	res.push("and %rsp, -16".to_string());
+	// The saved `%rsp` and the copies have to add up to an even count of
+	// slots
+	if on_stack.is_multiple_of(2) {
+		res.push("sub %rsp, 8".to_string());
+	}
+	res.push("push %rax".to_string());
+	for i in (0..on_stack).rev() {
+		res.push(format!("push QWORD PTR [%rax + {}]", i * ARGUMENT_SIZE));
+	}
+	if variadic {
+		res.push("mov %eax, 0".to_string());
+	}
+	res.push(format!("call F{func_id}"));
+	res.push(format!(
+		"mov %rsp, QWORD PTR [%rsp + {}]",
+		on_stack * ARGUMENT_SIZE
+	));
+	if on_stack > 0 {
+		res.push(format!("add %rsp, {}", on_stack * ARGUMENT_SIZE));
+	}
+	res
+}
+
 #[derive(Debug, Default)]
 struct StackAllocator {
 	stack_usage: usize,
 	ident_table: HashMap<Ident, usize>,
+	arrays: HashSet<Ident>,
 	arguments_size: usize,
 	temporary_offset: HashMap<usize, usize>,
+	/// Label the locals are addressed below instead of `rbp`
+	static_label: Option<String>,
+	/// Label of every `Ident::Static`, see `static_label`
+	statics: HashMap<Ident, String>,
+	/// Functions called through `system_v_call`, and whether they're variadic
+	externals: HashMap<usize, bool>,
 }
 impl StackAllocator {
+	/// Address of the local at `offset`
+	fn local(&self, offset: usize) -> String {
+		match &self.static_label {
+			Some(label) => format!("[%rip + {label} - {offset}]"),
+			None => format!("[%rbp - {offset}]"),
+		}
+	}
 	fn parse_operand(&mut self, operand: Operand) -> String {
 		match operand {
 			Operand::Ident(Ident::Parameter(offset)) => {
 				format!(
 					"DWORD PTR [%rbp + {}]",
-					ARGUMENTS_STACK_OFFSET + offset * INTEGER_SIZE
+					ARGUMENTS_STACK_OFFSET + offset * ARGUMENT_SIZE
 				)
 			}
+			Operand::Ident(ident @ Ident::Static(..)) => {
+				format!("DWORD PTR [%rip + {}]", self.statics[&ident])
+			}
+			Operand::Ident(Ident::Literal(_)) => unreachable!("Literals are only pushed"),
 			Operand::Ident(ident) => {
 				let offset = *self.ident_table.get(&ident).unwrap_or_else(|| {
 					self.stack_usage += INTEGER_SIZE;
 					&self.stack_usage
 				});
 				self.ident_table.insert(ident, offset);
-				format!("DWORD PTR [%rbp - {offset}]")
+				format!("DWORD PTR {}", self.local(offset))
 			}
 			Operand::Temporary(id) => {
 				let offset = *self.temporary_offset.get(&id).unwrap_or_else(|| {
@@ -193,7 +760,7 @@ impl StackAllocator {
 					&self.stack_usage
 				});
 				self.temporary_offset.insert(id, offset);
-				format!("DWORD PTR [%rbp - {offset}]")
+				format!("DWORD PTR {}", self.local(offset))
 			}
 			Operand::Immediate(val) => val.to_string(),
 		}
@@ -201,20 +768,70 @@ impl StackAllocator {
 	fn array_alloc(&mut self, name: Ident, size: u32) {
 		self.stack_usage += INTEGER_SIZE * size as usize;
 		self.ident_table.insert(name, self.stack_usage);
+		self.arrays.insert(name);
+		log::trace!("{name:?}[{size}] allocated at offset {}", self.stack_usage);
+	}
+	/// Loads the base address of `name` into `%rsi`, a parameter holds the
+	/// address of the caller's array whereas a local array lives in the frame
+	fn array_base(&self, name: Ident) -> Vec<String> {
+		match name {
+			Ident::Parameter(offset) => vec![format!(
+				"mov %rsi, QWORD PTR [%rbp + {}]",
+				ARGUMENTS_STACK_OFFSET + offset * ARGUMENT_SIZE
+			)],
+			Ident::Binded(..) if self.static_label.is_some() => {
+				vec![format!("lea %rsi, {}", self.local(self.ident_table[&name]))]
+			}
+			Ident::Binded(..) => vec![
+				format!("mov %rsi, %rbp"),
+				format!("sub %rsi, {}", self.ident_table.get(&name).unwrap()),
+			],
+			Ident::Static(..) => unreachable!("Only scalars can be static"),
+			Ident::Literal(_) => unreachable!("Literals are never indexed"),
+		}
+	}
+	/// Loads the address of `name[index]` into `%rsi`
+	fn array_address(&mut self, name: Ident, index: Operand) -> Vec<String> {
+		log::trace!("Addressing {name:?}[{index:?}]");
+		let mut res = vec![
+			format!("mov %edi, {}", self.parse_operand(index)),
+			format!("shl %rdi"),
+			format!("shl %rdi"),
+		];
+		res.append(&mut self.array_base(name));
+		res.push("add %rsi, %rdi".to_string());
+		res
+	}
+	/// Loads the value to be pushed as an argument into `%rax`, arrays are
+	/// passed as their base address
+	fn load_argument(&mut self, op: Operand) -> String {
+		match op {
+			Operand::Ident(ident @ Ident::Binded(..)) if self.arrays.contains(&ident) => {
+				format!("lea %rax, {}", self.local(self.ident_table[&ident]))
+			}
+			Operand::Ident(Ident::Literal(index)) => {
+				format!("lea %rax, [%rip + {}]", literal_label(index))
+			}
+			// Parameters are copied whole, as they might be an array's address
+			Operand::Ident(Ident::Parameter(offset)) => format!(
+				"mov %rax, QWORD PTR [%rbp + {}]",
+				ARGUMENTS_STACK_OFFSET + offset * ARGUMENT_SIZE
+			),
+			op => format!("mov %eax, {}", self.parse_operand(op)),
+		}
 	}
-	fn expression_gen(&mut self, l_value: Operand, r_value: RValue) -> Vec<String> {
+	fn expression_gen(
+		&mut self,
+		l_value: Operand,
+		r_value: RValue,
+		config: &Config,
+	) -> Vec<String> {
 		match r_value {
 			RValue::ArrayAccess(ident, index) => {
-				vec![
-					format!("mov %edi, {}", self.parse_operand(index)),
-					format!("shl %rdi"),
-					format!("shl %rdi"),
-					format!("mov %rsi, %rbp"),
-					format!("sub %rsi, {}", &self.ident_table.get(&ident).unwrap()),
-					format!("add %rsi, %rdi"),
-					format!("mov %eax, DWORD PTR [%rsi]"),
-					format!("mov {}, %eax", self.parse_operand(l_value)),
-				]
+				let mut res = self.array_address(ident, index);
+				res.push("mov %eax, DWORD PTR [%rsi]".to_string());
+				res.push(format!("mov {}, %eax", self.parse_operand(l_value)));
+				res
 			}
 			RValue::Assignment(Operand::Immediate(val)) => {
 				vec![format!("mov {}, {}", self.parse_operand(l_value), val)]
@@ -223,18 +840,38 @@ impl StackAllocator {
 				format!("mov %eax, {}", self.parse_operand(r_value)),
 				format!("mov {}, %eax", self.parse_operand(l_value)),
 			],
+			RValue::BitwiseNot(r_value) => vec![
+				format!("mov %eax, {}", self.parse_operand(r_value)),
+				"not %eax".to_string(),
+				format!("mov {}, %eax", self.parse_operand(l_value)),
+			],
+			RValue::Negate(r_value) => vec![
+				format!("mov %eax, {}", self.parse_operand(r_value)),
+				"neg %eax".to_string(),
+				format!("mov {}, %eax", self.parse_operand(l_value)),
+			],
+			RValue::FuncCall(func_id, arg_count)
+				if let Some(&variadic) = self.externals.get(&func_id) =>
+			{
+				self.arguments_size = 0;
+				let mut res = system_v_call(func_id, arg_count, variadic);
+				res.push(format!("mov {}, %eax", self.parse_operand(l_value)));
+				res
+			}
 			RValue::FuncCall(func_id, arg_count) => {
 				self.arguments_size = 0;
 				vec![
 					format!("call F{func_id}"),
 					format!("mov {}, %eax", self.parse_operand(l_value)),
-					format!("add %rsp, {}", arg_count * INTEGER_SIZE),
+					format!("add %rsp, {}", arg_count * ARGUMENT_SIZE),
 				]
 			}
 			RValue::Operation(lhs, operation, rhs) => {
 				enum Operation {
 					Arithmetic(&'static str),
 					Conditional(&'static str),
+					// The count has to be in `%cl`
+					Shift(&'static str),
 					// These require special code gen
 					Mul,
 					Div,
@@ -246,6 +883,8 @@ impl StackAllocator {
 					BinaryOperation::And => Operation::Arithmetic("and"),
 					BinaryOperation::Or => Operation::Arithmetic("or"),
 					BinaryOperation::Xor => Operation::Arithmetic("xor"),
+					BinaryOperation::ShiftLeft => Operation::Shift("sal"),
+					BinaryOperation::ShiftRight => Operation::Shift("sar"),
 					BinaryOperation::Less => Operation::Conditional("setl"),
 					BinaryOperation::LessEqual => Operation::Conditional("setle"),
 					BinaryOperation::Greater => Operation::Conditional("setg"),
@@ -255,6 +894,19 @@ impl StackAllocator {
 					BinaryOperation::Mul => Operation::Mul,
 					BinaryOperation::Div => Operation::Div,
 					BinaryOperation::Mod => Operation::Mod,
+					BinaryOperation::LogicalAnd | BinaryOperation::LogicalOr => {
+						unreachable!("Short-circuiting operations are lowered into jumps")
+					}
+				};
+				// Skips over the trap unless the divisor in `%ecx` is zero
+				let divisor_guard = || {
+					if !config.div_guard {
+						return Vec::new();
+					}
+					let mut res = vec![format!("test %ecx, %ecx"), format!("jnz 1f")];
+					res.append(&mut config.trap.instructions());
+					res.push("1:".to_string());
+					res
 				};
 				match operation {
 					Operation::Arithmetic(op_code) => vec![
@@ -262,36 +914,654 @@ impl StackAllocator {
 						format!("{} %eax, {}", op_code, self.parse_operand(rhs)),
 						format!("mov {}, %eax", self.parse_operand(l_value)),
 					],
+					// `setcc` writes exactly 0 or 1 to `%al`, and `movzx` clears
+					// the upper bits, so no masking is needed
 					Operation::Conditional(op_code) => vec![
 						format!("mov %eax, {}", self.parse_operand(lhs)),
 						format!("cmp %eax, {}", self.parse_operand(rhs)),
 						format!("{op_code} %al"),
-						format!("and %al, 1"),
 						format!("movzx %eax, %al"),
 						format!("mov {}, %eax", self.parse_operand(l_value)),
 					],
-					Operation::Mul => vec![
-						format!("mov %eax, {}", self.parse_operand(lhs),),
-						format!("mov %ecx, {}", self.parse_operand(rhs),),
-						format!("imul %eax, %ecx"),
+					// Only the low 5 bits of the count are used, as in
+					// `BinaryOperation::evaluate`
+					Operation::Shift(op_code) => vec![
+						format!("mov %eax, {}", self.parse_operand(lhs)),
+						format!("mov %ecx, {}", self.parse_operand(rhs)),
+						format!("{op_code} %eax, %cl"),
 						format!("mov {}, %eax", self.parse_operand(l_value)),
 					],
-					Operation::Div => vec![
+					Operation::Mul => vec![
 						format!("mov %eax, {}", self.parse_operand(lhs),),
 						format!("mov %ecx, {}", self.parse_operand(rhs),),
-						format!("cdq"),
-						format!("idiv %ecx"),
+						format!("imul %eax, %ecx"),
 						format!("mov {}, %eax", self.parse_operand(l_value)),
 					],
-					Operation::Mod => vec![
-						format!("mov %eax, {}", self.parse_operand(lhs),),
-						format!("mov %ecx, {}", self.parse_operand(rhs),),
-						format!("cdq"),
-						format!("idiv %ecx"),
-						format!("mov {}, %edx", self.parse_operand(l_value)),
-					],
+					Operation::Div => [
+						vec![
+							format!("mov %eax, {}", self.parse_operand(lhs),),
+							format!("mov %ecx, {}", self.parse_operand(rhs),),
+						],
+						divisor_guard(),
+						vec![
+							format!("cdq"),
+							format!("idiv %ecx"),
+							format!("mov {}, %eax", self.parse_operand(l_value)),
+						],
+					]
+					.concat(),
+					Operation::Mod => [
+						vec![
+							format!("mov %eax, {}", self.parse_operand(lhs),),
+							format!("mov %ecx, {}", self.parse_operand(rhs),),
+						],
+						divisor_guard(),
+						vec![
+							format!("cdq"),
+							format!("idiv %ecx"),
+							format!("mov {}, %edx", self.parse_operand(l_value)),
+						],
+					]
+					.concat(),
+				}
+			}
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+	#[allow(unused_imports)]
+	use crate::{lexer::tokenize, parser::parse, tac_gen::generate};
+
+	#[test]
+	fn array_parameter() {
+		use tac_gen::Instruction;
+		// int get(int a[]) { return a[1]; }
+		// int main() { int a[2]; a[1] = 7; return get(a); }
+		let functions = vec![
+			Function {
+				id: 0,
+				names: None,
+				signature: None,
+				instructions: vec![
+					Instruction::Expression(
+						Operand::Temporary(0),
+						RValue::ArrayAccess(Ident::Parameter(0), Operand::Immediate(1)),
+					),
+					Instruction::Return(Operand::Temporary(0)),
+				],
+				lines: Vec::new(),
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
+			},
+			Function {
+				id: 2,
+				names: None,
+				signature: None,
+				instructions: vec![
+					Instruction::ArrayAlloc(Ident::Binded(1, 0), 2),
+					Instruction::ArrayWrite(
+						Ident::Binded(1, 0),
+						Operand::Immediate(1),
+						Operand::Immediate(7),
+					),
+					Instruction::Push(Operand::Ident(Ident::Binded(1, 0))),
+					Instruction::Expression(Operand::Temporary(0), RValue::FuncCall(0, 1)),
+					Instruction::Return(Operand::Temporary(0)),
+				],
+				lines: Vec::new(),
+				statics: Vec::new(),
+				inlining: parser::Inlining::Auto,
+				literals: Vec::new(),
+				externals: Vec::new(),
+			},
+		];
+		let table = parser::IdentNameTable(vec!["get".into(), "a".into(), "main".into()]);
+		let asm = x86_gen(functions, table, &Config::default());
+		let (get, main) = asm.split_once("\nmain:").unwrap();
+		// Callee loads the base address from the argument slot
+		assert!(get.contains("\tmov %rsi, QWORD PTR [%rbp + 16]\n\tadd %rsi, %rdi\n"));
+		assert!(!get.contains("mov %rsi, %rbp"));
+		// Caller indexes its local array and passes its address
+		assert!(main.contains("\tmov %rsi, %rbp\n\tsub %rsi, 8\n"));
+		assert!(main.contains("\tlea %rax, [%rbp - 8]\n\tpush %rax\n"));
+	}
+
+	#[test]
+	fn void_epilogue() {
+		let test_program = "void nothing() {} void early(int n) { if (n) { return; } n = 2; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		assert!(asm.contains("\tjmp END_0\nEND_0:\n\tpop %rbp\n\t.cfi_def_cfa %rsp, 8\n\tret\n"));
+		let early = asm.split_once("\nearly:").unwrap().1;
+		assert_eq!(2, early.matches("jmp END_1").count());
+		assert!(early.contains("\tjmp END_1\nEND_1:\n"));
+	}
+
+	#[test]
+	fn trap_strategies() {
+		let test_program = "int main(int a, int b) { return a % b; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		for (strategy, trap) in [
+			("ud2", "\tud2\n"),
+			("abort", "\tcall abort@PLT\n"),
+			("exit:3", "\tmov %edi, 3\n\tmov %eax, 60\n\tsyscall\n"),
+		] {
+			let config = Config {
+				trap: strategy.parse().unwrap(),
+				div_guard: true,
+				..Default::default()
+			};
+			let expected = format!("\ttest %ecx, %ecx\n\tjnz 1f\n{trap}\t1:\n\tcdq\n");
+			assert!(x86_gen(functions.clone(), table.clone(), &config).contains(&expected));
+		}
+		assert_eq!(Ok(TrapStrategy::ExitSyscall(1)), "exit".parse());
+		assert!("exit:x".parse::<TrapStrategy>().is_err());
+		assert!(!x86_gen(functions, table, &Config::default()).contains("test %ecx"));
+	}
+
+	#[test]
+	fn annotate() {
+		let test_program = r"
+			int main(int n) {
+				int x, a[2];
+				x = n * 2;
+				a[1] = x;
+				return a[1];
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let tac_count = functions[0].instructions.len();
+		let config = Config {
+			annotate: true,
+			..Default::default()
+		};
+		let asm = x86_gen(functions.clone(), table.clone(), &config);
+		let comments: Vec<_> = asm.lines().filter(|line| line.starts_with("# ")).collect();
+		assert_eq!(tac_count, comments.len());
+		assert_eq!("# 1: x@0 = arg0 * 2", comments[1]);
+		let asm = x86_gen(functions, table, &Config::default());
+		assert!(!asm.lines().any(|line| line.starts_with("# ")));
+	}
+
+	#[test]
+	fn signature_comment() {
+		let test_program = "int fib(int n, int a[]) { return n; }\nvoid main() { return; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = tac_gen::generate_named(&parsed, &table);
+		let asm = x86_gen(functions, table, &Config::default());
+		assert!(asm.contains("\n# int fib(int n, int a[])\nfib:\n"));
+		assert!(asm.contains("\n# void main()\nmain:\n"));
+	}
+
+	#[test]
+	fn bitwise_not() {
+		let test_program = "int main(int x) { int y; y = ~x; return y + ~1; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		assert_eq!(
+			tac_gen::Instruction::Expression(
+				Operand::Ident(Ident::Binded(2, 0)),
+				RValue::BitwiseNot(Operand::Ident(Ident::Parameter(0)))
+			),
+			functions[0].instructions[0]
+		);
+		let asm = x86_gen(functions, table, &Config::default());
+		assert!(asm.contains(
+			"\tmov %eax, DWORD PTR [%rbp + 16]\n\tnot %eax\n\tmov DWORD PTR [%rbp - 4], %eax\n"
+		));
+		assert!(asm.contains("add %eax, -2\n"));
+		assert!(!asm.contains("xor"));
+	}
+
+	#[test]
+	fn shifts() {
+		let test_program = "int main(int x, int n) { return (x << 2) + (x >> n); }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		assert!(asm.contains("\tmov %ecx, 2\n\tsal %eax, %cl\n"));
+		assert!(asm.contains("\tmov %ecx, DWORD PTR [%rbp + 24]\n\tsar %eax, %cl\n"));
+	}
+
+	#[test]
+	fn negation() {
+		let test_program = "int main(int x) { int y; y = -(x + 1); return !y; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		assert!(asm.contains("\tmov %eax, DWORD PTR [%rbp - 4]\n\tneg %eax\n"));
+		assert!(asm.contains("\tcmp %eax, 0\n\tsete %al\n"));
+	}
+
+	#[test]
+	fn cfi_directives() {
+		let test_program = r"
+			int id(int n) { return n; }
+			int main(int n) { return id(n); }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		let directives: Vec<_> = asm
+			.lines()
+			.map(str::trim)
+			.filter(|line| matches!(*line, ".cfi_startproc" | ".cfi_endproc"))
+			.collect();
+		assert_eq!(
+			vec![
+				".cfi_startproc",
+				".cfi_endproc",
+				".cfi_startproc",
+				".cfi_endproc"
+			],
+			directives
+		);
+		assert!(asm.contains(
+			"\tpush %rbp\n\t.cfi_def_cfa_offset 16\n\t.cfi_offset %rbp, -16\n\tmov %rbp, %rsp\n\t.cfi_def_cfa_register %rbp\n"
+		));
+	}
+
+	#[test]
+	fn external_function() {
+		let test_program = r"
+			int ext(int n);
+			int main(int n) { return ext(n); }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		assert!(parsed.0[0].is_external());
+		assert!(!parsed.0[1].is_external());
+		assert!(crate::analyzer::analyze(&parsed).is_ok());
+		let functions = generate(&parsed, table.0.len());
+		assert_eq!(1, functions.len());
+		let asm = x86_gen(functions, table, &Config::default());
+		assert!(!asm.contains("\next:"));
+		assert!(!asm.contains(".global ext"));
+		assert!(asm.contains("\n.set F0, ext\n"));
+		assert!(asm.contains(
+			"\tpop %rdi\n\tmov %rax, %rsp\n\tand %rsp, -16\n\tsub %rsp, 8\n\tpush %rax\n\tcall F0\n\tmov %rsp, QWORD PTR [%rsp + 0]\n"
+		));
+	}
+
+	#[test]
+	fn variadic_call() {
+		let test_program = r#"
+			int printf(int format[], ...);
+			int main(int n) { int r; r = printf("%d", n); return r; }
+		"#;
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		assert!(asm.contains("\tpop %rdi\n\tpop %rsi\n"));
+		assert!(asm.contains("\tpush %rax\n\tmov %eax, 0\n\tcall F0\n"));
+	}
+
+	#[test]
+	fn stack_arguments() {
+		let test_program = r"
+			int ext(int a, int b, int c, int d, int e, int f, int g);
+			int main(int n) { return ext(n, n, n, n, n, n, 7); }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		let registers = "\tpop %rdi\n\tpop %rsi\n\tpop %rdx\n\tpop %rcx\n\tpop %r8\n\tpop %r9\n";
+		assert!(asm.contains(registers));
+		// The seventh argument is copied below the aligned `%rsp`, no padding
+		// needed along with the saved `%rsp`
+		assert!(asm.contains(
+			"\tand %rsp, -16\n\tpush %rax\n\tpush QWORD PTR [%rax + 0]\n\tcall F0\n\tmov %rsp, QWORD PTR [%rsp + 8]\n\tadd %rsp, 8\n"
+		));
+	}
+
+	#[test]
+	fn static_locals() {
+		let test_program = r"
+			int leaf(int n) {
+				int x, a[2];
+				x = n + 1;
+				a[1] = x;
+				return x;
+			}
+			int fact(int n) {
+				if (n) {
+					int m;
+					m = n - 1;
+					m = fact(m);
+					return n * m;
 				}
+				return 1;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let config = Config {
+			static_locals: true,
+			..Default::default()
+		};
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &config);
+		let (leaf, fact) = asm.split_once("\nfact:").unwrap();
+		assert!(!leaf.contains("%rsp, 0"));
+		assert!(leaf.contains("\tmov DWORD PTR [%rip + S0 - "));
+		assert!(leaf.contains("\tlea %rsi, [%rip + S0 - "));
+		assert!(leaf.contains("\n.bss\n\t.skip 20\nS0:\n.text\n"));
+		// Recursive functions keep their locals on the stack
+		assert!(!fact.contains(".bss"));
+		assert!(fact.contains("DWORD PTR [%rbp - 4]"));
+	}
+
+	#[test]
+	fn att_output() {
+		let test_program = "int main(int n) { int x; x = 5; return x; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let config = Config {
+			syntax: Syntax::Att,
+			..Default::default()
+		};
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &config);
+		assert!(asm.starts_with(".att_syntax\n"));
+		assert!(!asm.contains("PTR"));
+		assert!(asm.contains("\tmovl $5, -4(%rbp)\n"));
+		assert!(asm.contains("\tmov %rsp, %rbp\n"));
+	}
+
+	#[test]
+	fn nasm_output() {
+		let test_program = "int f(int n); int main(int n) { int x; x = f(n); return x; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let config = Config {
+			syntax: Syntax::Nasm,
+			..Default::default()
+		};
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &config);
+		assert!(asm.starts_with("extern f\nsection .text\n"));
+		assert!(!asm.contains(['%', '#']));
+		assert!(!asm.contains("PTR"));
+		assert!(asm.contains("\tmov dword [rbp - 4], eax\n"));
+		assert!(asm.contains("\tcall f\n"));
+		assert!(asm.contains("\nglobal main\n"));
+	}
+
+	#[test]
+	fn record_source() {
+		let test_program = "int main() { return 0; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let config = Config {
+			record_source: Some(SourceRecord::new("dir/\"main\".c", test_program)),
+			..Default::default()
+		};
+		let asm = x86_gen(functions.clone(), table.clone(), &config);
+		assert!(asm.ends_with(concat!(
+			"\n.section .comment, \"MS\", @progbits, 1\n",
+			"\t.string \"ezc: dir/\\\"main\\\".c fnv1a:8b517398780acb85\"\n.text\n"
+		)));
+		assert!(!x86_gen(functions, table, &Config::default()).contains(".comment"));
+	}
+
+	#[test]
+	fn conditional_move() {
+		let test_program = r"
+			int id(int n) { return n; }
+			int pure(int n) {
+				int x;
+				x = 7;
+				if (n < 3) { x = n; }
+				return x;
 			}
+			int call(int n) {
+				int x;
+				x = 7;
+				if (n < 3) { x = id(n); }
+				return x;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let asm = |id: usize| {
+			let function = functions.iter().find(|function| function.id == id).unwrap();
+			function_gen(function, &table, &Config::default(), false)
+				.body
+				.concat()
+		};
+		let position = |name: &str| table.0.iter().position(|n| n == name).unwrap();
+		let pure = asm(position("pure"));
+		assert!(pure.iter().any(|i| i == "cmovl %eax, %ecx"));
+		assert!(!pure.iter().any(|i| i.starts_with("jge")));
+		let call = asm(position("call"));
+		assert!(call.iter().any(|i| i.starts_with("jge")));
+		assert!(!call.iter().any(|i| i.starts_with("cmov")));
+
+		let test_program = "int max(int a, int b) { return a > b ? a : b; }";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let max = function_gen(&functions[0], &table, &Config::default(), false)
+			.body
+			.concat();
+		assert!(max.iter().any(|i| i == "cmovg %eax, %ecx"));
+		assert!(!max.iter().any(|i| i.starts_with("jle")));
+	}
+
+	#[test]
+	fn profile() {
+		let test_program = r"
+			int twice(int n) { return n * 2; }
+			int main() { return twice(3); }
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let config = Config {
+			profile: true,
+			..Default::default()
+		};
+		let asm = x86_gen(functions.clone(), table.clone(), &config);
+		for name in ["twice", "main"] {
+			let label = profile_label(name);
+			assert!(asm.contains(&format!("\tadd QWORD PTR [%rip + {label}], 1\n")));
+			assert!(asm.contains(&format!(".bss\n.global {label}\n{label}:\n\t.skip 8\n")));
 		}
+		assert!(!x86_gen(functions, table, &Config::default()).contains("__ezc_calls"));
+	}
+
+	#[test]
+	fn max_stack() {
+		let test_program = r"
+			int small(int n) {
+				int x;
+				x = n + 1;
+				return x;
+			}
+			int big(int n) {
+				int a[100];
+				a[0] = n;
+				return a[0];
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let big = table.0.iter().position(|name| name == "big").unwrap();
+		let oversized = oversized_frames(&functions, &table, &Config::default(), 64);
+		assert!(matches!(oversized[..], [(id, usage)] if id == big && usage > 400));
+		assert!(oversized_frames(&functions, &table, &Config::default(), 1024).is_empty());
+		let config = Config {
+			static_locals: true,
+			..Default::default()
+		};
+		assert!(oversized_frames(&functions, &table, &config, 0).is_empty());
+	}
+
+	#[test]
+	fn string_literals() {
+		let test_program = r#"
+			int puts(int s[]);
+			int main() {
+				int x;
+				x = puts("hi");
+				x = puts("hi");
+				return 0;
+			}
+		"#;
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let asm = x86_gen(functions, table, &Config::default());
+		assert_eq!(1, asm.matches("\nSTR0:\n\t.string \"hi\"\n").count());
+		assert!(asm.contains(".size STR0, 3\n"));
+		assert!(asm.contains(".equ STR0_len, 2\n"));
+		assert_eq!(2, asm.matches("lea %rax, [%rip + STR0]").count());
+	}
+
+	#[test]
+	fn many_parameters() {
+		// Every argument is pushed, there's no register limit to spill past
+		let test_program = r"
+			int last(int a, int b, int c, int d, int e, int f, int g, int h) {
+				return h;
+			}
+			int main() {
+				return last(1, 2, 3, 4, 5, 6, 7, 8);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		assert!(asm.contains("\tmov %eax, DWORD PTR [%rbp + 72]\n"));
+		assert_eq!(8, asm.matches("\tpush %rax\n").count());
+		assert!(asm.contains("\tadd %rsp, 64\n"));
+	}
+
+	#[test]
+	fn red_zone() {
+		let test_program = r"
+			int leaf(int n) {
+				int a[30];
+				a[0] = n;
+				return a[0];
+			}
+			int large(int n) {
+				int a[40];
+				a[0] = n;
+				return a[0];
+			}
+			int main(int n) {
+				int x;
+				x = leaf(n);
+				return large(x);
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let asm = x86_gen(generate(&parsed, table.0.len()), table, &Config::default());
+		let (leaf, rest) = asm.split_once("\nlarge:").unwrap();
+		let (large, main) = rest.split_once("\nmain:").unwrap();
+		assert!(!leaf.contains("sub %rsp") && !leaf.contains("add %rsp"));
+		assert!(leaf.contains("DWORD PTR [%rbp - 128]"));
+		// Past the red zone and calling functions still reserve their stack
+		assert!(large.contains("\tsub %rsp, 168\n"));
+		assert!(large.contains("\tadd %rsp, 168\n"));
+		assert!(main.contains("\tsub %rsp, 8\n"));
+		assert!(main.contains("\tadd %rsp, 8\n"));
+	}
+
+	#[test]
+	fn comparison() {
+		let mut allocator = StackAllocator::default();
+		let asm = allocator.expression_gen(
+			Operand::Temporary(0),
+			RValue::Operation(
+				Operand::Ident(Ident::Parameter(0)),
+				BinaryOperation::Less,
+				Operand::Immediate(4),
+			),
+			&Config::default(),
+		);
+		assert_eq!(
+			vec![
+				"mov %eax, DWORD PTR [%rbp + 16]",
+				"cmp %eax, 4",
+				"setl %al",
+				"movzx %eax, %al",
+				"mov DWORD PTR [%rbp - 4], %eax",
+			],
+			asm
+		);
+	}
+
+	#[test]
+	fn fused_loop_condition() {
+		let test_program = r"
+			int main(int n) {
+				int i = 0;
+				while (i < n) {
+					i = i + 1;
+				}
+				return i;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let asm = function_gen(&functions[0], &table, &Config::default(), false);
+		// `i = 0`, then the loop test
+		assert_eq!(
+			vec![
+				"G0_0:",
+				"mov %eax, DWORD PTR [%rbp - 4]",
+				"cmp %eax, DWORD PTR [%rbp + 16]"
+			],
+			asm.body[1]
+		);
+		assert_eq!(vec!["jge L0_0"], asm.body[2]);
+		let asm = asm.body.concat();
+		assert!(!asm.iter().any(|asm| asm.starts_with("set")));
+		assert!(!asm.iter().any(|asm| asm.contains("movzx")));
+	}
+	#[test]
+	fn fused_for_condition() {
+		let test_program = r"
+			int main(int n) {
+				int s = 0;
+				for (int i = 0; i < n; i += 1)
+					s += i;
+				return s;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let asm = function_gen(&functions[0], &table, &Config::default(), false);
+		// `s = 0` and `i = 0`, then the loop test
+		assert_eq!(
+			vec![
+				"G0_0:",
+				"mov %eax, DWORD PTR [%rbp - 8]",
+				"cmp %eax, DWORD PTR [%rbp + 16]"
+			],
+			asm.body[2]
+		);
+		assert_eq!(vec!["jge L0_0"], asm.body[3]);
+		let asm = asm.body.concat();
+		assert!(!asm.iter().any(|asm| asm.starts_with("set")));
+		assert!(!asm.iter().any(|asm| asm.contains("movzx")));
+	}
+
+	#[test]
+	fn fused_do_while_condition() {
+		let test_program = r"
+			int main(int s) {
+				do {
+					s = s - 1;
+				} while (s > 3);
+				return s;
+			}
+		";
+		let (parsed, table) = parse(tokenize(test_program).unwrap()).unwrap();
+		let functions = generate(&parsed, table.0.len());
+		let asm = function_gen(&functions[0], &table, &Config::default(), false);
+		// The body, then the loop test at the bottom
+		assert_eq!(
+			vec!["mov %eax, DWORD PTR [%rbp + 16]", "cmp %eax, 3"],
+			asm.body[1]
+		);
+		assert_eq!(vec!["jle L0_0"], asm.body[2]);
+		assert_eq!(vec!["jmp G0_0"], asm.body[3]);
+		let asm = asm.body.concat();
+		assert!(!asm.iter().any(|asm| asm.starts_with("set")));
+		assert!(!asm.iter().any(|asm| asm.contains("movzx")));
 	}
 }
@@ -0,0 +1,141 @@
+//! AT&T syntax formatter
+//!
+//! The backend generates Intel syntax, `att_syntax` rewrites every
+//! instruction line in place: operands are reversed, registers keep their `%`
+//! prefix, immediates gain a `$` and memory operands become
+//! `displacement(base)`. Instructions with a memory or immediate operand get
+//! a size suffix, as `as` can't infer the width from them.
+
+/// Rewrites every instruction of the Intel syntax `asm`, directives, labels and
+/// comments are left untouched
+pub fn att_syntax(asm: &str) -> String {
+	asm.lines()
+		.map(|line| {
+			let trimmed = line.trim();
+			if trimmed.is_empty() || trimmed.starts_with(['.', '#']) || trimmed.ends_with(':') {
+				return line.to_string();
+			}
+			let indent = &line[..line.len() - line.trim_start().len()];
+			format!("{indent}{}", instruction(trimmed))
+		})
+		.fold(String::new(), |res, line| res + line.as_str() + "\n")
+}
+
+/// Translates a single Intel syntax instruction
+fn instruction(instruction: &str) -> String {
+	let Some((mnemonic, operands)) = instruction.split_once(' ') else {
+		return instruction.to_string();
+	};
+	let operands: Vec<Operand> = operands.split(", ").map(Operand::parse).collect();
+	let needs_suffix = operands
+		.iter()
+		.any(|operand| matches!(operand, Operand::Memory(..) | Operand::Immediate(_)));
+	let suffix = match needs_suffix {
+		true => operands.iter().find_map(Operand::size_suffix).unwrap_or(""),
+		false => "",
+	};
+	let operands: Vec<String> = operands.iter().rev().map(Operand::to_string).collect();
+	format!("{mnemonic}{suffix} {}", operands.join(", "))
+}
+
+enum Operand<'a> {
+	Register(&'a str),
+	Immediate(&'a str),
+	/// Tuple struct of the `PTR` width, if given, and the address
+	Memory(Option<&'a str>, &'a str),
+	/// Jump and call targets
+	Label(&'a str),
+}
+impl<'a> Operand<'a> {
+	fn parse(operand: &'a str) -> Self {
+		if let Some((width, address)) = operand.split_once(" PTR ") {
+			Self::Memory(Some(width), address)
+		} else if operand.starts_with('[') {
+			Self::Memory(None, operand)
+		} else if operand.starts_with('%') {
+			Self::Register(operand)
+		} else if operand.parse::<i64>().is_ok() {
+			Self::Immediate(operand)
+		} else {
+			Self::Label(operand)
+		}
+	}
+	/// Suffix for the width of the operand, if it has one
+	fn size_suffix(&self) -> Option<&'static str> {
+		match self {
+			Self::Memory(Some("BYTE"), _) => Some("b"),
+			Self::Memory(Some("WORD"), _) => Some("w"),
+			Self::Memory(Some("DWORD"), _) => Some("l"),
+			Self::Memory(Some("QWORD"), _) => Some("q"),
+			Self::Register(register) => match register.as_bytes()[1] {
+				b'r' => Some("q"),
+				b'e' => Some("l"),
+				_ if register.ends_with('l') => Some("b"),
+				_ => Some("w"),
+			},
+			_ => None,
+		}
+	}
+}
+impl std::fmt::Display for Operand<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Register(operand) | Self::Label(operand) => write!(f, "{operand}"),
+			Self::Immediate(value) => write!(f, "${value}"),
+			Self::Memory(_, address) => {
+				// `[base + a - b]` becomes `a-b(base)`
+				let mut terms = address
+					.trim_start_matches('[')
+					.trim_end_matches(']')
+					.split(' ');
+				let base = terms.next().unwrap_or_default();
+				let mut displacement = String::new();
+				while let (Some(sign), Some(term)) = (terms.next(), terms.next()) {
+					if sign == "-" || !displacement.is_empty() {
+						displacement += sign;
+					}
+					displacement += term;
+				}
+				write!(f, "{displacement}({base})")
+			}
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+
+	#[test]
+	fn instructions() {
+		assert_eq!(
+			"movl %eax, -4(%rbp)",
+			instruction("mov DWORD PTR [%rbp - 4], %eax")
+		);
+		assert_eq!(
+			"movl $7, -8(%rbp)",
+			instruction("mov DWORD PTR [%rbp - 8], 7")
+		);
+		assert_eq!(
+			"movl 16(%rbp), %eax",
+			instruction("mov %eax, DWORD PTR [%rbp + 16]")
+		);
+		assert_eq!(
+			"movq (%rsp), %rax",
+			instruction("mov %rax, QWORD PTR [%rsp]")
+		);
+		assert_eq!(
+			"leaq S0-12(%rip), %rsi",
+			instruction("lea %rsi, [%rip + S0 - 12]")
+		);
+		assert_eq!(
+			"cmpl $0, -4(%rbp)",
+			instruction("cmp DWORD PTR [%rbp - 4], 0")
+		);
+		assert_eq!("subq $16, %rsp", instruction("sub %rsp, 16"));
+		assert_eq!("imul %ecx, %eax", instruction("imul %eax, %ecx"));
+		assert_eq!("movzx %al, %eax", instruction("movzx %eax, %al"));
+		assert_eq!("call F0", instruction("call F0"));
+		assert_eq!("ret", instruction("ret"));
+	}
+}
@@ -0,0 +1,201 @@
+//! NASM syntax formatter
+//!
+//! The backend generates GAS Intel syntax, `nasm_syntax` rewrites it for NASM:
+//! registers lose their `%` prefix, `DWORD PTR` becomes `dword`, directives
+//! become `section`, `global` and `extern`, and comments start with `;`. NASM
+//! has no CFI directives, so those are dropped.
+//!
+//! Jump targets within a function are renamed to `.L` local labels, NASM
+//! scoping them to the function label preceding them. It has no numeric
+//! labels either, the `1:` skipped to by `jnz 1f` in division guards becomes
+//! a numbered `.Lskip` label.
+
+/// Rewrites the GAS Intel syntax `asm`, without its `.intel_syntax` prelude
+pub fn nasm_syntax(asm: &str) -> String {
+	// External functions are aliased with `.set F{id}, name` for GAS, NASM
+	// can't alias an undefined symbol so calls name it directly
+	let aliases: Vec<(&str, &str)> = asm
+		.lines()
+		.filter_map(|line| line.trim().strip_prefix(".set "))
+		.filter_map(|alias| alias.split_once(", "))
+		.collect();
+	let mut externs: Vec<&str> = aliases.iter().map(|&(_, symbol)| symbol).collect();
+	externs.extend(
+		asm.split(|char: char| char.is_whitespace() || char == ',')
+			.filter_map(|word| word.strip_suffix("@PLT")),
+	);
+	externs.sort_unstable();
+	externs.dedup();
+	let mut res = String::new();
+	for symbol in externs {
+		res += &format!("extern {symbol}\n");
+	}
+	res + "section .text\n" + &translate(asm, &aliases)
+}
+
+/// Rewrites every line of `asm` on its own, for listings of single
+/// instructions. Calls through a `.set` alias keep naming the alias.
+pub fn nasm_lines(asm: &str) -> String {
+	translate(asm, &[])
+}
+
+fn translate(asm: &str, aliases: &[(&str, &str)]) -> String {
+	let mut res = String::new();
+	// Number of `1:` labels passed, a `1f` refers to the next one
+	let mut skips = 0;
+	for line in asm.lines() {
+		let trimmed = line.trim();
+		let indent = &line[..line.len() - line.trim_start().len()];
+		let line = if trimmed.is_empty() {
+			Some(line.to_string())
+		} else if let Some(name) = trimmed.strip_suffix(':') {
+			let line = match (name, label(name)) {
+				("1", _) => format!("{indent}.Lskip{skips}:"),
+				(_, Some(label)) => format!("{indent}{label}:"),
+				_ => line.to_string(),
+			};
+			if name == "1" {
+				skips += 1;
+			}
+			Some(line)
+		} else if let Some(comment) = trimmed.strip_prefix('#') {
+			Some(format!("{indent};{comment}"))
+		} else if trimmed.starts_with('.') {
+			directive(trimmed).map(|directive| format!("{indent}{directive}"))
+		} else {
+			Some(format!("{indent}{}", instruction(trimmed, aliases, skips)))
+		};
+		if let Some(line) = line {
+			res += &line;
+			res.push('\n');
+		}
+	}
+	res
+}
+
+/// The NASM equivalent of a GAS directive, `None` when it has none
+fn directive(directive: &str) -> Option<String> {
+	let (name, arguments) = directive.split_once(' ').unwrap_or((directive, ""));
+	match name {
+		".global" => Some(format!("global {arguments}")),
+		".text" | ".bss" | ".data" => Some(format!("section {name}")),
+		// Section flags are left to NASM's defaults
+		".section" => arguments
+			.split(',')
+			.next()
+			.map(|section| format!("section {section}")),
+		// Backquoted NASM strings take the same escapes as GAS ones
+		".string" => {
+			let string = arguments.strip_prefix('"')?.strip_suffix('"')?;
+			Some(format!("db `{string}`, 0"))
+		}
+		".skip" => Some(format!("resb {arguments}")),
+		".long" => Some(format!("dd {arguments}")),
+		".equ" => arguments
+			.split_once(", ")
+			.map(|(symbol, value)| format!("{symbol} equ {value}")),
+		// Aliases are resolved at the call sites
+		".set" | ".type" | ".size" => None,
+		_ if name.starts_with(".cfi_") => None,
+		_ => Some(directive.to_string()),
+	}
+}
+
+/// `.L` local label for a jump target generated within a function, which are
+/// `G{id}_{func}`, `L{id}_{func}` and `END_{func}`
+fn label(name: &str) -> Option<String> {
+	let id = ["END_", "G", "L"]
+		.iter()
+		.find_map(|prefix| name.strip_prefix(prefix))?;
+	let is_id = id.starts_with(|char: char| char.is_ascii_digit())
+		&& id.chars().all(|char| char.is_ascii_digit() || char == '_');
+	is_id.then(|| format!(".L{name}"))
+}
+
+/// Translates a single GAS Intel syntax instruction, calls to an alias of
+/// `aliases` naming the aliased symbol and `1f` naming `.Lskip{skips}`
+fn instruction(instruction: &str, aliases: &[(&str, &str)], skips: usize) -> String {
+	let Some((mnemonic, operands)) = instruction.split_once(' ') else {
+		return instruction.to_string();
+	};
+	let mut operands: Vec<String> = operands
+		.split(", ")
+		.map(|operand| {
+			if let Some(&(_, symbol)) = aliases.iter().find(|(alias, _)| *alias == operand) {
+				return symbol.to_string();
+			}
+			if let Some(symbol) = operand.strip_suffix("@PLT") {
+				return format!("{symbol} wrt ..plt");
+			}
+			if operand == "1f" {
+				return format!(".Lskip{skips}");
+			}
+			if let Some(label) = label(operand) {
+				return label;
+			}
+			let operand = match operand.split_once(" PTR ") {
+				Some((width, address)) => format!("{} {address}", width.to_lowercase()),
+				None => operand.to_string(),
+			};
+			operand.replace("[%rip + ", "[rel ").replace('%', "")
+		})
+		.collect();
+	// GAS shifts by one when the count is left out, NASM needs it explicit
+	if matches!(mnemonic, "shl" | "shr" | "sar" | "sal") && operands.len() == 1 {
+		operands.push("1".to_string());
+	}
+	format!("{mnemonic} {}", operands.join(", "))
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use super::*;
+
+	#[test]
+	fn instructions() {
+		assert_eq!(
+			"mov dword [rbp - 4], eax",
+			instruction("mov DWORD PTR [%rbp - 4], %eax", &[], 0)
+		);
+		assert_eq!(
+			"mov rsi, qword [rbp + 16]",
+			instruction("mov %rsi, QWORD PTR [%rbp + 16]", &[], 0)
+		);
+		assert_eq!(
+			"lea rsi, [rel S0 - 12]",
+			instruction("lea %rsi, [%rip + S0 - 12]", &[], 0)
+		);
+		assert_eq!("shl rdi, 1", instruction("shl %rdi", &[], 0));
+		assert_eq!("movzx eax, al", instruction("movzx %eax, %al", &[], 0));
+		assert_eq!("call ext", instruction("call F3", &[("F3", "ext")], 0));
+		assert_eq!(
+			"call abort wrt ..plt",
+			instruction("call abort@PLT", &[], 0)
+		);
+		assert_eq!("ret", instruction("ret", &[], 0));
+		assert_eq!("jge .LL0_2", instruction("jge L0_2", &[], 0));
+		assert_eq!("jmp .LEND_2", instruction("jmp END_2", &[], 0));
+		assert_eq!("jnz .Lskip1", instruction("jnz 1f", &[], 1));
+	}
+
+	#[test]
+	fn strings() {
+		assert_eq!(
+			Some("section .comment".to_string()),
+			directive(".section .comment, \"MS\", @progbits, 1")
+		);
+		assert_eq!(
+			Some("db `a \\\"b\\\"`, 0".to_string()),
+			directive(".string \"a \\\"b\\\"\"")
+		);
+	}
+
+	#[test]
+	fn directives() {
+		let asm = "\n.global f\n.type f, @function\n.set F1, ext\nf:\nF0:\n\t.cfi_startproc\n# 0: call\n\tcall F1\n\tjnz 1f\n\tcall abort@PLT\n\t1:\nEND_0:\n.bss\n\t.skip 8\nS0:\n.text\n";
+		assert_eq!(
+			"extern abort\nextern ext\nsection .text\n\nglobal f\nf:\nF0:\n; 0: call\n\tcall ext\n\tjnz .Lskip0\n\tcall abort wrt ..plt\n\t.Lskip0:\n.LEND_0:\nsection .bss\n\tresb 8\nS0:\nsection .text\n",
+			nasm_syntax(asm)
+		);
+	}
+}
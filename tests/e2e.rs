@@ -0,0 +1,622 @@
+//! End-to-end tests, assembling and linking the generated code and checking the
+//! exit code of the resulting binary
+//!
+//! Programs define `int start()`, called by a C driver the same way as
+//! `main.c`. Every test is skipped when no assembler or C compiler is present.
+use std::{fs, path::PathBuf, process::Command};
+
+use ezc::{analyzer, lexer, llvm_gen, parser, tac_gen, x86_gen};
+
+const DRIVER: &str = "extern int start();\nint main() { return start(); }\n";
+
+fn compile(source: &str, config: &x86_gen::Config) -> String {
+	ezc::compile(source, config).unwrap()
+}
+
+fn toolchain_available(tools: &[&str]) -> bool {
+	tools.iter().all(|tool| {
+		Command::new(tool)
+			.arg("--version")
+			.output()
+			.is_ok_and(|output| output.status.success())
+	})
+}
+
+/// Exit code of the compiled `source`, `None` when the test is skipped
+fn run(name: &str, source: &str) -> Option<i32> {
+	run_output(name, source).and_then(|output| output.status.code())
+}
+
+/// Exit status and output of the compiled `source`, `None` when the test is
+/// skipped
+fn run_output(name: &str, source: &str) -> Option<std::process::Output> {
+	if !toolchain_available(&["as", "cc"]) {
+		eprintln!("Skipping '{name}': no assembler or C compiler found");
+		return None;
+	}
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-{name}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = |extension: &str| -> PathBuf { dir.join(format!("{name}.{extension}")) };
+	let (asm, object, driver, binary) = (path("s"), path("o"), path("c"), path("out"));
+	fs::write(&asm, compile(source, &x86_gen::Config::default())).unwrap();
+	fs::write(&driver, DRIVER).unwrap();
+	let status = Command::new("as")
+		.arg(&asm)
+		.arg("-o")
+		.arg(&object)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to assemble '{name}'");
+	let status = Command::new("cc")
+		.args([&driver, &object])
+		.arg("-o")
+		.arg(&binary)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to link '{name}'");
+	let output = Command::new(&binary).output().unwrap();
+	let _ = fs::remove_dir_all(&dir);
+	Some(output)
+}
+
+fn assert_exit_code(name: &str, source: &str, expected: i32) {
+	if let Some(code) = run(name, source) {
+		assert_eq!(expected, code, "Exit code of '{name}'");
+	}
+}
+
+#[test]
+fn arithmetic() {
+	let source = r"
+		int start() {
+			int a = 7, b = 3, c, d;
+			c = a * b;
+			d = a / b;
+			c = c - d;
+			d = a % b;
+			return c + d;
+		}
+	";
+	assert_exit_code("arithmetic", source, 20);
+}
+
+#[test]
+fn loop_sum() {
+	let source = r"
+		int start() {
+			int i = 1, sum = 0;
+			while (i <= 10) {
+				sum = sum + i;
+				i = i + 1;
+			}
+			return sum;
+		}
+	";
+	assert_exit_code("loop_sum", source, 55);
+}
+
+#[test]
+fn recursion() {
+	let source = r"
+		int fibb(int n) {
+			if (n < 2) {
+				return n;
+			}
+			int a, b;
+			a = n - 1;
+			a = fibb(a);
+			b = n - 2;
+			b = fibb(b);
+			return a + b;
+		}
+		int start() {
+			return fibb(10);
+		}
+	";
+	assert_exit_code("recursion", source, 55);
+}
+
+#[test]
+fn array() {
+	let source = r"
+		int start() {
+			int a[5], i = 0, sum = 0, value;
+			while (i < 5) {
+				a[i] = i * i;
+				i = i + 1;
+			}
+			i = 0;
+			while (i < 5) {
+				value = a[i];
+				sum = sum + value;
+				i = i + 1;
+			}
+			return sum;
+		}
+	";
+	assert_exit_code("array", source, 30);
+}
+
+#[test]
+fn out_parameter() {
+	let source = r"
+		void fill(int out[], int n) {
+			int i = 0;
+			while (i < n) {
+				out[i] = i + 1;
+				i = i + 1;
+			}
+		}
+		int start() {
+			int a[4], sum = 0, value;
+			fill(a, 4);
+			value = a[0];
+			sum = sum + value;
+			value = a[3];
+			sum = sum * value;
+			return sum;
+		}
+	";
+	assert_exit_code("out_parameter", source, 4);
+}
+
+#[test]
+fn libc_call() {
+	let source = r"
+		int abs(int x);
+		int start() {
+			int x;
+			x = abs(-7);
+			return x;
+		}
+	";
+	assert_exit_code("libc_call", source, 7);
+}
+
+#[test]
+fn printf() {
+	let source = r#"
+		int printf(int format[], ...);
+		int start() {
+			int x;
+			x = 6 * 7;
+			printf("%d %s\n", x, "words");
+			return 0;
+		}
+	"#;
+	if let Some(output) = run_output("printf", source) {
+		assert_eq!(Some(0), output.status.code());
+		assert_eq!("42 words\n", String::from_utf8_lossy(&output.stdout));
+	}
+}
+
+#[test]
+fn string_literal() {
+	let source = r#"
+		int puts(int s[]);
+		int start() {
+			int r;
+			r = puts("say \"hi\"\tnow");
+			r = puts("");
+			return 0;
+		}
+	"#;
+	if let Some(output) = run_output("string_literal", source) {
+		assert_eq!(Some(0), output.status.code());
+		assert_eq!(
+			"say \"hi\"\tnow\n\n",
+			String::from_utf8_lossy(&output.stdout)
+		);
+	}
+}
+
+#[test]
+fn freestanding_argc() {
+	if !toolchain_available(&["as", "ld"]) {
+		eprintln!("Skipping 'freestanding_argc': no assembler or linker found");
+		return;
+	}
+	let source = "int main(int argc) { return argc; }";
+	let config = x86_gen::Config {
+		freestanding: true,
+		..Default::default()
+	};
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-freestanding", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (asm, object, binary) = (dir.join("main.s"), dir.join("main.o"), dir.join("main"));
+	fs::write(&asm, compile(source, &config)).unwrap();
+	let status = Command::new("as")
+		.arg(&asm)
+		.arg("-o")
+		.arg(&object)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to assemble");
+	let status = Command::new("ld")
+		.arg(&object)
+		.arg("-o")
+		.arg(&binary)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to link");
+	let code = Command::new(&binary)
+		.args(["a", "b"])
+		.status()
+		.unwrap()
+		.code();
+	let _ = fs::remove_dir_all(&dir);
+	assert_eq!(Some(3), code);
+}
+
+#[test]
+fn run_argument() {
+	let path = std::env::temp_dir().join(format!("ezc-e2e-{}-run.c", std::process::id()));
+	fs::write(&path, "int main(int n) { return n; }").unwrap();
+	let code = Command::new(env!("CARGO_BIN_EXE_ezc"))
+		.arg(&path)
+		.args(["--run", "--arg", "5"])
+		.status()
+		.unwrap()
+		.code();
+	let _ = fs::remove_file(&path);
+	assert_eq!(Some(5), code);
+}
+
+#[test]
+fn nasm_assembler() {
+	if !toolchain_available(&["nasm", "cc"]) {
+		eprintln!("Skipping 'nasm_assembler': no nasm or C compiler found");
+		return;
+	}
+	let source = r"
+		int square(int n) { return n * n; }
+		int start() {
+			int a[3], i = 0, sum = 0;
+			while (i < 3) {
+				a[i] = square(i);
+				i = i + 1;
+			}
+			i = 0;
+			while (i < 3) {
+				sum = sum + a[i];
+				i = i + 1;
+			}
+			return sum * 2;
+		}
+	";
+	let config = x86_gen::Config {
+		syntax: x86_gen::Syntax::Nasm,
+		..Default::default()
+	};
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-nasm", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = |file: &str| -> PathBuf { dir.join(file) };
+	let (asm, object, driver, binary) = (
+		path("start.asm"),
+		path("start.o"),
+		path("main.c"),
+		path("main"),
+	);
+	fs::write(&asm, compile(source, &config)).unwrap();
+	fs::write(&driver, DRIVER).unwrap();
+	let status = Command::new("nasm")
+		.args(["-f", "elf64"])
+		.arg(&asm)
+		.arg("-o")
+		.arg(&object)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to assemble");
+	let status = Command::new("cc")
+		.args([&driver, &object])
+		.arg("-o")
+		.arg(&binary)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to link");
+	let code = Command::new(&binary).status().unwrap().code();
+	let _ = fs::remove_dir_all(&dir);
+	assert_eq!(Some(10), code);
+}
+
+#[test]
+fn record_source() {
+	if !toolchain_available(&["as", "readelf"]) {
+		eprintln!("Skipping 'record_source': no assembler or readelf found");
+		return;
+	}
+	let source = "int start() { return 0; }";
+	let config = x86_gen::Config {
+		record_source: Some(x86_gen::SourceRecord::new("src/start.c", source)),
+		..Default::default()
+	};
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-record", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (asm, object) = (dir.join("start.s"), dir.join("start.o"));
+	fs::write(&asm, compile(source, &config)).unwrap();
+	let status = Command::new("as")
+		.arg(&asm)
+		.arg("-o")
+		.arg(&object)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to assemble");
+	let output = Command::new("readelf")
+		.args(["-p", ".comment"])
+		.arg(&object)
+		.output()
+		.unwrap();
+	let _ = fs::remove_dir_all(&dir);
+	let comment = String::from_utf8(output.stdout).unwrap();
+	assert!(comment.contains("ezc: src/start.c fnv1a:"), "{comment}");
+}
+
+#[test]
+fn stack_budget() {
+	let path = std::env::temp_dir().join(format!("ezc-e2e-{}-stack.c", std::process::id()));
+	fs::write(
+		&path,
+		"int small() {\n\treturn 0;\n}\nint big() {\n\tint a[100];\n\ta[0] = 1;\n\treturn a[0];\n}",
+	)
+	.unwrap();
+	let output = Command::new(env!("CARGO_BIN_EXE_ezc"))
+		.arg(&path)
+		.args(["--max-stack", "64", "-o", "-"])
+		.output()
+		.unwrap();
+	let _ = fs::remove_file(&path);
+	// Reported as a compile error of its own, not a panic
+	assert_eq!(Some(3), output.status.code());
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(
+		stderr.ends_with(":4: error: frame of 'big' uses 408 bytes, over the budget of 64\n"),
+		"{stderr}"
+	);
+	assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn pedantic() {
+	let path = std::env::temp_dir().join(format!("ezc-e2e-{}-pedantic.c", std::process::id()));
+	let write = |source: &str| fs::write(&path, source).unwrap();
+	write("int main() { // hi\n\treturn 0;\n}");
+	let ezc = |args: &[&str]| {
+		Command::new(env!("CARGO_BIN_EXE_ezc"))
+			.arg(&path)
+			.args(["--emit=sexpr", "-o", "-"])
+			.args(args)
+			.output()
+			.unwrap()
+	};
+	let (extended, pedantic) = (ezc(&[]), ezc(&["--pedantic"]));
+	// An extension enabled by its own flag applies whatever the order
+	write("main() {\n\treturn 0;\n}");
+	let before = ezc(&["--allow-implicit-int", "--pedantic"]);
+	let after = ezc(&["--pedantic", "--allow-implicit-int"]);
+	let implicit_int = ezc(&["--pedantic"]);
+	let _ = fs::remove_file(&path);
+	assert!(extended.status.success());
+	// `//` comments are an extension of C89
+	assert_eq!(Some(1), pedantic.status.code());
+	assert!(before.status.success() && after.status.success());
+	assert_eq!(Some(1), implicit_int.status.code());
+}
+
+#[test]
+fn quiet_compilation() {
+	let source = r"
+		int start() {
+			int a[4], i = 0;
+			while (i < 4) {
+				a[i] = i * 3;
+				i = i + 1;
+			}
+			return a[3] + a[1];
+		}
+	";
+	assert!(compile(source, &x86_gen::Config::default()).contains("\nstart:\n"));
+	assert_eq!(
+		"1: error: call to undefined function 'f'",
+		ezc::compile("int start() { return f(); }", &x86_gen::Config::default())
+			.unwrap_err()
+			.to_string()
+	);
+	// The driver only writes the assembly, nothing reaches stderr unless
+	// `RUST_LOG` asks for it
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-quiet", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("start.c"), source).unwrap();
+	let output = Command::new(env!("CARGO_BIN_EXE_ezc"))
+		.arg("start.c")
+		.current_dir(&dir)
+		.env_remove("RUST_LOG")
+		.output()
+		.unwrap();
+	let asm = fs::read_to_string(dir.join("ezc.asm"));
+	let _ = fs::remove_dir_all(&dir);
+	assert!(output.status.success());
+	assert_eq!("", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(compile(source, &x86_gen::Config::default()), asm.unwrap());
+}
+
+#[test]
+fn output_path() {
+	let source = "int start() { return 3; }";
+	let expected = compile(source, &x86_gen::Config::default());
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-output", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("start.c"), source).unwrap();
+	let ezc = |args: &[&str]| {
+		Command::new(env!("CARGO_BIN_EXE_ezc"))
+			.arg("start.c")
+			.args(args)
+			.current_dir(&dir)
+			.output()
+			.unwrap()
+	};
+	let custom = ezc(&["-o", "custom.s"]);
+	let asm = fs::read_to_string(dir.join("custom.s"));
+	let default_written = dir.join("ezc.asm").exists();
+	let stdout = ezc(&["-o", "-"]);
+	let llvm = ezc(&["--emit=llvm", "-o", "-"]);
+	let stdout_written = dir.join("ezc.asm").exists() || dir.join("ezc.ll").exists();
+	let _ = fs::remove_dir_all(&dir);
+	assert!(custom.status.success() && stdout.status.success());
+	assert_eq!(expected, asm.unwrap());
+	assert!(!default_written && !stdout_written);
+	assert_eq!(expected, String::from_utf8_lossy(&stdout.stdout));
+	assert!(String::from_utf8_lossy(&llvm.stdout).contains("define i32 @start()"));
+}
+
+#[test]
+fn profile_counter() {
+	if !toolchain_available(&["as", "cc"]) {
+		eprintln!("Skipping 'profile_counter': no assembler or C compiler found");
+		return;
+	}
+	let source = r"
+		int twice(int n) { return n * 2; }
+		int start() {
+			int i;
+			i = 0;
+			while (i < 10) {
+				i = twice(i);
+				i = i + 1;
+			}
+			return i;
+		}
+	";
+	let driver = concat!(
+		"extern int start();\nextern long __ezc_calls_twice;\n",
+		"int main() { return start() == 15 ? __ezc_calls_twice : 100; }\n"
+	);
+	let config = x86_gen::Config {
+		profile: true,
+		..Default::default()
+	};
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-profile", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (asm, object, binary) = (dir.join("start.s"), dir.join("start.o"), dir.join("main"));
+	fs::write(&asm, compile(source, &config)).unwrap();
+	fs::write(dir.join("main.c"), driver).unwrap();
+	let status = Command::new("as")
+		.arg(&asm)
+		.arg("-o")
+		.arg(&object)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to assemble");
+	let status = Command::new("cc")
+		.arg(dir.join("main.c"))
+		.arg(&object)
+		.arg("-o")
+		.arg(&binary)
+		.status()
+		.unwrap();
+	assert!(status.success(), "Failed to link");
+	let code = Command::new(&binary).status().unwrap().code();
+	let _ = fs::remove_dir_all(&dir);
+	assert_eq!(Some(4), code);
+}
+
+#[test]
+fn static_counter() {
+	let source = r"
+		int counter() {
+			static int count;
+			count = count + 1;
+			return count;
+		}
+		int other() {
+			static int count = 10;
+			count = count + 1;
+			return count;
+		}
+		int start() {
+			int a;
+			a = counter();
+			a = counter();
+			a = other();
+			a = counter();
+			return a;
+		}
+	";
+	assert_exit_code("static_counter", source, 3);
+}
+
+#[test]
+fn llvm_ir_assembles() {
+	if !toolchain_available(&["llvm-as"]) {
+		eprintln!("Skipping 'llvm_ir_assembles': no llvm-as found");
+		return;
+	}
+	let source = r"
+		int ext(int a[], int n);
+		void fill(int a[], int n) {
+			int i = 0;
+			while (i < n) {
+				a[i] = i * i;
+				i = i + 1;
+			}
+		}
+		int main(int n) {
+			static int calls;
+			int a[4], x;
+			calls = calls + 1;
+			fill(a, 4);
+			x = ext(a, n);
+			if (x >= 0) {
+				return ~x;
+			}
+			return a[3] % calls;
+		}
+	";
+	let (parsed, ident_table) = parser::parse(lexer::tokenize(source).unwrap()).unwrap();
+	analyzer::analyze(&parsed).unwrap();
+	let tac = tac_gen::generate(&parsed, ident_table.0.len());
+	let dir = std::env::temp_dir().join(format!("ezc-e2e-{}-llvm", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (ir, bitcode) = (dir.join("main.ll"), dir.join("main.bc"));
+	fs::write(&ir, llvm_gen::llvm_gen(&parsed, &tac, &ident_table)).unwrap();
+	let status = Command::new("llvm-as")
+		.arg(&ir)
+		.arg("-o")
+		.arg(&bitcode)
+		.status()
+		.unwrap();
+	let _ = fs::remove_dir_all(&dir);
+	assert!(status.success(), "Invalid LLVM IR");
+}
+
+#[test]
+fn stats() {
+	// Without an input the bundled fibonacci sample is compiled
+	let output = Command::new(env!("CARGO_BIN_EXE_ezc"))
+		.args(["--stats", "--fold", "--inline", "--licm", "-o", "-"])
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	let stats: Vec<(&str, isize)> = stderr
+		.lines()
+		.filter_map(|line| line.rsplit_once(' '))
+		.map(|(name, value)| (name.trim(), value.parse().unwrap()))
+		.collect();
+	let stat = |name: &str| stats.iter().find(|stat| stat.0 == name).unwrap().1;
+	assert_eq!(3, stat("functions"));
+	assert!(stat("tac instructions") > 0);
+	assert!(stat("x86 instructions") > stat("tac instructions"));
+	assert!(stat("max frame bytes") > 0);
+	for pass in tac_gen::pipeline::Pass::ALL {
+		let name = format!("removed by {}", pass.name());
+		assert!(
+			stats.iter().any(|stat| stat.0 == name),
+			"No '{name}' in {stderr}"
+		);
+	}
+	let asm = String::from_utf8_lossy(&output.stdout);
+	assert_eq!(
+		stat("x86 instructions") as usize,
+		x86_gen::instruction_count(&asm)
+	);
+}